@@ -260,6 +260,11 @@ impl<'c> MoneroWalletRpc {
         wallet::Client::localhost(self.rpc_port)
     }
 
+    /// The name of the wallet that is open in this container.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     // It takes a little while for the wallet to sync with monerod.
     pub async fn wait_for_wallet_height(&self, height: u32) -> Result<()> {
         let mut retry: u8 = 0;
@@ -1,6 +1,6 @@
 use crate::rpc::{Request, Response};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use tracing::debug;
@@ -18,6 +18,11 @@ impl Client {
         let url = format!("http://127.0.0.1:{}/json_rpc", port);
         let url = Url::parse(&url).expect("url is well formed");
 
+        Client::new(url)
+    }
+
+    /// New monerod RPC client with `url` as its JSON RPC endpoint.
+    pub fn new(url: Url) -> Self {
         Self {
             inner: reqwest::Client::new(),
             url,
@@ -84,6 +89,33 @@ impl Client {
         Ok(res.result.block_header)
     }
 
+    /// Gets the hex encoded transaction with `tx_hash`. This is not a JSON RPC
+    /// method, it has its own endpoint next to `json_rpc`.
+    pub async fn get_transaction(&self, tx_hash: &str) -> Result<TransactionEntry> {
+        let params = GetTransactionsParams {
+            txs_hashes: vec![tx_hash.to_owned()],
+        };
+        let url = self.url.join("get_transactions")?;
+
+        let response = self
+            .inner
+            .post(url)
+            .json(&params)
+            .send()
+            .await?
+            .text()
+            .await?;
+
+        debug!("get transactions response: {}", response);
+
+        let res: GetTransactions = serde_json::from_str(&response)?;
+
+        match res.txs.into_iter().find(|tx| tx.tx_hash == tx_hash) {
+            Some(tx) => Ok(tx),
+            None => bail!("monerod does not know transaction {}", tx_hash),
+        }
+    }
+
     pub async fn get_block_count(&self) -> Result<u32> {
         let request = Request::new("get_block_count", "");
 
@@ -153,3 +185,23 @@ pub struct BlockHeader {
     pub reward: u64,
     pub timestamp: u32,
 }
+
+#[derive(Clone, Debug, Serialize)]
+struct GetTransactionsParams {
+    txs_hashes: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct GetTransactions {
+    // Omitted if none of the transactions were found.
+    #[serde(default)]
+    txs: Vec<TransactionEntry>,
+    status: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TransactionEntry {
+    pub as_hex: String,
+    pub in_pool: bool,
+    pub tx_hash: String,
+}
@@ -1,7 +1,12 @@
 use crate::rpc::{Request, Response};
-use anyhow::{bail, Result};
-use reqwest::Url;
+use anyhow::{anyhow, bail, Result};
+use digest_auth::AuthContext;
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE},
+    StatusCode, Url,
+};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use tracing::debug;
 
 /// JSON RPC client for monero-wallet-rpc.
@@ -9,6 +14,23 @@ use tracing::debug;
 pub struct Client {
     pub inner: reqwest::Client,
     pub url: Url,
+    pub login: Option<Login>,
+}
+
+/// Credentials of a monero-wallet-rpc started with `--rpc-login`.
+#[derive(Clone, PartialEq)]
+pub struct Login {
+    pub username: String,
+    pub password: String,
+}
+
+impl fmt::Debug for Login {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Login")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
 }
 
 impl Client {
@@ -25,23 +47,75 @@ impl Client {
         Self {
             inner: reqwest::Client::new(),
             url,
+            login: None,
         }
     }
 
-    /// Get addresses for account by index.
-    pub async fn get_address(&self, account_index: u32) -> Result<GetAddress> {
-        let params = GetAddressParams { account_index };
-        let request = Request::new("get_address", params);
+    /// Authenticates requests with `login`.
+    pub fn with_login(self, login: Login) -> Self {
+        Self {
+            login: Some(login),
+            ..self
+        }
+    }
+
+    /// Posts `request` and returns the body of the response.
+    ///
+    /// monero-wallet-rpc uses digest authentication, so if we have a login
+    /// the first attempt is answered with a challenge which we respond to
+    /// with a second request.
+    async fn send<T: Serialize>(&self, request: &T) -> Result<String> {
+        let body = serde_json::to_vec(request)?;
 
         let response = self
             .inner
             .post(self.url.clone())
-            .json(&request)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.clone())
             .send()
-            .await?
-            .text()
             .await?;
 
+        let response = match &self.login {
+            Some(login) if response.status() == StatusCode::UNAUTHORIZED => {
+                let challenge = response
+                    .headers()
+                    .get(WWW_AUTHENTICATE)
+                    .ok_or_else(|| anyhow!("monero-wallet-rpc sent no authentication challenge"))?
+                    .to_str()?;
+                let mut prompt = digest_auth::parse(challenge)?;
+                let context = AuthContext::new_post(
+                    login.username.as_str(),
+                    login.password.as_str(),
+                    self.url.path(),
+                    Some(body.as_slice()),
+                );
+                let authorization = prompt.respond(&context)?.to_header_string();
+
+                self.inner
+                    .post(self.url.clone())
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(AUTHORIZATION, authorization)
+                    .body(body)
+                    .send()
+                    .await?
+            }
+            _ => response,
+        };
+
+        if response.status() == StatusCode::UNAUTHORIZED {
+            bail!("monero-wallet-rpc rejected our login")
+        }
+
+        Ok(response.text().await?)
+    }
+
+    /// Get addresses for account by index.
+    pub async fn get_address(&self, account_index: u32) -> Result<GetAddress> {
+        let params = GetAddressParams { account_index };
+        let request = Request::new("get_address", params);
+
+        let response = self.send(&request).await?;
+
         debug!("get address RPC response: {}", response);
 
         let r: Response<GetAddress> = serde_json::from_str(&response)?;
@@ -50,19 +124,26 @@ impl Client {
 
     /// Gets the balance of account by index.
     pub async fn get_balance(&self, index: u32) -> Result<u64> {
+        let balance = self.balance(index).await?.balance;
+
+        Ok(balance)
+    }
+
+    /// Gets the unlocked balance of account by index, i.e. the amount that
+    /// can be spent right away.
+    pub async fn get_unlocked_balance(&self, index: u32) -> Result<u64> {
+        let balance = self.balance(index).await?.unlocked_balance;
+
+        Ok(balance)
+    }
+
+    async fn balance(&self, index: u32) -> Result<GetBalance> {
         let params = GetBalanceParams {
             account_index: index,
         };
         let request = Request::new("get_balance", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response = self.send(&request).await?;
 
         debug!(
             "get balance of account index {} RPC response: {}",
@@ -71,9 +152,7 @@ impl Client {
 
         let res: Response<GetBalance> = serde_json::from_str(&response)?;
 
-        let balance = res.result.balance;
-
-        Ok(balance)
+        Ok(res.result)
     }
 
     pub async fn create_account(&self, label: &str) -> Result<CreateAccount> {
@@ -82,14 +161,7 @@ impl Client {
         };
         let request = Request::new("create_account", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response = self.send(&request).await?;
 
         debug!("create account RPC response: {}", response);
 
@@ -104,14 +176,7 @@ impl Client {
         };
         let request = Request::new("get_accounts", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response = self.send(&request).await?;
 
         debug!("get accounts RPC response: {}", response);
 
@@ -127,14 +192,7 @@ impl Client {
         };
         let request = Request::new("open_wallet", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response = self.send(&request).await?;
 
         debug!("open wallet RPC response: {}", response);
 
@@ -155,14 +213,7 @@ impl Client {
         };
         let request = Request::new("create_wallet", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response = self.send(&request).await?;
 
         debug!("create wallet RPC response: {}", response);
 
@@ -192,22 +243,28 @@ impl Client {
         &self,
         account_index: u32,
         destinations: Vec<Destination>,
+    ) -> Result<Transfer> {
+        self.transfer_with_unlock_time(account_index, destinations, 0)
+            .await
+    }
+
+    /// Transfers moneroj from `account_index` to `destinations` that cannot
+    /// be spent before the block height or timestamp `unlock_time`.
+    pub async fn transfer_with_unlock_time(
+        &self,
+        account_index: u32,
+        destinations: Vec<Destination>,
+        unlock_time: u64,
     ) -> Result<Transfer> {
         let params = TransferParams {
             account_index,
             destinations,
             get_tx_key: true,
+            unlock_time,
         };
         let request = Request::new("transfer", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response = self.send(&request).await?;
 
         debug!("transfer RPC response: {}", response);
 
@@ -215,18 +272,27 @@ impl Client {
         Ok(r.result)
     }
 
+    /// Sends all unlocked outputs of `account_index` to `address`.
+    pub async fn sweep_all(&self, account_index: u32, address: &str) -> Result<SweepAll> {
+        let params = SweepAllParams {
+            account_index,
+            address: address.to_owned(),
+        };
+        let request = Request::new("sweep_all", params);
+
+        let response = self.send(&request).await?;
+
+        debug!("sweep all RPC response: {}", response);
+
+        let r: Response<SweepAll> = serde_json::from_str(&response)?;
+        Ok(r.result)
+    }
+
     /// Get wallet block height, this might be behind monerod height.
     pub async fn block_height(&self) -> Result<BlockHeight> {
         let request = Request::new("get_height", "");
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response = self.send(&request).await?;
 
         debug!("wallet height RPC response: {}", response);
 
@@ -234,6 +300,36 @@ impl Client {
         Ok(r.result)
     }
 
+    /// Writes the open wallet to disk.
+    pub async fn store(&self) -> Result<()> {
+        let request = Request::new("store", "");
+
+        let response = self.send(&request).await?;
+
+        debug!("store RPC response: {}", response);
+
+        if response.contains("error") {
+            bail!("Failed to store wallet")
+        }
+
+        Ok(())
+    }
+
+    /// Stores the open wallet and stops monero-wallet-rpc.
+    pub async fn stop_wallet(&self) -> Result<()> {
+        let request = Request::new("stop_wallet", "");
+
+        let response = self.send(&request).await?;
+
+        debug!("stop wallet RPC response: {}", response);
+
+        if response.contains("error") {
+            bail!("Failed to stop wallet")
+        }
+
+        Ok(())
+    }
+
     /// Check a transaction in the blockchain with its secret key.
     pub async fn check_tx_key(
         &self,
@@ -248,14 +344,7 @@ impl Client {
         };
         let request = Request::new("check_tx_key", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response = self.send(&request).await?;
 
         debug!("transfer RPC response: {}", response);
 
@@ -263,8 +352,11 @@ impl Client {
         Ok(r.result)
     }
 
+    /// Creates a wallet named `filename` that can spend the outputs of
+    /// `address`.
     pub async fn generate_from_keys(
         &self,
+        filename: &str,
         address: &str,
         spend_key: &str,
         view_key: &str,
@@ -278,7 +370,7 @@ impl Client {
 
         let params = GenerateFromKeysParams {
             restore_height,
-            filename: view_key.into(),
+            filename: filename.into(),
             address: address.into(),
             spendkey: spend_key.into(),
             viewkey: view_key.into(),
@@ -287,14 +379,36 @@ impl Client {
         };
         let request = Request::new("generate_from_keys", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response = self.send(&request).await?;
+
+        debug!("generate_from_keys RPC response: {}", response);
+
+        let r: Response<GenerateFromKeys> = serde_json::from_str(&response)?;
+        Ok(r.result)
+    }
+
+    /// Creates a view-only wallet named `filename` that can see, but not
+    /// spend, the outputs of `address`.
+    pub async fn generate_view_only_from_keys(
+        &self,
+        filename: &str,
+        address: &str,
+        view_key: &str,
+        restore_height: u32,
+    ) -> Result<GenerateFromKeys> {
+        let params = GenerateFromKeysParams {
+            restore_height,
+            filename: filename.into(),
+            address: address.into(),
+            // monero-wallet-rpc creates a view-only wallet if no spend key is given
+            spendkey: "".into(),
+            viewkey: view_key.into(),
+            password: "".into(),
+            autosave_current: true,
+        };
+        let request = Request::new("generate_from_keys", params);
+
+        let response = self.send(&request).await?;
 
         debug!("generate_from_keys RPC response: {}", response);
 
@@ -302,17 +416,48 @@ impl Client {
         Ok(r.result)
     }
 
+    /// Get the incoming transfers of the open wallet, including those still in
+    /// the transaction pool.
+    pub async fn get_incoming_transfers(&self) -> Result<GetTransfers> {
+        let params = GetTransfersParams {
+            incoming: true,
+            pool: true,
+            outgoing: false,
+            pending: false,
+        };
+        let request = Request::new("get_transfers", params);
+
+        let response = self.send(&request).await?;
+
+        debug!("get transfers RPC response: {}", response);
+
+        let r: Response<GetTransfers> = serde_json::from_str(&response)?;
+        Ok(r.result)
+    }
+
+    /// Get the outgoing transfers of the open wallet, including those still in
+    /// the transaction pool.
+    pub async fn get_outgoing_transfers(&self) -> Result<GetTransfers> {
+        let params = GetTransfersParams {
+            incoming: false,
+            pool: false,
+            outgoing: true,
+            pending: true,
+        };
+        let request = Request::new("get_transfers", params);
+
+        let response = self.send(&request).await?;
+
+        debug!("get transfers RPC response: {}", response);
+
+        let r: Response<GetTransfers> = serde_json::from_str(&response)?;
+        Ok(r.result)
+    }
+
     pub async fn refresh(&self) -> Result<Refreshed> {
         let request = Request::new("refresh", "");
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response = self.send(&request).await?;
 
         debug!("refresh RPC response: {}", response);
 
@@ -397,12 +542,14 @@ struct TransferParams {
     destinations: Vec<Destination>,
     // Return the transaction key after sending.
     get_tx_key: bool,
+    // The outputs are locked until this block height or timestamp, 0 for none.
+    unlock_time: u64,
 }
 
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Destination {
-    amount: u64,
-    address: String,
+    pub amount: u64,
+    pub address: String,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -417,6 +564,19 @@ pub struct Transfer {
     pub unsigned_txset: String,
 }
 
+#[derive(Serialize, Debug, Clone)]
+struct SweepAllParams {
+    account_index: u32,
+    address: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SweepAll {
+    pub tx_hash_list: Vec<String>,
+    pub fee_list: Vec<u64>,
+    pub amount_list: Vec<u64>,
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
 pub struct BlockHeight {
     pub height: u32,
@@ -459,3 +619,42 @@ pub struct Refreshed {
     pub blocks_fetched: u32,
     pub received_money: bool,
 }
+
+#[derive(Serialize, Debug, Clone)]
+struct GetTransfersParams {
+    #[serde(rename = "in")]
+    incoming: bool,
+    pool: bool,
+    #[serde(rename = "out")]
+    outgoing: bool,
+    pending: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GetTransfers {
+    // All lists are omitted if they are empty.
+    #[serde(default, rename = "in")]
+    pub incoming: Vec<TransferEntry>,
+    #[serde(default)]
+    pub pool: Vec<TransferEntry>,
+    #[serde(default, rename = "out")]
+    pub outgoing: Vec<TransferEntry>,
+    #[serde(default)]
+    pub pending: Vec<TransferEntry>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TransferEntry {
+    pub txid: String,
+    pub address: String,
+    pub amount: u64,
+    #[serde(default)]
+    pub fee: u64,
+    #[serde(default)]
+    pub confirmations: u32,
+    pub height: u32,
+    pub unlock_time: u64,
+    // Only known for transfers sent by this wallet.
+    #[serde(default)]
+    pub destinations: Vec<Destination>,
+}
@@ -12,38 +12,39 @@
 #![forbid(unsafe_code)]
 #![allow(non_snake_case)]
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use log::LevelFilter;
 use prettytable::{row, Table};
-use std::{path::Path, sync::Arc};
+use std::{path::Path, sync::Arc, time::Duration};
 use structopt::StructOpt;
 use swap::{
-    bitcoin,
+    bitcoin, database,
     database::Database,
-    execution_params,
-    execution_params::GetExecutionParams,
     fs::default_config_path,
     monero,
-    monero::{CreateWallet, OpenWallet},
     nectar::{
         command::{Arguments, Command},
         config::{
             initial_setup, query_user_for_initial_testnet_config, read_config, Config,
-            ConfigNotInitialized,
+            ConfigNotInitialized, PriceSource,
         },
     },
-    protocol::alice::EventLoop,
+    price,
+    price::{FixedRate, PriceProvider, Rate, Spread},
+    protocol::{
+        alice,
+        alice::{cancel::CancelError, AliceState, EventLoop},
+    },
     seed::Seed,
     trace::init_tracing,
 };
-use tracing::{info, warn};
+use tracing::{error, info, warn};
+use uuid::Uuid;
 
 #[macro_use]
 extern crate prettytable;
 
 const MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME: &str = "swap-tool-blockchain-monitoring-wallet";
-const BITCOIN_NETWORK: bitcoin::Network = bitcoin::Network::Testnet;
-const MONERO_NETWORK: monero::Network = monero::Network::Stagenet;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -65,6 +66,8 @@ async fn main() -> Result<()> {
         }
     };
 
+    info!("Swapping on {}", config.chain);
+
     info!(
         "Database and Seed will be stored in directory: {}",
         config.data.dir.display()
@@ -76,18 +79,20 @@ async fn main() -> Result<()> {
         .context("Could not open database")?;
 
     let wallet_data_dir = config.data.dir.join("wallet");
+    let monero_wallet_data_dir = config.data.dir.join("monero");
 
     match opt.cmd {
         Command::Start => {
             let seed = Seed::from_file_or_generate(&config.data.dir)
                 .expect("Could not retrieve/initialize seed");
 
-            let execution_params = execution_params::Testnet::get_execution_params();
+            let execution_params = config.chain.execution_params();
 
-            let (bitcoin_wallet, monero_wallet) = init_wallets(
+            let (bitcoin_wallet, monero_wallet, monero_wallet_rpc) = init_wallets(
                 config.clone(),
                 &wallet_data_dir,
-                seed.extended_private_key(BITCOIN_NETWORK)?.private_key,
+                &monero_wallet_data_dir,
+                seed.extended_private_key(config.chain.bitcoin_network())?,
             )
             .await?;
 
@@ -96,19 +101,44 @@ async fn main() -> Result<()> {
                 bitcoin_wallet.new_address().await?
             );
 
+            let price_provider = init_price_provider(config.maker.price);
+            let spread = Spread::new(config.maker.spread)?;
+            let monero_wallet = Arc::new(monero_wallet);
+
             let (mut event_loop, _) = EventLoop::new(
                 config.network.listen,
                 seed,
                 execution_params,
                 Arc::new(bitcoin_wallet),
-                Arc::new(monero_wallet),
+                monero_wallet.clone(),
                 Arc::new(db),
+                price_provider,
+                spread,
+                config.maker.min_buy_btc,
+                config.maker.max_buy_btc,
             )
             .unwrap();
 
             info!("Our peer id is {}", event_loop.peer_id());
 
-            event_loop.run().await;
+            event_loop
+                .resume_unfinished_swaps()
+                .await
+                .context("Failed to resume unfinished swaps")?;
+
+            // Running swaps are resumed from the database on the next start
+            tokio::select! {
+                _ = event_loop.run() => {}
+                signal = shutdown_signal() => {
+                    signal?;
+                    info!("Shutting down");
+                }
+            }
+
+            if let Err(e) = monero_wallet.store().await {
+                warn!("Failed to store Monero wallet: {:#}", e);
+            }
+            shutdown_monero_wallet_rpc(monero_wallet_rpc).await;
         }
         Command::History => {
             let mut table = Table::new();
@@ -122,24 +152,199 @@ async fn main() -> Result<()> {
             // Print the table to stdout
             table.printstd();
         }
+        Command::Resume { swap_id } => {
+            let seed = Seed::from_file_or_generate(&config.data.dir)
+                .expect("Could not retrieve/initialize seed");
+
+            let execution_params = config.chain.execution_params();
+
+            let (bitcoin_wallet, monero_wallet, monero_wallet_rpc) = init_wallets(
+                config.clone(),
+                &wallet_data_dir,
+                &monero_wallet_data_dir,
+                seed.extended_private_key(config.chain.bitcoin_network())?,
+            )
+            .await?;
+            let bitcoin_wallet = Arc::new(bitcoin_wallet);
+            let monero_wallet = Arc::new(monero_wallet);
+            let db = Arc::new(db);
+
+            let price_provider = init_price_provider(config.maker.price);
+            let spread = Spread::new(config.maker.spread)?;
+
+            let (mut event_loop, _) = EventLoop::new(
+                config.network.listen.clone(),
+                seed,
+                execution_params,
+                bitcoin_wallet.clone(),
+                monero_wallet.clone(),
+                db.clone(),
+                price_provider,
+                spread,
+                config.maker.min_buy_btc,
+                config.maker.max_buy_btc,
+            )?;
+
+            let swap = alice::Builder::new(
+                event_loop.peer_id(),
+                execution_params,
+                swap_id,
+                bitcoin_wallet,
+                monero_wallet,
+                db,
+                config.network.listen,
+                event_loop.new_handle(swap_id),
+            )
+            .build()
+            .await?;
+
+            tokio::spawn(async move { event_loop.run().await });
+
+            let result = alice::run(swap).await;
+            shutdown_monero_wallet_rpc(monero_wallet_rpc).await;
+            let state = result?;
+            info!("Swap {} finished in state: {}", swap_id, state);
+        }
+        Command::Cancel { swap_id, force } => {
+            let seed = Seed::from_file_or_generate(&config.data.dir)
+                .expect("Could not retrieve/initialize seed");
+
+            let (bitcoin_wallet, monero_wallet, monero_wallet_rpc) = init_wallets(
+                config.clone(),
+                &wallet_data_dir,
+                &monero_wallet_data_dir,
+                seed.extended_private_key(config.chain.bitcoin_network())?,
+            )
+            .await?;
+            let state = load_alice_state(&db, swap_id)?;
+
+            let result = alice::cancel(
+                swap_id,
+                state,
+                Arc::new(bitcoin_wallet),
+                Arc::new(monero_wallet),
+                Arc::new(db),
+                force,
+            )
+            .await;
+            shutdown_monero_wallet_rpc(monero_wallet_rpc).await;
+
+            match result? {
+                Ok((txid, _)) => {
+                    info!("Cancel transaction successfully published with id {}", txid)
+                }
+                Err(CancelError::CancelTimelockNotExpiredYet) => error!(
+                    "The Cancel Transaction cannot be published yet, \
+                    because the timelock has not expired. Please try again later."
+                ),
+                Err(CancelError::CancelTxAlreadyPublished) => {
+                    warn!("The Cancel Transaction has already been published.")
+                }
+            }
+        }
+        Command::Punish { swap_id, force } => {
+            let seed = Seed::from_file_or_generate(&config.data.dir)
+                .expect("Could not retrieve/initialize seed");
+
+            let execution_params = config.chain.execution_params();
+
+            let bitcoin_wallet = init_bitcoin_wallet(
+                &config,
+                &wallet_data_dir,
+                seed.extended_private_key(config.chain.bitcoin_network())?,
+            )
+            .await?;
+            let state = load_alice_state(&db, swap_id)?;
+
+            let (txid, _) = alice::punish(
+                swap_id,
+                state,
+                execution_params,
+                Arc::new(bitcoin_wallet),
+                Arc::new(db),
+                force,
+            )
+            .await??;
+
+            info!("Punish transaction successfully published with id {}", txid);
+        }
+        Command::Redeem { swap_id, force } => {
+            let seed = Seed::from_file_or_generate(&config.data.dir)
+                .expect("Could not retrieve/initialize seed");
+
+            let execution_params = config.chain.execution_params();
+
+            let bitcoin_wallet = init_bitcoin_wallet(
+                &config,
+                &wallet_data_dir,
+                seed.extended_private_key(config.chain.bitcoin_network())?,
+            )
+            .await?;
+            let state = load_alice_state(&db, swap_id)?;
+
+            let (txid, _) = alice::redeem(
+                swap_id,
+                state,
+                execution_params,
+                Arc::new(bitcoin_wallet),
+                Arc::new(db),
+                force,
+            )
+            .await??;
+
+            info!("Redeem transaction successfully published with id {}", txid);
+        }
+        Command::SweepLegacyWallet { dry_run } => {
+            let seed = Seed::from_file_or_generate(&config.data.dir)
+                .expect("Could not retrieve/initialize seed");
+
+            let bitcoin_wallet = init_bitcoin_wallet(
+                &config,
+                &wallet_data_dir,
+                seed.extended_private_key(config.chain.bitcoin_network())?,
+            )
+            .await?;
+
+            bitcoin_wallet.sweep_legacy_wallet(dry_run).await?;
+        }
     };
 
     Ok(())
 }
 
-async fn init_wallets(
-    config: Config,
+fn load_alice_state(db: &Database, swap_id: Uuid) -> Result<AliceState> {
+    match db.get_state(swap_id)? {
+        database::Swap::Alice(state) => Ok(state.into()),
+        database::Swap::Bob(_) => bail!("Swap {} was not executed in the role of Alice", swap_id),
+    }
+}
+
+async fn init_bitcoin_wallet(
+    config: &Config,
     bitcoin_wallet_data_dir: &Path,
-    private_key: ::bitcoin::PrivateKey,
-) -> Result<(bitcoin::Wallet, monero::Wallet)> {
-    let bitcoin_wallet = bitcoin::Wallet::new(
-        config.bitcoin.electrum_rpc_url,
-        config.bitcoin.electrum_http_url,
-        BITCOIN_NETWORK,
-        bitcoin_wallet_data_dir,
-        private_key,
-    )
-    .await?;
+    master_key: ::bitcoin::util::bip32::ExtendedPrivKey,
+) -> Result<bitcoin::Wallet> {
+    let bitcoin_wallet = match &config.bitcoin.bitcoind {
+        Some(bitcoind) => {
+            bitcoin::Wallet::new_bitcoind(
+                bitcoind.rpc_url.clone(),
+                &bitcoind.wallet,
+                config.chain.bitcoin_network(),
+                config.bitcoin.fee_estimation,
+            )
+            .await?
+        }
+        None => {
+            bitcoin::Wallet::new(
+                config.bitcoin.electrum_rpc_url.clone(),
+                config.chain.bitcoin_network(),
+                bitcoin_wallet_data_dir,
+                master_key,
+                config.bitcoin.fee_estimation,
+            )
+            .await?
+        }
+    };
 
     bitcoin_wallet
         .sync_wallet()
@@ -152,35 +357,103 @@ async fn init_wallets(
         bitcoin_balance
     );
 
-    let monero_wallet = monero::Wallet::new(config.monero.wallet_rpc_url.clone(), MONERO_NETWORK);
+    Ok(bitcoin_wallet)
+}
+
+async fn init_wallets(
+    config: Config,
+    bitcoin_wallet_data_dir: &Path,
+    monero_wallet_data_dir: &Path,
+    master_key: ::bitcoin::util::bip32::ExtendedPrivKey,
+) -> Result<(bitcoin::Wallet, monero::Wallet, Option<monero::WalletRpc>)> {
+    let bitcoin_wallet = init_bitcoin_wallet(&config, bitcoin_wallet_data_dir, master_key).await?;
+
+    let monero_wallet_rpc = match &config.monero.wallet_rpc {
+        Some(wallet_rpc) => Some(
+            monero::WalletRpc::start(
+                wallet_rpc.binary.clone(),
+                wallet_rpc.daemon_address.clone(),
+                monero_wallet_data_dir,
+                config.chain.monero_network(),
+                MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME,
+            )
+            .await?,
+        ),
+        None => None,
+    };
+    let monero_wallet_rpc_url = match &monero_wallet_rpc {
+        Some(wallet_rpc) => wallet_rpc.url(),
+        None => config.monero.wallet_rpc_url.clone(),
+    };
+
+    let mut monero_wallet = monero::Wallet::new(
+        monero_wallet_rpc_url.clone(),
+        config.chain.monero_network(),
+        MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME,
+    );
+    if let Some(wallet_rpc) = &monero_wallet_rpc {
+        monero_wallet = monero_wallet.with_login(wallet_rpc.login());
+    }
 
     // Setup the temporary Monero wallet necessary for monitoring the blockchain
-    let open_monitoring_wallet_response = monero_wallet
-        .open_wallet(MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME)
-        .await;
-    if open_monitoring_wallet_response.is_err() {
-        monero_wallet
-            .create_wallet(MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME)
-            .await
-            .context(format!(
-                "Unable to create Monero wallet for blockchain monitoring.\
+    monero_wallet
+        .open_or_create_main_wallet()
+        .await
+        .context(format!(
+            "Unable to open Monero wallet for blockchain monitoring.\
              Please ensure that the monero-wallet-rpc is available at {}",
-                config.monero.wallet_rpc_url
-            ))?;
+            monero_wallet_rpc_url
+        ))?;
 
-        info!(
-            "Created Monero wallet for blockchain monitoring with name {}",
-            MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME
-        );
-    } else {
-        info!(
-            "Opened Monero wallet for blockchain monitoring with name {}",
-            MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME
-        );
+    let _test_wallet_connection = monero_wallet.block_height().await?;
+    monero_wallet.ensure_network().await?;
+    info!("The Monero wallet RPC is set up correctly!");
+
+    Ok((bitcoin_wallet, monero_wallet, monero_wallet_rpc))
+}
+
+async fn shutdown_monero_wallet_rpc(monero_wallet_rpc: Option<monero::WalletRpc>) {
+    if let Some(monero_wallet_rpc) = monero_wallet_rpc {
+        if let Err(e) = monero_wallet_rpc.shutdown().await {
+            warn!("Failed to shut down monero-wallet-rpc: {:#}", e);
+        }
     }
+}
 
-    let _test_wallet_connection = monero_wallet.inner.block_height().await?;
-    info!("The Monero wallet RPC is set up correctly!");
+/// Resolves once we are asked to stop with Ctrl+C or, on unix, SIGTERM.
+async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
 
-    Ok((bitcoin_wallet, monero_wallet))
+    Ok(())
+}
+
+fn init_price_provider(source: PriceSource) -> Box<dyn PriceProvider> {
+    match source {
+        PriceSource::Static { ask } => {
+            info!("Quoting against static ask of {}", ask);
+            Box::new(FixedRate::new(Rate::new(ask)))
+        }
+        PriceSource::File { path, max_age_secs } => {
+            info!("Quoting against ask read from {}", path.display());
+            Box::new(price::file::poll(path, Duration::from_secs(max_age_secs)))
+        }
+        PriceSource::Ticker { url, max_age_secs } => {
+            info!("Quoting against ask of price ticker at {}", url);
+            Box::new(price::ticker::connect(
+                url,
+                Duration::from_secs(max_age_secs),
+            ))
+        }
+    }
 }
@@ -12,7 +12,8 @@
 #![forbid(unsafe_code)]
 #![allow(non_snake_case)]
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use libp2p::{core::Multiaddr, PeerId};
 use log::LevelFilter;
 use prettytable::{row, Table};
 use std::{path::Path, sync::Arc};
@@ -27,19 +28,17 @@ use swap::{
         },
     },
     database::Database,
-    execution_params,
-    execution_params::GetExecutionParams,
     fs::default_config_path,
     monero,
-    monero::{CreateWallet, OpenWallet},
     protocol::{
         bob,
-        bob::{cancel::CancelError, Builder},
+        bob::{cancel::CancelError, Builder, Recovery},
     },
     seed::Seed,
     trace::init_tracing,
 };
 use tracing::{error, info, warn};
+use url::Url;
 use uuid::Uuid;
 
 #[macro_use]
@@ -67,6 +66,8 @@ async fn main() -> Result<()> {
         }
     };
 
+    info!("Swapping on {}", config.chain);
+
     info!(
         "Database and Seed will be stored in directory: {}",
         config.data.dir.display()
@@ -76,40 +77,68 @@ async fn main() -> Result<()> {
         .context("Could not open database")?;
 
     let wallet_data_dir = config.data.dir.join("wallet");
+    let monero_wallet_data_dir = config.data.dir.join("monero");
     let seed =
         Seed::from_file_or_generate(&config.data.dir).expect("Could not retrieve/initialize seed");
 
-    // hardcode to testnet/stagenet
-    let bitcoin_network = bitcoin::Network::Testnet;
-    let monero_network = monero::Network::Stagenet;
-    let execution_params = execution_params::Testnet::get_execution_params();
+    let bitcoin_network = config.chain.bitcoin_network();
+    let monero_network = config.chain.monero_network();
+    let execution_params = config.chain.execution_params();
 
     match opt.cmd {
         Command::BuyXmr {
             alice_peer_id,
             alice_addr,
             send_bitcoin,
+            utxos,
+            confirmed_only,
+            single_input,
+            receive_address,
         } => {
-            let (bitcoin_wallet, monero_wallet) = init_wallets(
+            if let Some(receive_address) = receive_address {
+                if receive_address.network != monero_network {
+                    bail!(
+                        "The receive address {} is not on Monero {:?}",
+                        receive_address,
+                        monero_network
+                    )
+                }
+            }
+
+            let psbt_dir = config.bitcoin.psbt_dir.clone();
+            let (bitcoin_wallet, monero_wallet, monero_wallet_rpc) = init_wallets(
                 config,
                 bitcoin_network,
                 &wallet_data_dir,
                 monero_network,
+                &monero_wallet_data_dir,
                 seed,
             )
             .await?;
 
             let swap_id = Uuid::new_v4();
+            db.insert_peer(swap_id, alice_peer_id, alice_addr.clone())
+                .await?;
+            if let Some(receive_address) = receive_address {
+                db.insert_monero_address(swap_id, receive_address).await?;
+                info!("The redeemed XMR will be sent to {}", receive_address);
+            }
 
             info!(
                 "Swap buy XMR with {} started with ID {}",
                 send_bitcoin, swap_id
             );
 
-            info!(
-                "BTC deposit address: {}",
-                bitcoin_wallet.new_address().await?
-            );
+            match psbt_dir {
+                Some(psbt_dir) => info!(
+                    "The BTC lock transaction will be exported to {} for signing",
+                    psbt_dir.display()
+                ),
+                None => info!(
+                    "BTC deposit address: {}",
+                    bitcoin_wallet.new_address().await?
+                ),
+            }
 
             let bob_factory = Builder::new(
                 seed,
@@ -121,10 +150,20 @@ async fn main() -> Result<()> {
                 alice_peer_id,
                 execution_params,
             );
-            let (swap, event_loop) = bob_factory.with_init_params(send_bitcoin).build().await?;
+            let (swap, event_loop) = bob_factory
+                .with_init_params(send_bitcoin)
+                .with_coin_control(bitcoin::CoinControl {
+                    outpoints: utxos,
+                    confirmed_only,
+                    single_input,
+                })
+                .build()
+                .await?;
 
             tokio::spawn(async move { event_loop.run().await });
-            bob::run(swap).await?;
+            let result = bob::run(swap).await;
+            shutdown_monero_wallet_rpc(monero_wallet_rpc).await;
+            result?;
         }
         Command::History => {
             let mut table = Table::new();
@@ -143,11 +182,15 @@ async fn main() -> Result<()> {
             alice_peer_id,
             alice_addr,
         }) => {
-            let (bitcoin_wallet, monero_wallet) = init_wallets(
+            let (alice_peer_id, alice_addr) =
+                load_alice_peer(&db, swap_id, alice_peer_id, alice_addr).await?;
+
+            let (bitcoin_wallet, monero_wallet, monero_wallet_rpc) = init_wallets(
                 config,
                 bitcoin_network,
                 &wallet_data_dir,
                 monero_network,
+                &monero_wallet_data_dir,
                 seed,
             )
             .await?;
@@ -165,43 +208,21 @@ async fn main() -> Result<()> {
             let (swap, event_loop) = bob_factory.build().await?;
 
             tokio::spawn(async move { event_loop.run().await });
-            bob::run(swap).await?;
+            let result = bob::run(swap).await;
+            shutdown_monero_wallet_rpc(monero_wallet_rpc).await;
+            result?;
         }
-        Command::Cancel(Cancel::BuyXmr {
-            swap_id,
-            alice_peer_id,
-            alice_addr,
-            force,
-        }) => {
-            // TODO: Optimization: Only init the Bitcoin wallet, Monero wallet unnecessary
-            let (bitcoin_wallet, monero_wallet) = init_wallets(
-                config,
-                bitcoin_network,
-                &wallet_data_dir,
-                monero_network,
-                seed,
-            )
-            .await?;
-
-            let bob_factory = Builder::new(
-                seed,
-                db,
-                swap_id,
-                Arc::new(bitcoin_wallet),
-                Arc::new(monero_wallet),
-                alice_addr,
-                alice_peer_id,
-                execution_params,
-            );
-            let (swap, event_loop) = bob_factory.build().await?;
+        Command::Cancel(Cancel::BuyXmr { swap_id, force }) => {
+            let bitcoin_wallet =
+                init_bitcoin_wallet(&config, bitcoin_network, &wallet_data_dir, seed).await?;
 
-            tokio::spawn(async move { event_loop.run().await });
+            let recovery = Recovery::load(db, swap_id, Arc::new(bitcoin_wallet), execution_params)?;
 
             match bob::cancel(
-                swap.swap_id,
-                swap.state,
-                swap.bitcoin_wallet,
-                swap.db,
+                recovery.swap_id,
+                recovery.state,
+                recovery.bitcoin_wallet,
+                recovery.db,
                 force,
             )
             .await?
@@ -218,65 +239,101 @@ async fn main() -> Result<()> {
                 }
             }
         }
-        Command::Refund(Refund::BuyXmr {
-            swap_id,
-            alice_peer_id,
-            alice_addr,
-            force,
-        }) => {
-            let (bitcoin_wallet, monero_wallet) = init_wallets(
-                config,
-                bitcoin_network,
-                &wallet_data_dir,
-                monero_network,
-                seed,
-            )
-            .await?;
+        Command::Refund(Refund::BuyXmr { swap_id, force }) => {
+            let bitcoin_wallet =
+                init_bitcoin_wallet(&config, bitcoin_network, &wallet_data_dir, seed).await?;
 
-            // TODO: Optimize to only use the Bitcoin wallet, Monero wallet is unnecessary
-            let bob_factory = Builder::new(
-                seed,
-                db,
-                swap_id,
-                Arc::new(bitcoin_wallet),
-                Arc::new(monero_wallet),
-                alice_addr,
-                alice_peer_id,
-                execution_params,
-            );
-            let (swap, event_loop) = bob_factory.build().await?;
+            let recovery = Recovery::load(db, swap_id, Arc::new(bitcoin_wallet), execution_params)?;
 
-            tokio::spawn(async move { event_loop.run().await });
             bob::refund(
-                swap.swap_id,
-                swap.state,
-                swap.execution_params,
-                swap.bitcoin_wallet,
-                swap.db,
+                recovery.swap_id,
+                recovery.state,
+                recovery.execution_params,
+                recovery.bitcoin_wallet,
+                recovery.db,
                 force,
             )
             .await??;
         }
+        Command::SweepLegacyWallet { dry_run } => {
+            let bitcoin_wallet =
+                init_bitcoin_wallet(&config, bitcoin_network, &wallet_data_dir, seed).await?;
+
+            bitcoin_wallet.sweep_legacy_wallet(dry_run).await?;
+        }
     };
 
     Ok(())
 }
 
-async fn init_wallets(
-    config: Config,
+/// Returns the peer id and address of Alice stored for the swap, unless the
+/// user provided overrides for them.
+async fn load_alice_peer(
+    db: &Database,
+    swap_id: Uuid,
+    alice_peer_id: Option<PeerId>,
+    alice_addr: Option<Multiaddr>,
+) -> Result<(PeerId, Multiaddr)> {
+    match (db.get_peer(swap_id), alice_peer_id, alice_addr) {
+        (Ok((stored_peer_id, stored_addr)), alice_peer_id, alice_addr) => Ok((
+            alice_peer_id.unwrap_or(stored_peer_id),
+            alice_addr.unwrap_or(stored_addr),
+        )),
+        // Swaps started before the peer was stored in the database, remember the
+        // provided peer so it does not have to be given again.
+        (Err(_), Some(alice_peer_id), Some(alice_addr)) => {
+            db.insert_peer(swap_id, alice_peer_id, alice_addr.clone())
+                .await?;
+            Ok((alice_peer_id, alice_addr))
+        }
+        (Err(e), ..) => Err(e).context(
+            "Unable to determine the counterparty of the swap, \
+            please provide --counterpart-peer-id and --counterpart-addr",
+        ),
+    }
+}
+
+async fn init_bitcoin_wallet(
+    config: &Config,
     bitcoin_network: bitcoin::Network,
     bitcoin_wallet_data_dir: &Path,
-    monero_network: monero::Network,
     seed: Seed,
-) -> Result<(bitcoin::Wallet, monero::Wallet)> {
-    let bitcoin_wallet = bitcoin::Wallet::new(
-        config.bitcoin.electrum_rpc_url,
-        config.bitcoin.electrum_http_url,
-        bitcoin_network,
-        bitcoin_wallet_data_dir,
-        seed.extended_private_key(bitcoin_network)?.private_key,
-    )
-    .await?;
+) -> Result<bitcoin::Wallet> {
+    if config.bitcoin.psbt_dir.is_some() && config.bitcoin.bitcoind.is_none() {
+        bail!(
+            "Signing the lock transaction with an external wallet requires a Bitcoin Core wallet \
+            to fund it, please configure [bitcoin.bitcoind]"
+        )
+    }
+
+    let bitcoin_wallet = match &config.bitcoin.bitcoind {
+        Some(bitcoind) => {
+            bitcoin::Wallet::new_bitcoind(
+                bitcoind.rpc_url.clone(),
+                &bitcoind.wallet,
+                bitcoin_network,
+                config.bitcoin.fee_estimation,
+            )
+            .await?
+        }
+        None => {
+            bitcoin::Wallet::new(
+                config.bitcoin.electrum_rpc_url.clone(),
+                bitcoin_network,
+                bitcoin_wallet_data_dir,
+                seed.extended_private_key(bitcoin_network)?,
+                config.bitcoin.fee_estimation,
+            )
+            .await?
+        }
+    };
+
+    let bitcoin_wallet = match &config.bitcoin.psbt_dir {
+        Some(psbt_dir) => {
+            bitcoin_wallet.with_external_signer(bitcoin::ExternalSigner::new(psbt_dir.clone()))
+        }
+        None => bitcoin_wallet,
+    };
 
     bitcoin_wallet
         .sync_wallet()
@@ -289,35 +346,86 @@ async fn init_wallets(
         bitcoin_balance
     );
 
-    let monero_wallet = monero::Wallet::new(config.monero.wallet_rpc_url.clone(), monero_network);
+    Ok(bitcoin_wallet)
+}
 
-    // Setup the temporary Monero wallet necessary for monitoring the blockchain
-    let open_monitoring_wallet_response = monero_wallet
-        .open_wallet(MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME)
-        .await;
-    if open_monitoring_wallet_response.is_err() {
-        monero_wallet
-            .create_wallet(MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME)
-            .await
-            .context(format!(
-                "Unable to create Monero wallet for blockchain monitoring.\
-             Please ensure that the monero-wallet-rpc is available at {}",
-                config.monero.wallet_rpc_url
-            ))?;
+async fn init_wallets(
+    config: Config,
+    bitcoin_network: bitcoin::Network,
+    bitcoin_wallet_data_dir: &Path,
+    monero_network: monero::Network,
+    monero_wallet_data_dir: &Path,
+    seed: Seed,
+) -> Result<(bitcoin::Wallet, monero::Wallet, Option<monero::WalletRpc>)> {
+    let bitcoin_wallet =
+        init_bitcoin_wallet(&config, bitcoin_network, bitcoin_wallet_data_dir, seed).await?;
+
+    let monero_wallet_rpc = match &config.monero.wallet_rpc {
+        Some(wallet_rpc) => Some(
+            monero::WalletRpc::start(
+                wallet_rpc.binary.clone(),
+                wallet_rpc.daemon_address.clone(),
+                monero_wallet_data_dir,
+                monero_network,
+                MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME,
+            )
+            .await?,
+        ),
+        None => None,
+    };
+    let monero_wallet_rpc_url = match &monero_wallet_rpc {
+        Some(wallet_rpc) => wallet_rpc.url(),
+        None => config.monero.wallet_rpc_url.clone(),
+    };
 
-        info!(
-            "Created Monero wallet for blockchain monitoring with name {}",
-            MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME
-        );
-    } else {
-        info!(
-            "Opened Monero wallet for blockchain monitoring with name {}",
-            MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME
-        );
+    let monero_daemon_url = match (&config.monero.daemon_url, &config.monero.wallet_rpc) {
+        (Some(daemon_url), _) => Some(daemon_url.clone()),
+        (None, Some(wallet_rpc)) => Some(
+            Url::parse(&format!("http://{}/json_rpc", wallet_rpc.daemon_address))
+                .context("Invalid monerod address")?,
+        ),
+        (None, None) => None,
+    };
+
+    let mut monero_wallet = monero::Wallet::new(
+        monero_wallet_rpc_url.clone(),
+        monero_network,
+        MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME,
+    );
+    match monero_daemon_url {
+        Some(monero_daemon_url) => monero_wallet = monero_wallet.with_daemon(monero_daemon_url),
+        // Without monerod only the transfer proof of Alice's Monero lock
+        // transaction is checked
+        None => warn!(
+            "No monerod configured, the Monero lock transaction of Alice will NOT be fully \
+             checked. Please set `daemon_url` in the monero section of the config"
+        ),
+    }
+    if let Some(wallet_rpc) = &monero_wallet_rpc {
+        monero_wallet = monero_wallet.with_login(wallet_rpc.login());
     }
 
-    let _test_wallet_connection = monero_wallet.inner.block_height().await?;
+    // Setup the temporary Monero wallet necessary for monitoring the blockchain
+    monero_wallet
+        .open_or_create_main_wallet()
+        .await
+        .context(format!(
+            "Unable to open Monero wallet for blockchain monitoring.\
+             Please ensure that the monero-wallet-rpc is available at {}",
+            monero_wallet_rpc_url
+        ))?;
+
+    let _test_wallet_connection = monero_wallet.block_height().await?;
+    monero_wallet.ensure_network().await?;
     info!("The Monero wallet RPC is set up correctly!");
 
-    Ok((bitcoin_wallet, monero_wallet))
+    Ok((bitcoin_wallet, monero_wallet, monero_wallet_rpc))
+}
+
+async fn shutdown_monero_wallet_rpc(monero_wallet_rpc: Option<monero::WalletRpc>) {
+    if let Some(monero_wallet_rpc) = monero_wallet_rpc {
+        if let Err(e) = monero_wallet_rpc.shutdown().await {
+            warn!("Failed to shut down monero-wallet-rpc: {:#}", e);
+        }
+    }
 }
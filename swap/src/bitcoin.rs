@@ -1,7 +1,13 @@
 pub mod wallet;
 
+mod bitcoind;
 mod cancel;
+mod coin_control;
+mod cpfp;
+mod electrum;
+mod fee;
 mod lock;
+mod psbt;
 mod punish;
 mod redeem;
 mod refund;
@@ -9,7 +15,12 @@ mod timelocks;
 
 pub use crate::bitcoin::{
     cancel::{CancelTimelock, PunishTimelock, TxCancel},
+    coin_control::CoinControl,
+    cpfp::{CpfpPackage, Deadline},
+    electrum::LegacySweep,
+    fee::{ensure_lock_amount_covers_fees, FeeRate, FeeRateBounds},
     lock::TxLock,
+    psbt::ExternalSigner,
     punish::TxPunish,
     redeem::TxRedeem,
     refund::TxRefund,
@@ -17,14 +28,14 @@ pub use crate::bitcoin::{
 };
 pub use ::bitcoin::{util::amount::Amount, Address, Network, Transaction, Txid};
 pub use ecdsa_fun::{adaptor::EncryptedSignature, fun::Scalar, Signature};
-pub use wallet::Wallet;
+pub use wallet::{FeeEstimation, Wallet};
 
 use crate::execution_params::ExecutionParams;
 use ::bitcoin::{
     hashes::{hex::ToHex, Hash},
     secp256k1,
     util::psbt::PartiallySignedTransaction,
-    SigHash,
+    Script, SigHash,
 };
 use anyhow::{anyhow, bail, Result};
 use async_trait::async_trait;
@@ -33,16 +44,7 @@ use miniscript::{Descriptor, Segwitv0};
 use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
-use std::str::FromStr;
-
-// TODO: Configurable tx-fee (note: parties have to agree prior to swapping)
-// Current reasoning:
-// tx with largest weight (as determined by get_weight() upon broadcast in e2e
-// test) = 609 assuming segwit and 60 sat/vB:
-// (609 / 4) * 60 (sat/vB) = 9135 sats
-// Recommended: Overpay a bit to ensure we don't have to wait too long for test
-// runs.
-pub const TX_FEE: u64 = 15_000;
+use std::{ops::Add, str::FromStr};
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct SecretKey {
@@ -196,6 +198,7 @@ pub trait BuildTxLockPsbt {
         &self,
         output_address: Address,
         output_amount: Amount,
+        coin_control: &CoinControl,
     ) -> Result<PartiallySignedTransaction>;
 }
 
@@ -211,7 +214,9 @@ pub trait BroadcastSignedTransaction {
 
 #[async_trait]
 pub trait WatchForRawTransaction {
-    async fn watch_for_raw_transaction(&self, txid: Txid) -> Result<Transaction>;
+    /// `script` is the script of an output of the transaction, Electrum
+    /// servers index transactions by script.
+    async fn watch_for_raw_transaction(&self, txid: Txid, script: Script) -> Result<Transaction>;
 }
 
 #[async_trait]
@@ -223,9 +228,24 @@ pub trait WaitForTransactionFinality {
     ) -> Result<()>;
 }
 
+#[async_trait]
+pub trait BumpFeeUntilConfirmed {
+    /// Waits for the package to be confirmed, paying for it with a child
+    /// transaction whenever it is unlikely to confirm before `deadline`.
+    async fn bump_fee_until_confirmed(
+        &self,
+        package: CpfpPackage,
+        deadline: Deadline,
+    ) -> Result<()>;
+}
+
 #[async_trait]
 pub trait GetBlockHeight {
     async fn get_block_height(&self) -> Result<BlockHeight>;
+
+    /// Waits until the tip of the chain is above `height` and returns the new
+    /// tip.
+    async fn wait_for_new_block(&self, height: BlockHeight) -> Result<BlockHeight>;
 }
 
 #[async_trait]
@@ -258,8 +278,9 @@ pub async fn poll_until_block_height_is_gte<B>(client: &B, target: BlockHeight)
 where
     B: GetBlockHeight,
 {
-    while client.get_block_height().await? < target {
-        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    let mut height = client.get_block_height().await?;
+    while height < target {
+        height = client.wait_for_new_block(height).await?;
     }
     Ok(())
 }
@@ -296,10 +317,38 @@ pub async fn wait_for_cancel_timelock_to_expire<W>(
 where
     W: WatchForRawTransaction + TransactionBlockHeight + GetBlockHeight,
 {
-    let tx_lock_height = bitcoin_wallet.transaction_block_height(lock_tx_id).await?;
+    poll_until_relative_timelock_is_expired(bitcoin_wallet, lock_tx_id, cancel_timelock).await
+}
+
+/// Waits until the transaction is `timelock` blocks deep. Its height is looked
+/// up again once that height is reached, as a reorg may have moved the
+/// transaction into a later block in the meantime.
+pub async fn poll_until_relative_timelock_is_expired<W, T>(
+    bitcoin_wallet: &W,
+    txid: Txid,
+    timelock: T,
+) -> Result<()>
+where
+    W: TransactionBlockHeight + GetBlockHeight,
+    T: Copy,
+    BlockHeight: Add<T, Output = BlockHeight>,
+{
+    let mut tx_height = bitcoin_wallet.transaction_block_height(txid).await?;
+    loop {
+        poll_until_block_height_is_gte(bitcoin_wallet, tx_height + timelock).await?;
 
-    poll_until_block_height_is_gte(bitcoin_wallet, tx_lock_height + cancel_timelock).await?;
-    Ok(())
+        let current_tx_height = bitcoin_wallet.transaction_block_height(txid).await?;
+        if current_tx_height == tx_height {
+            return Ok(());
+        }
+        tracing::warn!(
+            "Transaction {} moved from block {} to block {} in a reorganisation",
+            txid,
+            tx_height,
+            current_tx_height
+        );
+        tx_height = current_tx_height;
+    }
 }
 
 #[derive(Clone, Copy, thiserror::Error, Debug)]
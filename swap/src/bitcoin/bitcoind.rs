@@ -0,0 +1,434 @@
+use crate::bitcoin::{
+    psbt, timelocks::BlockHeight, Address, Amount, CoinControl, CpfpPackage, Transaction, Txid,
+};
+use ::bitcoin::{
+    blockdata::constants::genesis_block,
+    consensus::encode::{deserialize, serialize_hex},
+    hashes::hex::FromHex,
+    util::psbt::PartiallySignedTransaction,
+    BlockHash, OutPoint,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use backoff::{backoff::Constant as ConstantBackoff, tokio::retry};
+use reqwest::Url;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::{str::FromStr, time::Duration};
+
+/// `waitfornewblock` returns after this long even if there was no new block,
+/// so we do not rely on the HTTP connection staying open indefinitely.
+const WAIT_FOR_NEW_BLOCK_TIMEOUT_MS: u64 = 60_000;
+
+/// Talks to the wallet of a Bitcoin Core node through its JSON-RPC interface.
+///
+/// Looking up transactions the wallet is not involved in requires the node to
+/// run with `-txindex`.
+#[derive(Debug, Clone)]
+pub struct Bitcoind {
+    client: reqwest::Client,
+    url: Url,
+    wallet_url: Url,
+    username: String,
+    password: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Response<T> {
+    result: Option<T>,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize, thiserror::Error)]
+#[error("bitcoind responded with error {code}: {message}")]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct VerboseTransaction {
+    blockhash: Option<BlockHash>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlockHeader {
+    height: u32,
+    /// -1 if the block is not part of the best chain.
+    confirmations: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct FundedPsbt {
+    psbt: String,
+    #[serde(with = "::bitcoin::util::amount::serde::as_btc")]
+    fee: Amount,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProcessedPsbt {
+    psbt: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SignedTransaction {
+    hex: Option<String>,
+    complete: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct WalletTransaction {
+    /// In BTC, negative for transactions sent by the wallet.
+    fee: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Unspent {
+    txid: Txid,
+    vout: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct FeeEstimate {
+    /// In BTC/kvB.
+    feerate: Option<f64>,
+}
+
+impl Bitcoind {
+    pub async fn new(url: Url, wallet_name: &str, network: ::bitcoin::Network) -> Result<Self> {
+        let username = url.username().to_owned();
+        let password = url.password().map(ToOwned::to_owned);
+        let mut url = url;
+        url.set_username("")
+            .and_then(|_| url.set_password(None))
+            .map_err(|_| anyhow!("Invalid bitcoind url"))?;
+        let wallet_url = url.join(&format!("wallet/{}", wallet_name))?;
+
+        let bitcoind = Self {
+            client: reqwest::Client::new(),
+            url,
+            wallet_url,
+            username,
+            password,
+        };
+        bitcoind.ensure_network(network).await?;
+
+        Ok(bitcoind)
+    }
+
+    async fn call<T>(&self, url: &Url, method: &str, params: Value) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let response: Response<T> = self
+            .client
+            .post(url.clone())
+            .basic_auth(&self.username, self.password.as_ref())
+            .json(&json!({
+                "jsonrpc": "1.0",
+                "id": "swap",
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await
+            .with_context(|| format!("Failed to call {} on bitcoind", method))?
+            .json()
+            .await
+            .with_context(|| format!("Failed to parse response of {} from bitcoind", method))?;
+
+        match (response.result, response.error) {
+            (_, Some(error)) => Err(error.into()),
+            (Some(result), None) => Ok(result),
+            (None, None) => bail!("bitcoind returned no result for {}", method),
+        }
+    }
+
+    async fn node_call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T> {
+        self.call(&self.url, method, params).await
+    }
+
+    async fn wallet_call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T> {
+        self.call(&self.wallet_url, method, params).await
+    }
+
+    /// Compares the genesis block of the node with the one of the network we
+    /// are configured for, to not send funds on the wrong chain.
+    async fn ensure_network(&self, network: ::bitcoin::Network) -> Result<()> {
+        let actual: BlockHash = self.node_call("getblockhash", json!([0])).await?;
+        let expected = genesis_block(network).block_hash();
+
+        if actual != expected {
+            bail!(
+                "bitcoind is not on Bitcoin {}, its genesis block is {} but {} was expected",
+                network,
+                actual,
+                expected
+            )
+        }
+
+        Ok(())
+    }
+
+    pub async fn new_address(&self) -> Result<Address> {
+        let address: String = self
+            .wallet_call("getnewaddress", json!(["", "bech32"]))
+            .await?;
+
+        Ok(Address::from_str(&address)?)
+    }
+
+    pub async fn balance(&self) -> Result<Amount> {
+        let balance: f64 = self.wallet_call("getbalance", json!([])).await?;
+
+        Ok(Amount::from_btc(balance)?)
+    }
+
+    pub async fn get_tx(&self, txid: Txid) -> Result<Option<Transaction>> {
+        match self
+            .node_call::<String>("getrawtransaction", json!([txid, false]))
+            .await
+        {
+            Ok(hex) => Ok(Some(deserialize(&Vec::<u8>::from_hex(&hex)?)?)),
+            Err(e) if is_unknown_transaction(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn transaction_fee(&self, txid: Txid) -> Result<Amount> {
+        let transaction: WalletTransaction =
+            self.wallet_call("gettransaction", json!([txid])).await?;
+        let fee = transaction
+            .fee
+            .ok_or_else(|| anyhow!("Transaction {} was not sent by the wallet", txid))?;
+
+        Ok(Amount::from_btc(fee.abs())?)
+    }
+
+    /// Returns the estimated fee rate in sat/vB, if the node has enough data.
+    pub async fn estimate_fee(&self, target_block: usize) -> Result<Option<f32>> {
+        let estimate: FeeEstimate = self
+            .node_call("estimatesmartfee", json!([target_block]))
+            .await?;
+
+        #[allow(clippy::cast_possible_truncation)]
+        let sat_per_vb = estimate
+            .feerate
+            .map(|btc_per_kvb| (btc_per_kvb * 100_000.0) as f32);
+
+        Ok(sat_per_vb)
+    }
+
+    pub async fn build_tx_lock_psbt(
+        &self,
+        output_address: Address,
+        output_amount: Amount,
+        sat_per_vb: f32,
+        coin_control: &CoinControl,
+    ) -> Result<(PartiallySignedTransaction, Amount)> {
+        let btc_per_kvb = f64::from(sat_per_vb) / 100_000.0;
+        let inputs = coin_control
+            .outpoints
+            .iter()
+            .map(|outpoint| json!({ "txid": outpoint.txid, "vout": outpoint.vout }))
+            .collect::<Vec<_>>();
+        let mut options = json!({ "feeRate": btc_per_kvb });
+        if coin_control.is_manual() {
+            options["add_inputs"] = json!(false);
+        }
+
+        // The node has no option to skip unconfirmed coins, so they are locked
+        // while it picks the inputs
+        let unconfirmed = if coin_control.confirmed_only && !coin_control.is_manual() {
+            self.unconfirmed_utxos().await?
+        } else {
+            Vec::new()
+        };
+        self.lock_unspent(&unconfirmed, true).await?;
+
+        let funded = self
+            .wallet_call::<FundedPsbt>(
+                "walletcreatefundedpsbt",
+                json!([
+                    inputs,
+                    [{ output_address.to_string(): output_amount.as_btc() }],
+                    0,
+                    options,
+                ]),
+            )
+            .await;
+
+        self.lock_unspent(&unconfirmed, false).await?;
+        let funded = funded?;
+
+        Ok((psbt::decode(&funded.psbt)?, funded.fee))
+    }
+
+    async fn unconfirmed_utxos(&self) -> Result<Vec<OutPoint>> {
+        let unspent: Vec<Unspent> = self.wallet_call("listunspent", json!([0, 0])).await?;
+
+        Ok(unspent
+            .into_iter()
+            .map(|unspent| OutPoint::new(unspent.txid, unspent.vout))
+            .collect())
+    }
+
+    async fn lock_unspent(&self, outpoints: &[OutPoint], lock: bool) -> Result<()> {
+        if outpoints.is_empty() {
+            return Ok(());
+        }
+
+        let outpoints = outpoints
+            .iter()
+            .map(|outpoint| json!({ "txid": outpoint.txid, "vout": outpoint.vout }))
+            .collect::<Vec<_>>();
+        let _: bool = self
+            .wallet_call("lockunspent", json!([!lock, outpoints]))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn sign_psbt(&self, psbt: PartiallySignedTransaction) -> Result<Transaction> {
+        let processed: ProcessedPsbt = self
+            .wallet_call("walletprocesspsbt", json!([psbt::encode(&psbt)]))
+            .await?;
+        let finalized: SignedTransaction = self
+            .node_call("finalizepsbt", json!([processed.psbt]))
+            .await?;
+
+        signed_transaction(finalized)
+    }
+
+    pub async fn broadcast(&self, transaction: &Transaction) -> Result<()> {
+        let _: Txid = self
+            .node_call("sendrawtransaction", json!([serialize_hex(transaction)]))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn watch_for_raw_transaction(&self, txid: Txid) -> Result<Transaction> {
+        retry(ConstantBackoff::new(Duration::from_secs(1)), || async {
+            match self.get_tx(txid).await {
+                Ok(Some(tx)) => Ok(tx),
+                Ok(None) => Err(backoff::Error::Transient(anyhow!(
+                    "Transaction not seen yet"
+                ))),
+                Err(e) => Err(backoff::Error::Permanent(e)),
+            }
+        })
+        .await
+    }
+
+    pub async fn block_height(&self) -> Result<BlockHeight> {
+        let height: u32 = self.node_call("getblockcount", json!([])).await?;
+
+        Ok(BlockHeight::new(height))
+    }
+
+    /// Waits until the tip of the chain is above `height` and returns the new
+    /// tip.
+    pub async fn wait_for_new_block(&self, height: BlockHeight) -> Result<BlockHeight> {
+        loop {
+            let tip = self.block_height().await?;
+            if tip > height {
+                return Ok(tip);
+            }
+            let _: Value = self
+                .node_call("waitfornewblock", json!([WAIT_FOR_NEW_BLOCK_TIMEOUT_MS]))
+                .await?;
+        }
+    }
+
+    pub async fn transaction_block_height(&self, txid: Txid) -> Result<BlockHeight> {
+        loop {
+            let tip = self.block_height().await?;
+            if let Some((height, _)) = self.confirmation(txid).await? {
+                return Ok(height);
+            }
+            self.wait_for_new_block(tip).await?;
+        }
+    }
+
+    pub async fn is_confirmed(&self, txid: Txid) -> Result<bool> {
+        Ok(self.confirmation(txid).await?.is_some())
+    }
+
+    /// The height and hash of the block of the best chain that contains the
+    /// transaction.
+    pub async fn confirmation(&self, txid: Txid) -> Result<Option<(BlockHeight, BlockHash)>> {
+        let block_hash = match self
+            .node_call::<VerboseTransaction>("getrawtransaction", json!([txid, true]))
+            .await
+        {
+            Ok(VerboseTransaction {
+                blockhash: Some(block_hash),
+            }) => block_hash,
+            Ok(VerboseTransaction { blockhash: None }) => return Ok(None),
+            Err(e) if is_unknown_transaction(&e) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let header: BlockHeader = self
+            .node_call("getblockheader", json!([block_hash]))
+            .await?;
+
+        // The transaction index still points to blocks that were reorganised
+        // out of the chain
+        if header.confirmations < 1 {
+            return Ok(None);
+        }
+
+        Ok(Some((BlockHeight::new(header.height), block_hash)))
+    }
+
+    /// Broadcasts a child spending the output of the package that pays the
+    /// given fee. The child signals replaceability, so a child paying a
+    /// higher fee replaces the previous one.
+    pub async fn broadcast_child(&self, package: &CpfpPackage, fee: Amount) -> Result<Txid> {
+        let output = package.output();
+        let parent = package
+            .transactions()
+            .last()
+            .expect("package contains at least one transaction");
+        let value = Amount::from_sat(parent.output[output.vout as usize].value);
+        let child_value = value
+            .checked_sub(fee)
+            .ok_or_else(|| anyhow!("Output of {} cannot pay a fee of {}", output.txid, fee))?;
+        let address = self.new_address().await?;
+
+        let unsigned: String = self
+            .node_call(
+                "createrawtransaction",
+                json!([
+                    [{ "txid": output.txid, "vout": output.vout, "sequence": 0xffff_fffd_u32 }],
+                    [{ address.to_string(): child_value.as_btc() }],
+                ]),
+            )
+            .await?;
+        let signed: SignedTransaction = self
+            .wallet_call("signrawtransactionwithwallet", json!([unsigned]))
+            .await?;
+        let child = signed_transaction(signed)?;
+
+        self.broadcast(&child).await?;
+
+        Ok(child.txid())
+    }
+}
+
+/// RPC_INVALID_ADDRESS_OR_KEY is returned for transactions the node does not
+/// know about.
+fn is_unknown_transaction(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<RpcError>(),
+        Some(RpcError { code: -5, .. })
+    )
+}
+
+fn signed_transaction(signed: SignedTransaction) -> Result<Transaction> {
+    match signed.hex {
+        Some(hex) if signed.complete => Ok(deserialize(&Vec::<u8>::from_hex(&hex)?)?),
+        _ => bail!("bitcoind could not sign the transaction"),
+    }
+}
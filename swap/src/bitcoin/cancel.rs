@@ -1,8 +1,10 @@
 use crate::bitcoin::{
-    build_shared_output_descriptor, Address, Amount, BlockHeight, PublicKey, Transaction, TxLock,
-    TX_FEE,
+    build_shared_output_descriptor, fee::spend_shared_output_weight, Address, Amount, BlockHeight,
+    FeeRate, Network, PublicKey, Transaction, TxLock,
+};
+use ::bitcoin::{
+    util::bip143::SigHashCache, OutPoint, Script, SigHash, SigHashType, TxIn, TxOut, Txid,
 };
-use ::bitcoin::{util::bip143::SigHashCache, OutPoint, SigHash, SigHashType, TxIn, TxOut, Txid};
 use anyhow::Result;
 use ecdsa_fun::Signature;
 use miniscript::{Descriptor, NullCtx};
@@ -45,6 +47,12 @@ impl PunishTimelock {
     }
 }
 
+impl From<PunishTimelock> for u32 {
+    fn from(timelock: PunishTimelock) -> Self {
+        timelock.0
+    }
+}
+
 impl Add<PunishTimelock> for BlockHeight {
     type Output = BlockHeight;
 
@@ -66,6 +74,7 @@ impl TxCancel {
         cancel_timelock: CancelTimelock,
         A: PublicKey,
         B: PublicKey,
+        fee_rate: FeeRate,
     ) -> Self {
         let cancel_output_descriptor = build_shared_output_descriptor(A.0, B.0);
 
@@ -77,7 +86,10 @@ impl TxCancel {
         };
 
         let tx_out = TxOut {
-            value: tx_lock.lock_amount().as_sat() - TX_FEE,
+            value: tx_lock
+                .lock_amount()
+                .as_sat()
+                .saturating_sub(Self::fee(fee_rate).as_sat()),
             script_pubkey: cancel_output_descriptor.script_pubkey(NullCtx),
         };
 
@@ -102,6 +114,19 @@ impl TxCancel {
         }
     }
 
+    /// Upper bound of the weight of the signed transaction, which spends the
+    /// lock output to the shared output. All P2WSH output scripts have the
+    /// same length, whatever the keys.
+    pub fn weight() -> usize {
+        let shared_output = Address::p2wsh(&Script::new(), Network::Bitcoin);
+
+        spend_shared_output_weight(&shared_output.script_pubkey())
+    }
+
+    pub fn fee(fee_rate: FeeRate) -> Amount {
+        fee_rate.fee_for_weight(Self::weight())
+    }
+
     pub fn txid(&self) -> Txid {
         self.inner.txid()
     }
@@ -155,6 +180,7 @@ impl TxCancel {
         &self,
         spend_address: &Address,
         sequence: Option<PunishTimelock>,
+        fee: Amount,
     ) -> Transaction {
         let previous_output = self.as_outpoint();
 
@@ -166,7 +192,7 @@ impl TxCancel {
         };
 
         let tx_out = TxOut {
-            value: self.amount().as_sat() - TX_FEE,
+            value: self.amount().as_sat().saturating_sub(fee.as_sat()),
             script_pubkey: spend_address.script_pubkey(),
         };
 
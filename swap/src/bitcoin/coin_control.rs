@@ -0,0 +1,123 @@
+use crate::bitcoin::Transaction;
+use ::bitcoin::OutPoint;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// Restricts which of our coins may fund the lock transaction, because every
+/// input we spend becomes publicly linked to the swap.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CoinControl {
+    /// Spend exactly these outputs. If empty, the wallet chooses.
+    pub outpoints: Vec<OutPoint>,
+    /// Never spend outputs of unconfirmed transactions, e.g. our own change.
+    pub confirmed_only: bool,
+    /// Fail instead of combining several coins into the lock transaction.
+    pub single_input: bool,
+}
+
+impl CoinControl {
+    pub fn is_manual(&self) -> bool {
+        !self.outpoints.is_empty()
+    }
+
+    /// Checks the inputs the wallet picked for `transaction`. Whether they
+    /// are confirmed is up to the wallet to check.
+    pub fn ensure_inputs_allowed(&self, transaction: &Transaction) -> Result<()> {
+        let inputs = transaction
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+            .collect::<Vec<_>>();
+
+        if self.single_input && inputs.len() != 1 {
+            bail!(
+                "The lock transaction needs {} inputs but a single input was required",
+                inputs.len()
+            )
+        }
+
+        if self.is_manual() {
+            if let Some(input) = inputs.iter().find(|input| !self.outpoints.contains(input)) {
+                bail!(
+                    "The lock transaction spends {} which was not selected",
+                    input
+                )
+            }
+            if let Some(outpoint) = self
+                .outpoints
+                .iter()
+                .find(|outpoint| !inputs.contains(outpoint))
+            {
+                bail!(
+                    "The lock transaction does not spend the selected {}",
+                    outpoint
+                )
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::bitcoin::{TxIn, Txid};
+    use std::str::FromStr;
+
+    fn outpoint(vout: u32) -> OutPoint {
+        OutPoint::new(
+            Txid::from_str("3aa4f4f8ea0a64f1a21e4a9dda2a9e8d0d2d2e2f57a1ff8d1bdbd7d8a1e8c4f1")
+                .unwrap(),
+            vout,
+        )
+    }
+
+    fn spending(outpoints: &[OutPoint]) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: outpoints
+                .iter()
+                .map(|outpoint| TxIn {
+                    previous_output: *outpoint,
+                    ..TxIn::default()
+                })
+                .collect(),
+            output: vec![],
+        }
+    }
+
+    #[test]
+    fn checks_selected_outpoints_and_input_count() {
+        let anything = CoinControl::default();
+        assert!(anything
+            .ensure_inputs_allowed(&spending(&[outpoint(0), outpoint(1)]))
+            .is_ok());
+
+        let single = CoinControl {
+            single_input: true,
+            ..CoinControl::default()
+        };
+        assert!(single
+            .ensure_inputs_allowed(&spending(&[outpoint(0)]))
+            .is_ok());
+        assert!(single
+            .ensure_inputs_allowed(&spending(&[outpoint(0), outpoint(1)]))
+            .is_err());
+
+        let manual = CoinControl {
+            outpoints: vec![outpoint(0)],
+            ..CoinControl::default()
+        };
+        assert!(manual
+            .ensure_inputs_allowed(&spending(&[outpoint(0)]))
+            .is_ok());
+        assert!(manual
+            .ensure_inputs_allowed(&spending(&[outpoint(1)]))
+            .is_err());
+        assert!(manual
+            .ensure_inputs_allowed(&spending(&[outpoint(0), outpoint(1)]))
+            .is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use crate::bitcoin::{Amount, BlockHeight, FeeRate, Transaction, Txid};
+use ::bitcoin::OutPoint;
+
+/// Upper bound of the virtual size of a child transaction spending one P2WPKH
+/// output to one P2WPKH output.
+const CHILD_VSIZE: u64 = 110;
+
+/// The default incremental relay fee of Bitcoin Core in satoshi per virtual
+/// byte. A child that replaces an earlier one must pay this rate for its own
+/// size on top of the fee of the earlier child (BIP125 rule 4).
+const INCREMENTAL_RELAY_FEE: u64 = 1;
+
+/// The block by which a package must be confirmed.
+#[derive(Debug, Clone, Copy)]
+pub enum Deadline {
+    Height(BlockHeight),
+    /// This many blocks after `txid` is confirmed, e.g. when a relative
+    /// timelock on one of its outputs expires.
+    AfterConfirmation {
+        txid: Txid,
+        blocks: u32,
+    },
+}
+
+impl Deadline {
+    /// The height of the deadline given the block `txid` of an
+    /// [`Deadline::AfterConfirmation`] was confirmed in, if any, and the
+    /// current tip. An unconfirmed transaction can at the earliest be
+    /// confirmed in the next block.
+    pub fn height(self, confirmed_in: Option<BlockHeight>, tip: BlockHeight) -> BlockHeight {
+        match self {
+            Deadline::Height(height) => height,
+            Deadline::AfterConfirmation { blocks, .. } => confirmed_in.unwrap_or(tip + 1) + blocks,
+        }
+    }
+}
+
+/// Transactions we published, the last of which pays to our wallet. If they
+/// are slow to confirm, a child spending that output can pay for the whole
+/// package.
+#[derive(Debug, Clone)]
+pub struct CpfpPackage {
+    transactions: Vec<(Transaction, Amount)>,
+}
+
+impl CpfpPackage {
+    /// The fee of each transaction must be given as it cannot be computed
+    /// without fetching its inputs.
+    pub fn new(transaction: Transaction, fee: Amount) -> Self {
+        Self {
+            transactions: vec![(transaction, fee)],
+        }
+    }
+
+    /// Adds an unconfirmed ancestor of the transactions already in the
+    /// package.
+    pub fn with_parent(mut self, transaction: Transaction, fee: Amount) -> Self {
+        self.transactions.insert(0, (transaction, fee));
+        self
+    }
+
+    pub fn transactions(&self) -> impl Iterator<Item = &Transaction> {
+        self.transactions.iter().map(|(transaction, _)| transaction)
+    }
+
+    /// The transaction whose confirmation confirms the whole package.
+    pub fn txid(&self) -> Txid {
+        self.transactions
+            .last()
+            .expect("package contains at least one transaction")
+            .0
+            .txid()
+    }
+
+    /// The output the child transaction spends.
+    pub fn output(&self) -> OutPoint {
+        OutPoint::new(self.txid(), 0)
+    }
+
+    /// Whether the transactions of the package that are still unconfirmed
+    /// pay less than `fee_rate` on their own.
+    pub fn needs_child<F>(&self, fee_rate: FeeRate, is_unconfirmed: F) -> bool
+    where
+        F: Fn(&Txid) -> bool,
+    {
+        let (vsize, fee) = self.unconfirmed_vsize_and_fee(is_unconfirmed);
+
+        fee < vsize * fee_rate.as_sat_per_vb()
+    }
+
+    /// The fee a child must pay so that, together with the transactions of
+    /// the package that are still unconfirmed, it pays `fee_rate`.
+    pub fn child_fee<F>(&self, fee_rate: FeeRate, is_unconfirmed: F) -> Amount
+    where
+        F: Fn(&Txid) -> bool,
+    {
+        let (vsize, fee) = self.unconfirmed_vsize_and_fee(is_unconfirmed);
+
+        let package_fee = (vsize + CHILD_VSIZE) * fee_rate.as_sat_per_vb();
+        // The child must at least pay the minimum relay fee for itself.
+        Amount::from_sat(package_fee.saturating_sub(fee).max(CHILD_VSIZE))
+    }
+
+    fn unconfirmed_vsize_and_fee<F>(&self, is_unconfirmed: F) -> (u64, u64)
+    where
+        F: Fn(&Txid) -> bool,
+    {
+        self.transactions
+            .iter()
+            .filter(|(transaction, _)| is_unconfirmed(&transaction.txid()))
+            .fold((0, 0), |(vsize, fee), (transaction, tx_fee)| {
+                let tx_vsize = (transaction.get_weight() as u64 + 3) / 4;
+                (vsize + tx_vsize, fee + tx_fee.as_sat())
+            })
+    }
+}
+
+/// The fee a child paying `fee` must actually pay to replace the child that
+/// paid `previous_fee`, so that nodes relay it.
+pub(in crate::bitcoin) fn replacement_fee(fee: Amount, previous_fee: Option<Amount>) -> Amount {
+    match previous_fee {
+        Some(previous_fee) => {
+            fee.max(previous_fee + Amount::from_sat(INCREMENTAL_RELAY_FEE * CHILD_VSIZE))
+        }
+        None => fee,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::bitcoin::{TxIn, TxOut};
+
+    fn transaction(lock_time: u32) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time,
+            input: vec![TxIn::default()],
+            output: vec![TxOut::default()],
+        }
+    }
+
+    #[test]
+    fn child_pays_for_unconfirmed_transactions_only() {
+        let parent = transaction(0);
+        let tx = transaction(1);
+        let parent_txid = parent.txid();
+        let vsize = (tx.get_weight() as u64 + 3) / 4;
+        let package = CpfpPackage::new(tx, Amount::from_sat(vsize))
+            .with_parent(parent, Amount::from_sat(vsize));
+        let fee_rate = FeeRate::from_sat_per_vb(10);
+
+        let child_fee = package.child_fee(fee_rate, |txid| *txid != parent_txid);
+        assert_eq!(
+            child_fee,
+            Amount::from_sat((vsize + CHILD_VSIZE) * 10 - vsize)
+        );
+
+        let child_fee = package.child_fee(fee_rate, |_| true);
+        assert_eq!(
+            child_fee,
+            Amount::from_sat((2 * vsize + CHILD_VSIZE) * 10 - 2 * vsize)
+        );
+    }
+
+    #[test]
+    fn child_is_only_needed_below_fee_rate() {
+        let tx = transaction(0);
+        let txid = tx.txid();
+        let vsize = (tx.get_weight() as u64 + 3) / 4;
+        let package = CpfpPackage::new(tx, Amount::from_sat(vsize * 10));
+
+        assert!(!package.needs_child(FeeRate::from_sat_per_vb(10), |_| true));
+        assert!(package.needs_child(FeeRate::from_sat_per_vb(11), |_| true));
+        // A confirmed package never needs a child
+        assert!(!package.needs_child(FeeRate::from_sat_per_vb(11), |other| *other != txid));
+    }
+
+    #[test]
+    fn child_pays_at_least_for_itself() {
+        let tx = transaction(0);
+        let package = CpfpPackage::new(tx, Amount::from_sat(100_000));
+
+        assert_eq!(
+            package.child_fee(FeeRate::from_sat_per_vb(1), |_| true),
+            Amount::from_sat(CHILD_VSIZE)
+        );
+    }
+
+    #[test]
+    fn replacement_pays_incremental_relay_fee() {
+        let increment = Amount::from_sat(INCREMENTAL_RELAY_FEE * CHILD_VSIZE);
+
+        assert_eq!(
+            replacement_fee(Amount::from_sat(1_000), None),
+            Amount::from_sat(1_000)
+        );
+        assert_eq!(
+            replacement_fee(Amount::from_sat(1_050), Some(Amount::from_sat(1_000))),
+            Amount::from_sat(1_000) + increment
+        );
+        assert_eq!(
+            replacement_fee(Amount::from_sat(5_000), Some(Amount::from_sat(1_000))),
+            Amount::from_sat(5_000)
+        );
+    }
+
+    #[test]
+    fn deadline_after_confirmation_counts_from_confirmation() {
+        let txid = transaction(0).txid();
+        let deadline = Deadline::AfterConfirmation { txid, blocks: 12 };
+        let tip = BlockHeight::new(100);
+
+        assert_eq!(
+            deadline.height(Some(BlockHeight::new(90)), tip),
+            BlockHeight::new(102)
+        );
+        // Not confirmed yet, so it can at the earliest be in the next block
+        assert_eq!(deadline.height(None, tip), BlockHeight::new(113));
+        assert_eq!(
+            Deadline::Height(BlockHeight::new(50)).height(None, tip),
+            BlockHeight::new(50)
+        );
+    }
+}
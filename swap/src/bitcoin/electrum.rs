@@ -0,0 +1,1034 @@
+use crate::bitcoin::{
+    timelocks::BlockHeight, Address, Amount, CoinControl, CpfpPackage, Transaction,
+};
+use ::bitcoin::{
+    blockdata::constants::genesis_block, util::psbt::PartiallySignedTransaction, BlockHash,
+    OutPoint, Script, Txid,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use backoff::{tokio::retry, ExponentialBackoff};
+use bdk::{
+    bitcoin::util::bip32::ExtendedPrivKey,
+    blockchain::{noop_progress, Blockchain, ElectrumBlockchain, OfflineBlockchain},
+    electrum_client::{self, Client, ElectrumApi},
+    FeeRate, KeychainKind,
+};
+use reqwest::Url;
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    path::Path,
+    sync::{Arc, Mutex, RwLock, Weak},
+    time::Duration,
+};
+use tokio::{
+    sync::watch,
+    time::{interval, interval_at, Instant},
+};
+
+const SLED_TREE_NAME: &str = "bip84";
+
+/// The tree of the wallet that used the master private key as its only key.
+const LEGACY_SLED_TREE_NAME: &str = "default_tree";
+
+/// Number of blocks within which the sweep of the legacy wallet should be
+/// confirmed.
+const LEGACY_SWEEP_TARGET_BLOCK: usize = 6;
+
+/// How often notifications about new headers and changed scripts are read
+/// from the connection.
+const NOTIFICATION_READ_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often the wallet is synced in the background.
+const WALLET_SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+/// For how long we try to reach the Electrum server before giving up.
+const MAX_RECONNECT_TIME: Duration = Duration::from_secs(120);
+
+#[derive(Debug, thiserror::Error)]
+enum Error {
+    #[error("Electrum client error: {0:?}")]
+    ElectrumClient(electrum_client::Error),
+}
+
+type BdkWallet = bdk::Wallet<OfflineBlockchain, bdk::sled::Tree>;
+type SyncWallet = bdk::Wallet<Arc<ElectrumBlockchain>, bdk::sled::Tree>;
+
+/// The height and hash of the best block.
+type Tip = (BlockHeight, BlockHash);
+
+/// A bdk wallet synced through an Electrum server, which also notifies us
+/// about new blocks and about changes to the transactions we wait for. All of
+/// that goes through one `Connection` to the server.
+///
+/// The Electrum client is blocking, so all calls to it and to the bdk wallet
+/// are made on the blocking thread pool.
+pub struct Electrum {
+    wallet: Arc<ElectrumWallet>,
+    connection: Arc<Connection>,
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    /// The script of an output of every transaction we watched for or looked
+    /// up the confirmation of.
+    scripts: Mutex<HashMap<Txid, Script>>,
+    tip: watch::Receiver<Tip>,
+    /// Notified whenever the history of a script we are subscribed to
+    /// changed.
+    history_changes: watch::Receiver<()>,
+    legacy_db: bdk::sled::Tree,
+}
+
+/// A transaction that moves all funds of the wallet that used the master
+/// private key as its only key into the BIP84 wallet.
+#[derive(Debug, Clone)]
+pub struct LegacySweep {
+    pub transaction: Transaction,
+    pub address: Address,
+    pub amount: Amount,
+    pub fee: Amount,
+}
+
+impl Electrum {
+    pub async fn new(
+        electrum_rpc_url: Url,
+        network: bitcoin::Network,
+        wallet_dir: &Path,
+        master_key: ExtendedPrivKey,
+    ) -> Result<Self> {
+        let sled = bdk::sled::open(wallet_dir)?;
+        let db = sled.open_tree(SLED_TREE_NAME)?;
+        let legacy_db = sled.open_tree(LEGACY_SLED_TREE_NAME)?;
+
+        let (wallet, connection, client, header) = tokio::task::spawn_blocking(move || {
+            let connection = Arc::new(Connection::new(electrum_rpc_url)?);
+            let client = connection.client();
+            ensure_electrum_network(&client, network)?;
+
+            let wallet = ElectrumWallet::new(connection.clone(), network, master_key, db)?;
+
+            let header = client
+                .block_headers_subscribe()
+                .map_err(|e| anyhow!("Failed to subscribe to headers: {:?}", e))?;
+
+            Result::<_>::Ok((wallet, connection, client, header))
+        })
+        .await??;
+
+        let wallet = Arc::new(wallet);
+        let subscriptions = Arc::new(Mutex::new(Subscriptions::new(client)));
+
+        let (tip_sender, tip) = watch::channel((
+            BlockHeight::new(u32::try_from(header.height)?),
+            header.header.block_hash(),
+        ));
+        let (history_sender, history_changes) = watch::channel(());
+        tokio::spawn(track_tip(
+            connection.clone(),
+            subscriptions.clone(),
+            tip_sender,
+            history_sender,
+        ));
+        tokio::spawn(sync_periodically(Arc::downgrade(&wallet)));
+
+        if !legacy_db.is_empty() {
+            tracing::info!(
+                "Found the single key wallet used by earlier versions, move its funds with the \
+                `sweep-legacy-wallet` command"
+            );
+        }
+
+        Ok(Self {
+            wallet,
+            connection,
+            subscriptions,
+            scripts: Mutex::new(HashMap::new()),
+            tip,
+            history_changes,
+            legacy_db,
+        })
+    }
+
+    /// Builds and signs, but does not publish, the transaction that moves
+    /// the funds of the single key wallet used by earlier versions into this
+    /// wallet. Returns `None` if there is nothing to move.
+    pub async fn legacy_sweep(&self) -> Result<Option<LegacySweep>> {
+        let address = self.new_address().await?;
+        let wallet = self.wallet.clone();
+        let legacy_db = self.legacy_db.clone();
+
+        tokio::task::spawn_blocking(move || {
+            build_legacy_sweep(
+                &wallet.connection,
+                wallet.network,
+                wallet.master_key,
+                legacy_db,
+                address,
+            )
+        })
+        .await?
+    }
+
+    /// Runs `f` with the bdk wallet on the blocking thread pool.
+    async fn with_wallet<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&BdkWallet) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let wallet = self.wallet.clone();
+        tokio::task::spawn_blocking(move || f(&wallet.lock())).await?
+    }
+
+    pub async fn balance(&self) -> Result<Amount> {
+        self.with_wallet(|wallet| Ok(Amount::from_sat(wallet.get_balance()?)))
+            .await
+    }
+
+    pub async fn new_address(&self) -> Result<Address> {
+        self.with_wallet(|wallet| wallet.get_new_address().map_err(Into::into))
+            .await
+    }
+
+    pub async fn get_tx(&self, txid: Txid) -> Result<Option<Transaction>> {
+        match self
+            .connection
+            .call(move |client| client.transaction_get(&txid))
+            .await
+        {
+            Ok(tx) => Ok(Some(tx)),
+            // The transaction is unknown to the server
+            Err(electrum_client::Error::Protocol(_)) => Ok(None),
+            Err(err) => Err(Error::ElectrumClient(err).into()),
+        }
+    }
+
+    pub async fn transaction_fee(&self, txid: Txid) -> Result<Amount> {
+        let fees = self
+            .with_wallet(move |wallet| {
+                let fees = wallet
+                    .list_transactions(true)?
+                    .iter()
+                    .find(|tx| tx.txid == txid)
+                    .ok_or_else(|| {
+                        anyhow!("Could not find tx in bdk wallet when trying to determine fees")
+                    })?
+                    .fees;
+
+                Ok(fees)
+            })
+            .await?;
+
+        Ok(Amount::from_sat(fees))
+    }
+
+    /// Returns the estimated fee rate in sat/vB. Electrum servers return a
+    /// negative value if they do not have enough data.
+    #[allow(clippy::cast_possible_truncation)]
+    pub async fn estimate_fee(&self, target_block: usize) -> Result<Option<f32>> {
+        let btc_per_kvb = self
+            .connection
+            .call(move |client| client.estimate_fee(target_block))
+            .await
+            .map_err(Error::ElectrumClient)?;
+
+        Ok(Some((btc_per_kvb * 100_000.0) as f32))
+    }
+
+    pub async fn sync(&self) -> Result<()> {
+        let backoff = ExponentialBackoff {
+            max_elapsed_time: Some(MAX_RECONNECT_TIME),
+            ..ExponentialBackoff::default()
+        };
+
+        retry(backoff, || async {
+            let wallet = self.wallet.clone();
+            tokio::task::spawn_blocking(move || wallet.sync())
+                .await
+                .map_err(|e| backoff::Error::Permanent(e.into()))?
+                .map_err(backoff::Error::Transient)
+        })
+        .await
+    }
+
+    pub async fn build_tx_lock_psbt(
+        &self,
+        output_address: Address,
+        output_amount: Amount,
+        sat_per_vb: f32,
+        coin_control: &CoinControl,
+    ) -> Result<(PartiallySignedTransaction, Amount)> {
+        let coin_control = coin_control.clone();
+        let (psbt, details) = self
+            .with_wallet(move |wallet| {
+                let mut builder = bdk::TxBuilder::with_recipients(vec![(
+                    output_address.script_pubkey(),
+                    output_amount.as_sat(),
+                )])
+                .fee_rate(FeeRate::from_sat_per_vb(sat_per_vb));
+                if coin_control.is_manual() {
+                    builder = builder
+                        .utxos(coin_control.outpoints.clone())
+                        .manually_selected_only();
+                }
+                if coin_control.confirmed_only {
+                    builder = builder.unspendable(unconfirmed_utxos(wallet)?);
+                }
+
+                let tx = wallet.create_tx(builder)?;
+
+                Ok(tx)
+            })
+            .await?;
+
+        Ok((psbt, Amount::from_sat(details.fees)))
+    }
+
+    pub async fn sign_psbt(&self, psbt: PartiallySignedTransaction) -> Result<Transaction> {
+        let (signed_psbt, finalized) = self
+            .with_wallet(move |wallet| Ok(wallet.sign(psbt, None)?))
+            .await?;
+        if !finalized {
+            bail!("Could not finalize psbt")
+        }
+
+        Ok(signed_psbt.extract_tx())
+    }
+
+    pub async fn broadcast(&self, transaction: &Transaction) -> Result<()> {
+        let transaction = transaction.clone();
+        self.connection
+            .call(move |client| client.transaction_broadcast(&transaction))
+            .await
+            .map_err(Error::ElectrumClient)?;
+
+        Ok(())
+    }
+
+    /// Waits until the transaction shows up in the history of `script`, which
+    /// must be the script of one of its outputs. The history is only fetched
+    /// again once the server notifies us that it changed.
+    pub async fn watch_for_raw_transaction(
+        &self,
+        txid: Txid,
+        script: Script,
+    ) -> Result<Transaction> {
+        self.scripts().insert(txid, script.clone());
+
+        let mut history_changes = self.history_changes.clone();
+        loop {
+            let history = self.history(script.clone()).await?;
+            if history.iter().any(|(tx_hash, _)| *tx_hash == txid) {
+                return self
+                    .get_tx(txid)
+                    .await?
+                    .ok_or_else(|| anyhow!("Electrum server does not know transaction {}", txid));
+            }
+
+            history_changes
+                .changed()
+                .await
+                .context("Lost the subscriptions to the Electrum server")?;
+        }
+    }
+
+    pub async fn block_height(&self) -> Result<BlockHeight> {
+        Ok(self.tip.borrow().0)
+    }
+
+    /// Waits until the tip of the chain is above `height`, or until a
+    /// reorganisation replaced the block at `height` if that is the tip, and
+    /// returns the new tip.
+    pub async fn wait_for_new_block(&self, height: BlockHeight) -> Result<BlockHeight> {
+        let mut tip = self.tip.clone();
+        let (_, hash) = *tip.borrow();
+        loop {
+            let (current, current_hash) = *tip.borrow();
+            if current > height || (current == height && current_hash != hash) {
+                return Ok(current);
+            }
+            tip.changed()
+                .await
+                .context("Lost the subscription to the headers of the Electrum server")?;
+        }
+    }
+
+    pub async fn transaction_block_height(&self, txid: Txid) -> Result<BlockHeight> {
+        loop {
+            let tip = self.block_height().await?;
+            if let Some(height) = self.confirmation_height(txid).await? {
+                return Ok(height);
+            }
+            self.wait_for_new_block(tip).await?;
+        }
+    }
+
+    pub async fn is_confirmed(&self, txid: Txid) -> Result<bool> {
+        Ok(self.confirmation_height(txid).await?.is_some())
+    }
+
+    /// The height and hash of the block of the best chain that contains the
+    /// transaction.
+    pub async fn confirmation(&self, txid: Txid) -> Result<Option<(BlockHeight, BlockHash)>> {
+        let height = match self.confirmation_height(txid).await? {
+            Some(height) => height,
+            None => return Ok(None),
+        };
+
+        Ok(self
+            .block_hash(height)
+            .await?
+            .map(|block_hash| (height, block_hash)))
+    }
+
+    async fn block_hash(&self, height: BlockHeight) -> Result<Option<BlockHash>> {
+        let height = usize::try_from(u32::from(height))?;
+        match self
+            .connection
+            .call(move |client| client.block_header(height))
+            .await
+        {
+            Ok(header) => Ok(Some(header.block_hash())),
+            // The server does not know a block at that height yet
+            Err(electrum_client::Error::Protocol(_)) => Ok(None),
+            Err(e) => Err(Error::ElectrumClient(e).into()),
+        }
+    }
+
+    /// Looks the transaction up in the history of the script of its first
+    /// output, as Electrum servers index transactions by script. We subscribe
+    /// to the script, so its history is only fetched again once the server
+    /// notifies us that it changed.
+    async fn confirmation_height(&self, txid: Txid) -> Result<Option<BlockHeight>> {
+        let script = match self.script(txid).await? {
+            Some(script) => script,
+            // The transaction is unknown until it reaches the mempool of the server
+            None => return Ok(None),
+        };
+
+        let history = self.history(script).await?;
+
+        // Unconfirmed transactions have a height of 0, or -1 if they spend
+        // unconfirmed outputs
+        let height = history
+            .iter()
+            .find(|(tx_hash, _)| *tx_hash == txid)
+            .and_then(|(_, height)| u32::try_from(*height).ok())
+            .filter(|height| *height > 0)
+            .map(BlockHeight::new);
+
+        Ok(height)
+    }
+
+    /// The transactions of the script along with their heights, subscribing
+    /// to the script first if we have not done so yet.
+    async fn history(&self, script: Script) -> Result<Vec<(Txid, i32)>> {
+        let backoff = ExponentialBackoff {
+            max_elapsed_time: Some(MAX_RECONNECT_TIME),
+            ..ExponentialBackoff::default()
+        };
+        let history = retry(backoff, || async {
+            let connection = self.connection.clone();
+            let subscriptions = self.subscriptions.clone();
+            let script = script.clone();
+
+            tokio::task::spawn_blocking(move || {
+                subscriptions
+                    .lock()
+                    .expect("electrum subscriptions mutex is not poisoned")
+                    .history(&connection, script)
+            })
+            .await
+            .expect("electrum request does not panic")
+            .map_err(|err| match err {
+                err @ electrum_client::Error::Protocol(_) => backoff::Error::Permanent(err),
+                err => backoff::Error::Transient(err),
+            })
+        })
+        .await
+        .map_err(Error::ElectrumClient)?;
+
+        Ok(history)
+    }
+
+    /// The script of the first output of the transaction, if the server knows
+    /// the transaction.
+    async fn script(&self, txid: Txid) -> Result<Option<Script>> {
+        if let Some(script) = self.scripts().get(&txid) {
+            return Ok(Some(script.clone()));
+        }
+
+        let transaction = match self.get_tx(txid).await? {
+            Some(transaction) => transaction,
+            None => return Ok(None),
+        };
+        let script = transaction
+            .output
+            .first()
+            .ok_or_else(|| anyhow!("Transaction {} has no outputs", txid))?
+            .script_pubkey
+            .clone();
+        self.scripts().insert(txid, script.clone());
+
+        Ok(Some(script))
+    }
+
+    fn scripts(&self) -> std::sync::MutexGuard<'_, HashMap<Txid, Script>> {
+        self.scripts
+            .lock()
+            .expect("electrum scripts mutex is not poisoned")
+    }
+
+    /// Broadcasts a child spending the output of the package that pays the
+    /// given fee, replacing the previous child if there is one.
+    pub async fn broadcast_child(
+        &self,
+        package: &CpfpPackage,
+        previous_child: Option<Txid>,
+        fee: Amount,
+    ) -> Result<Txid> {
+        self.sync().await?;
+
+        let output = package.output();
+        let tx = self
+            .with_wallet(move |wallet| {
+                let (psbt, _) = match previous_child {
+                    Some(txid) => wallet.bump_fee(
+                        &txid,
+                        bdk::TxBuilder::new()
+                            .fee_absolute(fee.as_sat())
+                            .maintain_single_recipient(),
+                    )?,
+                    None => {
+                        let address = wallet.get_new_address()?;
+                        wallet.create_tx(
+                            bdk::TxBuilder::new()
+                                .add_utxo(output)
+                                .manually_selected_only()
+                                .set_single_recipient(address.script_pubkey())
+                                .fee_absolute(fee.as_sat())
+                                .enable_rbf(),
+                        )?
+                    }
+                };
+
+                let (signed_psbt, finalized) = wallet.sign(psbt, None)?;
+                if !finalized {
+                    bail!("Could not finalize child transaction psbt")
+                }
+
+                Ok(signed_psbt.extract_tx())
+            })
+            .await?;
+        self.broadcast(&tx).await?;
+
+        Ok(tx.txid())
+    }
+}
+
+/// The bdk wallet, along with what it takes to sync it through the
+/// connection to the Electrum server.
+///
+/// Syncing talks to the server for a while, so it is done by a second bdk
+/// wallet on the same database. That way the wallet stays available for
+/// building and signing transactions in the meantime, which needs no
+/// connection.
+struct ElectrumWallet {
+    inner: Mutex<BdkWallet>,
+    syncer: Mutex<SyncWallet>,
+    connection: Arc<Connection>,
+    network: bitcoin::Network,
+    master_key: ExtendedPrivKey,
+    db: bdk::sled::Tree,
+}
+
+impl ElectrumWallet {
+    fn new(
+        connection: Arc<Connection>,
+        network: bitcoin::Network,
+        master_key: ExtendedPrivKey,
+        db: bdk::sled::Tree,
+    ) -> Result<Self> {
+        let inner = bdk::Wallet::new_offline(
+            bdk::template::BIP84(master_key, KeychainKind::External),
+            Some(bdk::template::BIP84(master_key, KeychainKind::Internal)),
+            network,
+            db.clone(),
+        )?;
+        let syncer = Self::syncer(connection.blockchain(), network, master_key, db.clone())?;
+
+        Ok(Self {
+            inner: Mutex::new(inner),
+            syncer: Mutex::new(syncer),
+            connection,
+            network,
+            master_key,
+            db,
+        })
+    }
+
+    /// Receives on the external and sends change to the internal chain of
+    /// BIP84, so the wallet can be restored from the master key by other
+    /// wallets. Syncing stops after 20 unused addresses on either chain.
+    fn syncer(
+        blockchain: Arc<ElectrumBlockchain>,
+        network: bitcoin::Network,
+        master_key: ExtendedPrivKey,
+        db: bdk::sled::Tree,
+    ) -> Result<SyncWallet> {
+        let wallet = bdk::Wallet::new(
+            bdk::template::BIP84(master_key, KeychainKind::External),
+            Some(bdk::template::BIP84(master_key, KeychainKind::Internal)),
+            network,
+            db,
+            blockchain,
+        )?;
+
+        Ok(wallet)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BdkWallet> {
+        self.inner.lock().expect("bdk wallet mutex is not poisoned")
+    }
+
+    /// Syncs the wallet. If that fails, the connection bdk syncs through is
+    /// established again for the next attempt, as it may have broken.
+    fn sync(&self) -> Result<()> {
+        let mut syncer = self
+            .syncer
+            .lock()
+            .expect("bdk syncer mutex is not poisoned");
+
+        let error = match syncer.sync(noop_progress(), None) {
+            Ok(()) => return Ok(()),
+            Err(error) => error,
+        };
+
+        if let Err(e) = self.connection.reconnect_blockchain(syncer.client()) {
+            tracing::debug!("Failed to reconnect wallet: {:#}", e);
+        }
+        match Self::syncer(
+            self.connection.blockchain(),
+            self.network,
+            self.master_key,
+            self.db.clone(),
+        ) {
+            Ok(wallet) => *syncer = wallet,
+            Err(e) => tracing::debug!("Failed to reconnect wallet: {:#}", e),
+        }
+
+        Err(anyhow!("Failed to sync wallet: {:?}", error))
+    }
+}
+
+/// The unspent outputs of the wallet that belong to transactions which are not
+/// in a block yet, as of the last sync.
+fn unconfirmed_utxos(wallet: &BdkWallet) -> Result<Vec<OutPoint>> {
+    let unconfirmed_txids = wallet
+        .list_transactions(false)?
+        .into_iter()
+        .filter(|details| details.height.is_none())
+        .map(|details| details.txid)
+        .collect::<Vec<_>>();
+
+    let utxos = wallet
+        .list_unspent()?
+        .into_iter()
+        .map(|utxo| utxo.outpoint)
+        .filter(|outpoint| unconfirmed_txids.contains(&outpoint.txid))
+        .collect();
+
+    Ok(utxos)
+}
+
+/// Swaps that were set up before the BIP84 wallet existed still pay to the
+/// address of the single key wallet, so this may find funds more than once.
+fn build_legacy_sweep(
+    connection: &Connection,
+    network: bitcoin::Network,
+    master_key: ExtendedPrivKey,
+    legacy_db: bdk::sled::Tree,
+    address: Address,
+) -> Result<Option<LegacySweep>> {
+    let legacy_wallet = bdk::Wallet::new(
+        bdk::template::P2WPKH(master_key.private_key),
+        None,
+        network,
+        legacy_db,
+        connection.blockchain(),
+    )?;
+    legacy_wallet.sync(noop_progress(), None)?;
+
+    if legacy_wallet.get_balance()? == 0 {
+        return Ok(None);
+    }
+
+    let fee_rate = legacy_wallet
+        .client()
+        .estimate_fee(LEGACY_SWEEP_TARGET_BLOCK)?;
+    let (psbt, details) = legacy_wallet.create_tx(
+        bdk::TxBuilder::new()
+            .drain_wallet()
+            .set_single_recipient(address.script_pubkey())
+            .fee_rate(fee_rate),
+    )?;
+    let (psbt, finalized) = legacy_wallet.sign(psbt, None)?;
+    if !finalized {
+        bail!("Could not finalize psbt")
+    }
+
+    Ok(Some(LegacySweep {
+        transaction: psbt.extract_tx(),
+        address,
+        amount: Amount::from_sat(details.sent - details.fees),
+        fee: Amount::from_sat(details.fees),
+    }))
+}
+
+/// A connection to an Electrum server that is established again if it
+/// breaks.
+///
+/// bdk syncs wallets through a client it owns, so it cannot share the client
+/// we make our own requests and subscriptions with. Both are kept here.
+struct Connection {
+    url: Url,
+    client: RwLock<Arc<Client>>,
+    blockchain: RwLock<Arc<ElectrumBlockchain>>,
+}
+
+impl Connection {
+    fn new(url: Url) -> Result<Self> {
+        let client = connect(&url)?;
+        let blockchain = ElectrumBlockchain::from(connect(&url)?);
+
+        Ok(Self {
+            url,
+            client: RwLock::new(Arc::new(client)),
+            blockchain: RwLock::new(Arc::new(blockchain)),
+        })
+    }
+
+    fn client(&self) -> Arc<Client> {
+        self.client
+            .read()
+            .expect("electrum client lock is not poisoned")
+            .clone()
+    }
+
+    /// The client bdk syncs wallets through.
+    fn blockchain(&self) -> Arc<ElectrumBlockchain> {
+        self.blockchain
+            .read()
+            .expect("electrum client lock is not poisoned")
+            .clone()
+    }
+
+    /// Replaces the client unless somebody else already replaced the broken
+    /// one.
+    fn reconnect(&self, broken: &Arc<Client>) -> Result<()> {
+        let client = connect(&self.url)?;
+        replace_broken(&self.client, broken, client);
+
+        Ok(())
+    }
+
+    /// Replaces the client of bdk unless somebody else already replaced the
+    /// broken one.
+    fn reconnect_blockchain(&self, broken: &Arc<ElectrumBlockchain>) -> Result<()> {
+        let blockchain = ElectrumBlockchain::from(connect(&self.url)?);
+        replace_broken(&self.blockchain, broken, blockchain);
+
+        Ok(())
+    }
+
+    /// Makes a request on the blocking thread pool. If it fails for any reason
+    /// other than the server rejecting it, the request is retried on a new
+    /// connection.
+    async fn call<T, F>(self: &Arc<Self>, request: F) -> Result<T, electrum_client::Error>
+    where
+        F: Fn(&Client) -> Result<T, electrum_client::Error> + Clone + Send + 'static,
+        T: Send + 'static,
+    {
+        let backoff = ExponentialBackoff {
+            max_elapsed_time: Some(MAX_RECONNECT_TIME),
+            ..ExponentialBackoff::default()
+        };
+
+        retry(backoff, || async {
+            let connection = self.clone();
+            let request = request.clone();
+
+            tokio::task::spawn_blocking(move || {
+                let client = connection.client();
+                match request(&client) {
+                    Ok(response) => Ok(response),
+                    Err(err @ electrum_client::Error::Protocol(_)) => {
+                        Err(backoff::Error::Permanent(err))
+                    }
+                    Err(err) => {
+                        tracing::debug!("Electrum request failed, reconnecting: {:?}", err);
+                        if let Err(e) = connection.reconnect(&client) {
+                            tracing::debug!("Failed to reconnect to Electrum server: {:#}", e);
+                        }
+                        Err(backoff::Error::Transient(err))
+                    }
+                }
+            })
+            .await
+            .expect("electrum request does not panic")
+        })
+        .await
+    }
+}
+
+fn replace_broken<T>(current: &RwLock<Arc<T>>, broken: &Arc<T>, replacement: T) {
+    let mut current = current
+        .write()
+        .expect("electrum client lock is not poisoned");
+    if Arc::ptr_eq(&current, broken) {
+        *current = Arc::new(replacement);
+    }
+}
+
+fn connect(url: &Url) -> Result<Client> {
+    // Workaround for https://github.com/bitcoindevkit/rust-electrum-client/issues/47.
+    let config = electrum_client::ConfigBuilder::default().retry(2).build();
+
+    Client::from_config(url.as_str(), config)
+        .map_err(|e| anyhow!("Failed to init electrum rpc client: {:?}", e))
+}
+
+/// Compares the genesis block of the Electrum server with the one of the
+/// network we are configured for, to not send funds on the wrong chain.
+fn ensure_electrum_network(client: &Client, network: bitcoin::Network) -> Result<()> {
+    let genesis_header = client
+        .block_header(0)
+        .map_err(|e| anyhow!("Failed to fetch genesis block from electrum: {:?}", e))?;
+    let expected = genesis_block(network).block_hash();
+    let actual = genesis_header.block_hash();
+
+    if actual != expected {
+        bail!(
+            "Electrum server is not on Bitcoin {}, its genesis block is {} but {} was expected",
+            network,
+            actual,
+            expected
+        )
+    }
+
+    Ok(())
+}
+
+/// What the Electrum server notified us about: new headers, and changes to the
+/// history of the scripts of the transactions we wait for.
+struct Subscriptions {
+    /// The client the subscriptions were made with.
+    client: Arc<Client>,
+    /// The heights and hashes of the headers received since they were last
+    /// published.
+    tips: Vec<(usize, BlockHash)>,
+    /// The transactions of every script we are subscribed to, along with
+    /// their heights.
+    histories: HashMap<Script, Vec<(Txid, i32)>>,
+    /// Whether any history changed since the changes were last published.
+    histories_changed: bool,
+}
+
+/// What changed since the notifications were last read.
+struct Notifications {
+    tips: Vec<(usize, BlockHash)>,
+    histories_changed: bool,
+}
+
+impl Subscriptions {
+    /// `client` must already be subscribed to headers.
+    fn new(client: Arc<Client>) -> Self {
+        Self {
+            client,
+            tips: Vec::new(),
+            histories: HashMap::new(),
+            histories_changed: false,
+        }
+    }
+
+    /// Returns the history of the script, subscribing to it first if we have
+    /// not done so yet.
+    fn history(
+        &mut self,
+        connection: &Connection,
+        script: Script,
+    ) -> Result<Vec<(Txid, i32)>, electrum_client::Error> {
+        self.with_client(connection, |subscriptions| {
+            if !subscriptions.histories.contains_key(&script) {
+                subscriptions.client.script_subscribe(&script)?;
+                let history = fetch_history(&subscriptions.client, &script)?;
+                subscriptions.histories.insert(script.clone(), history);
+            }
+
+            Ok(subscriptions.histories[&script].clone())
+        })
+    }
+
+    /// Reads the notifications that arrived since the last call, fetches the
+    /// history of every script that changed and returns what changed.
+    fn read_notifications(
+        &mut self,
+        connection: &Connection,
+    ) -> Result<Notifications, electrum_client::Error> {
+        self.with_client(connection, |subscriptions| {
+            let client = subscriptions.client.clone();
+
+            // Notifications are only read from the connection along with responses
+            client.ping()?;
+            for (script, history) in subscriptions.histories.iter_mut() {
+                let mut changed = false;
+                while client.script_pop(script)?.is_some() {
+                    changed = true;
+                }
+                if changed {
+                    *history = fetch_history(&client, script)?;
+                    subscriptions.histories_changed = true;
+                }
+            }
+            while let Some(header) = client.block_headers_pop()? {
+                subscriptions
+                    .tips
+                    .push((header.height, header.header.block_hash()));
+            }
+
+            Ok(Notifications {
+                tips: std::mem::take(&mut subscriptions.tips),
+                histories_changed: std::mem::take(&mut subscriptions.histories_changed),
+            })
+        })
+    }
+
+    /// Runs `f` once the current client of the connection is subscribed to
+    /// everything we watch. If `f` fails for any reason other than the server
+    /// rejecting a request, the connection is established again.
+    fn with_client<T, F>(
+        &mut self,
+        connection: &Connection,
+        f: F,
+    ) -> Result<T, electrum_client::Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, electrum_client::Error>,
+    {
+        let client = connection.client();
+        let result = self.resubscribe(client.clone()).and_then(|()| f(self));
+
+        match result {
+            Err(err @ electrum_client::Error::Protocol(_)) => Err(err),
+            Err(err) => {
+                tracing::debug!("Electrum subscription failed, reconnecting: {:?}", err);
+                if let Err(e) = connection.reconnect(&client) {
+                    tracing::debug!("Failed to reconnect to Electrum server: {:#}", e);
+                }
+                Err(err)
+            }
+            Ok(response) => Ok(response),
+        }
+    }
+
+    /// Subscriptions belong to a connection, so they are made again whenever
+    /// the connection was established again. Changes we missed in the
+    /// meantime are caught by fetching every history again.
+    fn resubscribe(&mut self, client: Arc<Client>) -> Result<(), electrum_client::Error> {
+        if Arc::ptr_eq(&client, &self.client) {
+            return Ok(());
+        }
+
+        let header = client.block_headers_subscribe()?;
+        self.tips.push((header.height, header.header.block_hash()));
+        for (script, history) in self.histories.iter_mut() {
+            client.script_subscribe(script)?;
+            *history = fetch_history(&client, script)?;
+        }
+        self.histories_changed = true;
+        self.client = client;
+
+        Ok(())
+    }
+}
+
+fn fetch_history(
+    client: &Client,
+    script: &Script,
+) -> Result<Vec<(Txid, i32)>, electrum_client::Error> {
+    let history = client
+        .script_get_history(script)?
+        .into_iter()
+        .map(|entry| (entry.tx_hash, entry.height))
+        .collect();
+
+    Ok(history)
+}
+
+/// Publishes every header the Electrum server notifies us about and every
+/// change to the histories we are subscribed to, until nobody is listening
+/// anymore.
+async fn track_tip(
+    connection: Arc<Connection>,
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    tip_sender: watch::Sender<Tip>,
+    history_sender: watch::Sender<()>,
+) {
+    let mut interval = interval(NOTIFICATION_READ_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let read = {
+            let connection = connection.clone();
+            let subscriptions = subscriptions.clone();
+            tokio::task::spawn_blocking(move || {
+                subscriptions
+                    .lock()
+                    .expect("electrum subscriptions mutex is not poisoned")
+                    .read_notifications(&connection)
+            })
+            .await
+        };
+        let notifications = match read {
+            Ok(Ok(notifications)) => notifications,
+            Ok(Err(e)) => {
+                tracing::warn!("Failed to read notifications from Electrum server: {:?}", e);
+                continue;
+            }
+            Err(e) => {
+                tracing::warn!("Failed to read notifications from Electrum server: {:#}", e);
+                continue;
+            }
+        };
+
+        for (height, hash) in notifications.tips {
+            match u32::try_from(height) {
+                Ok(height) => {
+                    if tip_sender.send((BlockHeight::new(height), hash)).is_err() {
+                        return;
+                    }
+                }
+                Err(_) => tracing::warn!("Invalid block height {}", height),
+            }
+        }
+        if notifications.histories_changed && history_sender.send(()).is_err() {
+            return;
+        }
+    }
+}
+
+/// Keeps balances and UTXOs current in long running processes, until the
+/// wallet is dropped.
+async fn sync_periodically(wallet: Weak<ElectrumWallet>) {
+    // The wallet is synced right after it is created
+    let mut interval = interval_at(Instant::now() + WALLET_SYNC_INTERVAL, WALLET_SYNC_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let wallet = match wallet.upgrade() {
+            Some(wallet) => wallet,
+            None => return,
+        };
+        match tokio::task::spawn_blocking(move || wallet.sync()).await {
+            Ok(Ok(())) => tracing::trace!("Synced wallet in the background"),
+            Ok(Err(e)) => tracing::warn!("{:#}", e),
+            Err(e) => tracing::warn!("Failed to sync wallet: {:#}", e),
+        }
+    }
+}
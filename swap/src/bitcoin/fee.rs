@@ -0,0 +1,222 @@
+use crate::bitcoin::{Address, Amount, TxCancel, TxRefund};
+use ::bitcoin::{consensus::encode::VarInt, Script};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Outputs below this value are considered dust and are not relayed by the
+/// network.
+const DUST_AMOUNT: u64 = 546;
+
+/// The fee that every presigned transaction paid before the parties agreed
+/// on a fee rate.
+const LEGACY_TX_FEE: u64 = 15_000;
+
+/// Represent the fee rate paid by the presigned transactions, expressed in
+/// satoshi per virtual byte. Both parties agree on it during execution setup.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FeeRate(Repr);
+
+/// A rate serializes as a plain number. Swaps set up before the fee rate was
+/// agreed on are `Legacy`, which serializes as null.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Repr {
+    Legacy,
+    SatPerVb(u64),
+}
+
+impl FeeRate {
+    pub const fn from_sat_per_vb(sat_per_vb: u64) -> Self {
+        Self(Repr::SatPerVb(sat_per_vb))
+    }
+
+    /// The fee rate of swaps whose state was stored without one, which pay
+    /// the fixed fee of that time for every transaction. Swaps set up before
+    /// the fee rate was agreed on are read with this rate, it is the serde
+    /// default of every `tx_fee_rate` field.
+    pub const fn legacy() -> Self {
+        Self(Repr::Legacy)
+    }
+
+    /// Legacy swaps did not agree on a rate, for them this is 0.
+    pub const fn as_sat_per_vb(self) -> u64 {
+        match self.0 {
+            Repr::Legacy => 0,
+            Repr::SatPerVb(sat_per_vb) => sat_per_vb,
+        }
+    }
+
+    /// The fee paid by a transaction of the given weight, rounding its virtual
+    /// size up.
+    pub fn fee_for_weight(self, weight: usize) -> Amount {
+        match self.0 {
+            Repr::Legacy => Amount::from_sat(LEGACY_TX_FEE),
+            Repr::SatPerVb(sat_per_vb) => {
+                let vsize = (weight as u64 + 3) / 4;
+
+                Amount::from_sat(vsize * sat_per_vb)
+            }
+        }
+    }
+}
+
+/// Upper bound of the weight of a signed transaction that spends the shared
+/// 2-of-2 P2WSH output to a single output with `script_pubkey`.
+pub(in crate::bitcoin) fn spend_shared_output_weight(script_pubkey: &Script) -> usize {
+    // version, input and output counts, outpoint, empty script_sig, sequence,
+    // output value and lock time
+    const FIXED_SIZE: usize = 4 + 1 + 1 + 36 + 1 + 4 + 8 + 4;
+    // segwit marker and flag, number of witness items, the empty item consumed by
+    // OP_CHECKMULTISIG, two signatures of at most 72 bytes and the witness script,
+    // each with its length
+    const WITNESS_SIZE: usize = 2 + 1 + 1 + 2 * (1 + 72) + (1 + 71);
+
+    let script_len = script_pubkey.len();
+    let output_script_size = VarInt(script_len as u64).len() + script_len;
+
+    (FIXED_SIZE + output_script_size) * 4 + WITNESS_SIZE
+}
+
+impl fmt::Display for FeeRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Repr::Legacy => write!(f, "a fixed fee of {} sat", LEGACY_TX_FEE),
+            Repr::SatPerVb(sat_per_vb) => write!(f, "{} sat/vB", sat_per_vb),
+        }
+    }
+}
+
+/// The fee rates a party is willing to agree on for the presigned
+/// transactions.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeRateBounds {
+    pub min: FeeRate,
+    pub max: FeeRate,
+}
+
+impl FeeRateBounds {
+    pub fn contains(&self, fee_rate: FeeRate) -> bool {
+        self.min <= fee_rate && fee_rate <= self.max
+    }
+
+    pub fn clamp(&self, fee_rate: FeeRate) -> FeeRate {
+        fee_rate.max(self.min).min(self.max)
+    }
+}
+
+/// Ensures the locked amount pays for the cancel and refund transactions, the
+/// most expensive path of the swap, and still leaves a relayable output.
+pub fn ensure_lock_amount_covers_fees(
+    lock_amount: Amount,
+    refund_address: &Address,
+    fee_rate: FeeRate,
+) -> Result<()> {
+    let fees = TxCancel::fee(fee_rate) + TxRefund::fee(fee_rate, refund_address);
+
+    if lock_amount < fees + Amount::from_sat(DUST_AMOUNT) {
+        bail!(
+            "Swap amount {} does not cover the fees of {} at {}",
+            lock_amount,
+            fees,
+            fee_rate
+        )
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fee_rounds_virtual_size_up() {
+        let fee_rate = FeeRate::from_sat_per_vb(10);
+
+        assert_eq!(fee_rate.fee_for_weight(400), Amount::from_sat(1000));
+        assert_eq!(fee_rate.fee_for_weight(401), Amount::from_sat(1010));
+    }
+
+    #[test]
+    fn clamps_fee_rate_into_bounds() {
+        let bounds = FeeRateBounds {
+            min: FeeRate::from_sat_per_vb(2),
+            max: FeeRate::from_sat_per_vb(50),
+        };
+
+        assert_eq!(
+            bounds.clamp(FeeRate::from_sat_per_vb(1)),
+            FeeRate::from_sat_per_vb(2)
+        );
+        assert_eq!(
+            bounds.clamp(FeeRate::from_sat_per_vb(100)),
+            FeeRate::from_sat_per_vb(50)
+        );
+        assert!(bounds.contains(FeeRate::from_sat_per_vb(20)));
+        assert!(!bounds.contains(FeeRate::from_sat_per_vb(51)));
+    }
+
+    #[test]
+    fn rejects_lock_amount_not_covering_fees() {
+        let fee_rate = FeeRate::from_sat_per_vb(100);
+        let refund_address = p2wpkh_address();
+
+        assert!(ensure_lock_amount_covers_fees(
+            Amount::from_sat(10_000),
+            &refund_address,
+            fee_rate
+        )
+        .is_err());
+        assert!(ensure_lock_amount_covers_fees(
+            Amount::from_sat(1_000_000),
+            &refund_address,
+            fee_rate
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn weight_depends_on_output_script() {
+        let p2wsh = Address::p2wsh(&Script::new(), ::bitcoin::Network::Regtest).script_pubkey();
+
+        assert_eq!(spend_shared_output_weight(&p2wsh), 598);
+        assert_eq!(
+            spend_shared_output_weight(&p2wpkh_address().script_pubkey()),
+            550
+        );
+    }
+
+    #[test]
+    fn legacy_fee_rate_pays_fixed_fee() {
+        let fee_rate = FeeRate::legacy();
+
+        assert_eq!(fee_rate.fee_for_weight(400), Amount::from_sat(15_000));
+        assert_eq!(fee_rate.fee_for_weight(598), Amount::from_sat(15_000));
+    }
+
+    #[test]
+    fn fee_rate_round_trips_and_keeps_wire_format() {
+        let fee_rate = FeeRate::from_sat_per_vb(10);
+        let encoded = serde_cbor::to_vec(&fee_rate).unwrap();
+
+        assert_eq!(encoded, serde_cbor::to_vec(&10u64).unwrap());
+        assert_eq!(
+            serde_cbor::from_slice::<FeeRate>(&encoded).unwrap(),
+            fee_rate
+        );
+
+        let legacy = serde_cbor::to_vec(&FeeRate::legacy()).unwrap();
+        assert_eq!(
+            serde_cbor::from_slice::<FeeRate>(&legacy).unwrap(),
+            FeeRate::legacy()
+        );
+    }
+
+    fn p2wpkh_address() -> Address {
+        "bcrt1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5phstwt"
+            .parse()
+            .unwrap()
+    }
+}
@@ -1,9 +1,9 @@
 use crate::bitcoin::{
-    build_shared_output_descriptor, Address, Amount, BuildTxLockPsbt, GetNetwork, PublicKey,
-    Transaction, TX_FEE,
+    build_shared_output_descriptor, Address, Amount, BuildTxLockPsbt, CoinControl, GetNetwork,
+    PublicKey, Transaction,
 };
-use ::bitcoin::{util::psbt::PartiallySignedTransaction, OutPoint, TxIn, TxOut, Txid};
-use anyhow::Result;
+use ::bitcoin::{util::psbt::PartiallySignedTransaction, OutPoint, Script, TxIn, TxOut, Txid};
+use anyhow::{bail, Result};
 use miniscript::{Descriptor, NullCtx};
 use serde::{Deserialize, Serialize};
 
@@ -14,7 +14,13 @@ pub struct TxLock {
 }
 
 impl TxLock {
-    pub async fn new<W>(wallet: &W, amount: Amount, A: PublicKey, B: PublicKey) -> Result<Self>
+    pub async fn new<W>(
+        wallet: &W,
+        amount: Amount,
+        A: PublicKey,
+        B: PublicKey,
+        coin_control: &CoinControl,
+    ) -> Result<Self>
     where
         W: BuildTxLockPsbt + GetNetwork,
     {
@@ -23,7 +29,9 @@ impl TxLock {
             .address(wallet.get_network().await, NullCtx)
             .expect("can derive address from descriptor");
 
-        let psbt = wallet.build_tx_lock_psbt(address, amount).await?;
+        let psbt = wallet
+            .build_tx_lock_psbt(address, amount, coin_control)
+            .await?;
 
         Ok(Self {
             inner: psbt,
@@ -39,6 +47,21 @@ impl TxLock {
         self.inner.clone().extract_tx().txid()
     }
 
+    /// The coins of our wallet this transaction spends.
+    pub fn inputs(&self) -> Vec<OutPoint> {
+        self.inner
+            .global
+            .unsigned_tx
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+            .collect()
+    }
+
+    pub fn script_pubkey(&self) -> Script {
+        self.output_descriptor.script_pubkey(NullCtx)
+    }
+
     pub fn as_outpoint(&self) -> OutPoint {
         // This is fine because a transaction that has that many outputs is not
         // realistic
@@ -46,6 +69,46 @@ impl TxLock {
         OutPoint::new(self.txid(), self.lock_output_vout() as u32)
     }
 
+    /// Checks that `transaction` is this lock transaction with all of its
+    /// inputs signed. The presigned transactions spend the lock output by its
+    /// txid, so a signer, e.g. an external wallet, must not change anything.
+    pub fn ensure_signed(&self, transaction: &Transaction) -> Result<()> {
+        let vout = self.lock_output_vout();
+        match transaction.output.get(vout) {
+            Some(output)
+                if output.script_pubkey == self.script_pubkey()
+                    && output.value == self.lock_amount().as_sat() => {}
+            _ => bail!(
+                "Transaction {} does not lock {} in output {}",
+                transaction.txid(),
+                self.lock_amount(),
+                vout
+            ),
+        }
+
+        if transaction.txid() != self.txid() {
+            bail!(
+                "Transaction {} differs from the lock transaction {}",
+                transaction.txid(),
+                self.txid()
+            )
+        }
+
+        // Only segwit inputs keep the txid from changing once signed
+        if let Some(index) = transaction
+            .input
+            .iter()
+            .position(|input| input.witness.is_empty())
+        {
+            bail!(
+                "Input {} of the lock transaction is not signed or not segwit",
+                index
+            )
+        }
+
+        Ok(())
+    }
+
     /// Retreive the index of the locked output in the transaction outputs
     /// vector
     fn lock_output_vout(&self) -> usize {
@@ -64,6 +127,7 @@ impl TxLock {
         &self,
         spend_address: &Address,
         sequence: Option<u32>,
+        fee: Amount,
     ) -> Transaction {
         let previous_output = self.as_outpoint();
 
@@ -75,7 +139,7 @@ impl TxLock {
         };
 
         let tx_out = TxOut {
-            value: self.inner.clone().extract_tx().output[self.lock_output_vout()].value - TX_FEE,
+            value: self.lock_amount().as_sat().saturating_sub(fee.as_sat()),
             script_pubkey: spend_address.script_pubkey(),
         };
 
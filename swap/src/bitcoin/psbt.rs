@@ -0,0 +1,88 @@
+use crate::bitcoin::{Transaction, TxLock};
+use ::bitcoin::{
+    consensus::encode::{deserialize, serialize},
+    util::psbt::PartiallySignedTransaction,
+};
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// How often we look for the signed PSBT.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Hands the lock transaction to a wallet we do not hold the keys of, e.g. a
+/// hardware wallet, by exchanging base64 encoded PSBT files in a directory.
+#[derive(Debug, Clone)]
+pub struct ExternalSigner {
+    dir: PathBuf,
+}
+
+impl ExternalSigner {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Writes the lock transaction to `<txid>.psbt` and waits for the user to
+    /// put the signed and finalized PSBT next to it as `<txid>-signed.psbt`.
+    pub async fn sign_tx_lock(&self, tx_lock: TxLock) -> Result<Transaction> {
+        let txid = tx_lock.txid();
+        let unsigned_path = self.dir.join(format!("{}.psbt", txid));
+        let signed_path = self.dir.join(format!("{}-signed.psbt", txid));
+
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Could not create directory {}", self.dir.display()))?;
+        fs::write(&unsigned_path, encode(&tx_lock.into()))
+            .with_context(|| format!("Could not write PSBT to {}", unsigned_path.display()))?;
+
+        tracing::info!(
+            "Sign the Bitcoin lock transaction in {} with your wallet and save the finalized PSBT as {}. Do not broadcast it yourself.",
+            unsigned_path.display(),
+            signed_path.display()
+        );
+
+        while !signed_path.exists() {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+
+        read_signed(&signed_path)
+    }
+}
+
+fn read_signed(path: &Path) -> Result<Transaction> {
+    let psbt = fs::read_to_string(path)
+        .with_context(|| format!("Could not read PSBT from {}", path.display()))?;
+    let psbt = decode(psbt.trim())
+        .with_context(|| format!("Could not decode PSBT in {}", path.display()))?;
+
+    Ok(psbt.extract_tx())
+}
+
+pub(in crate::bitcoin) fn encode(psbt: &PartiallySignedTransaction) -> String {
+    base64::encode(serialize(psbt))
+}
+
+pub(in crate::bitcoin) fn decode(psbt: &str) -> Result<PartiallySignedTransaction> {
+    Ok(deserialize(&base64::decode(psbt)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::bitcoin::{Transaction, TxIn, TxOut};
+
+    #[test]
+    fn psbt_roundtrip() {
+        let psbt = PartiallySignedTransaction::from_unsigned_tx(Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn::default()],
+            output: vec![TxOut::default()],
+        })
+        .unwrap();
+
+        assert_eq!(decode(&encode(&psbt)).unwrap(), psbt);
+    }
+}
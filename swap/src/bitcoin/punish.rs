@@ -1,4 +1,7 @@
-use crate::bitcoin::{Address, PublicKey, PunishTimelock, Transaction, TxCancel};
+use crate::bitcoin::{
+    fee::spend_shared_output_weight, Address, Amount, FeeRate, PublicKey, PunishTimelock,
+    Transaction, TxCancel,
+};
 use ::bitcoin::{util::bip143::SigHashCache, SigHash, SigHashType};
 use anyhow::Result;
 use ecdsa_fun::Signature;
@@ -16,8 +19,13 @@ impl TxPunish {
         tx_cancel: &TxCancel,
         punish_address: &Address,
         punish_timelock: PunishTimelock,
+        fee_rate: FeeRate,
     ) -> Self {
-        let tx_punish = tx_cancel.build_spend_transaction(punish_address, Some(punish_timelock));
+        let tx_punish = tx_cancel.build_spend_transaction(
+            punish_address,
+            Some(punish_timelock),
+            Self::fee(fee_rate, punish_address),
+        );
 
         let digest = SigHashCache::new(&tx_punish).signature_hash(
             0, // Only one input: cancel transaction
@@ -32,6 +40,16 @@ impl TxPunish {
         }
     }
 
+    /// Upper bound of the weight of the signed transaction, which spends the
+    /// cancel output to `punish_address`.
+    pub fn weight(punish_address: &Address) -> usize {
+        spend_shared_output_weight(&punish_address.script_pubkey())
+    }
+
+    pub fn fee(fee_rate: FeeRate, punish_address: &Address) -> Amount {
+        fee_rate.fee_for_weight(Self::weight(punish_address))
+    }
+
     pub fn digest(&self) -> SigHash {
         self.digest
     }
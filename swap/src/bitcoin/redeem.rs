@@ -1,6 +1,6 @@
 use crate::bitcoin::{
-    verify_sig, Address, EmptyWitnessStack, NoInputs, NotThreeWitnesses, PublicKey, TooManyInputs,
-    Transaction, TxLock,
+    fee::spend_shared_output_weight, verify_sig, Address, Amount, EmptyWitnessStack, FeeRate,
+    NoInputs, NotThreeWitnesses, PublicKey, TooManyInputs, Transaction, TxLock,
 };
 use ::bitcoin::{util::bip143::SigHashCache, SigHash, SigHashType, Txid};
 use anyhow::{bail, Context, Result};
@@ -15,10 +15,14 @@ pub struct TxRedeem {
 }
 
 impl TxRedeem {
-    pub fn new(tx_lock: &TxLock, redeem_address: &Address) -> Self {
+    pub fn new(tx_lock: &TxLock, redeem_address: &Address, fee_rate: FeeRate) -> Self {
         // lock_input is the shared output that is now being used as an input for the
         // redeem transaction
-        let tx_redeem = tx_lock.build_spend_transaction(redeem_address, None);
+        let tx_redeem = tx_lock.build_spend_transaction(
+            redeem_address,
+            None,
+            Self::fee(fee_rate, redeem_address),
+        );
 
         let digest = SigHashCache::new(&tx_redeem).signature_hash(
             0, // Only one input: lock_input (lock transaction)
@@ -33,6 +37,16 @@ impl TxRedeem {
         }
     }
 
+    /// Upper bound of the weight of the signed transaction, which spends the
+    /// lock output to `redeem_address`.
+    pub fn weight(redeem_address: &Address) -> usize {
+        spend_shared_output_weight(&redeem_address.script_pubkey())
+    }
+
+    pub fn fee(fee_rate: FeeRate, redeem_address: &Address) -> Amount {
+        fee_rate.fee_for_weight(Self::weight(redeem_address))
+    }
+
     pub fn txid(&self) -> Txid {
         self.inner.txid()
     }
@@ -1,6 +1,6 @@
 use crate::bitcoin::{
-    verify_sig, Address, EmptyWitnessStack, NoInputs, NotThreeWitnesses, PublicKey, TooManyInputs,
-    Transaction, TxCancel,
+    fee::spend_shared_output_weight, verify_sig, Address, Amount, EmptyWitnessStack, FeeRate,
+    NoInputs, NotThreeWitnesses, PublicKey, TooManyInputs, Transaction, TxCancel,
 };
 use ::bitcoin::{util::bip143::SigHashCache, SigHash, SigHashType, Txid};
 use anyhow::{bail, Context, Result};
@@ -15,8 +15,12 @@ pub struct TxRefund {
 }
 
 impl TxRefund {
-    pub fn new(tx_cancel: &TxCancel, refund_address: &Address) -> Self {
-        let tx_punish = tx_cancel.build_spend_transaction(refund_address, None);
+    pub fn new(tx_cancel: &TxCancel, refund_address: &Address, fee_rate: FeeRate) -> Self {
+        let tx_punish = tx_cancel.build_spend_transaction(
+            refund_address,
+            None,
+            Self::fee(fee_rate, refund_address),
+        );
 
         let digest = SigHashCache::new(&tx_punish).signature_hash(
             0, // Only one input: cancel transaction
@@ -31,6 +35,16 @@ impl TxRefund {
         }
     }
 
+    /// Upper bound of the weight of the signed transaction, which spends the
+    /// cancel output to `refund_address`.
+    pub fn weight(refund_address: &Address) -> usize {
+        spend_shared_output_weight(&refund_address.script_pubkey())
+    }
+
+    pub fn fee(fee_rate: FeeRate, refund_address: &Address) -> Amount {
+        fee_rate.fee_for_weight(Self::weight(refund_address))
+    }
+
     pub fn txid(&self) -> Txid {
         self.inner.txid()
     }
@@ -1,119 +1,266 @@
 use crate::{
     bitcoin::{
-        timelocks::BlockHeight, Address, Amount, BroadcastSignedTransaction, BuildTxLockPsbt,
-        GetBlockHeight, GetNetwork, GetRawTransaction, SignTxLock, Transaction,
+        bitcoind::Bitcoind, cpfp::replacement_fee, electrum::Electrum, timelocks::BlockHeight,
+        Address, Amount, BroadcastSignedTransaction, BuildTxLockPsbt, BumpFeeUntilConfirmed,
+        CoinControl, CpfpPackage, Deadline, ExternalSigner, FeeRateBounds, GetBlockHeight,
+        GetNetwork, GetRawTransaction, LegacySweep, SignTxLock, Transaction,
         TransactionBlockHeight, TxLock, WaitForTransactionFinality, WatchForRawTransaction,
     },
     execution_params::ExecutionParams,
 };
-use ::bitcoin::{util::psbt::PartiallySignedTransaction, Txid};
-use anyhow::{anyhow, bail, Context, Result};
+use ::bitcoin::{util::psbt::PartiallySignedTransaction, BlockHash, Script, Txid};
+use anyhow::{anyhow, bail, Result};
 use async_trait::async_trait;
-use backoff::{backoff::Constant as ConstantBackoff, tokio::retry};
-use bdk::{
-    blockchain::{noop_progress, Blockchain, ElectrumBlockchain},
-    electrum_client::{self, Client, ElectrumApi},
-    miniscript::bitcoin::PrivateKey,
-    FeeRate,
-};
-use reqwest::{Method, Url};
+use bdk::{bitcoin::util::bip32::ExtendedPrivKey, FeeRate};
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
-use std::{path::Path, sync::Arc, time::Duration};
-use tokio::{sync::Mutex, time::interval};
-
-const SLED_TREE_NAME: &str = "default_tree";
-
-#[derive(Debug, thiserror::Error)]
-enum Error {
-    #[error("Sending the request failed")]
-    Io(reqwest::Error),
-    #[error("Conversion to Integer failed")]
-    Parse(std::num::ParseIntError),
-    #[error("The transaction is not minded yet")]
-    NotYetMined,
-    #[error("Deserialization failed")]
-    JsonDeserialization(reqwest::Error),
-    #[error("Electrum client error")]
-    ElectrumClient(electrum_client::Error),
+use std::path::Path;
+
+/// How the fee rate of the transactions funded by the wallet is chosen.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeeEstimation {
+    /// Number of blocks within which the transaction should be confirmed.
+    pub target_block: usize,
+    pub min_sat_per_vb: f32,
+    pub max_sat_per_vb: f32,
+    /// Used if the backend cannot estimate the fee rate.
+    pub fallback_sat_per_vb: f32,
+}
+
+impl Default for FeeEstimation {
+    fn default() -> Self {
+        Self {
+            target_block: 6,
+            min_sat_per_vb: 1.0,
+            max_sat_per_vb: 100.0,
+            fallback_sat_per_vb: 5.0,
+        }
+    }
+}
+
+impl FeeEstimation {
+    /// Bounds the estimated fee rate by the configured floor and ceiling,
+    /// using the fallback if there is no usable estimate.
+    fn fee_rate(&self, estimate: Option<f32>) -> FeeRate {
+        let sat_per_vb = match estimate {
+            Some(sat_per_vb) if sat_per_vb > 0.0 => sat_per_vb,
+            _ => self.fallback_sat_per_vb,
+        };
+
+        FeeRate::from_sat_per_vb(sat_per_vb.max(self.min_sat_per_vb).min(self.max_sat_per_vb))
+    }
+
+    /// The fee rates we accept for the presigned transactions.
+    pub fn presigned_fee_rate_bounds(&self) -> FeeRateBounds {
+        FeeRateBounds {
+            min: presigned_fee_rate(self.min_sat_per_vb),
+            max: presigned_fee_rate(self.max_sat_per_vb),
+        }
+    }
+}
+
+/// Rounds up to a whole number of satoshi per virtual byte so the presigned
+/// transactions never pay less than estimated.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn presigned_fee_rate(sat_per_vb: f32) -> crate::bitcoin::FeeRate {
+    crate::bitcoin::FeeRate::from_sat_per_vb(sat_per_vb.max(0.0).ceil() as u64)
+}
+
+/// The node the wallet uses to fund, sign and follow transactions.
+enum Backend {
+    Electrum(Electrum),
+    Bitcoind(Bitcoind),
 }
 
 pub struct Wallet {
-    pub inner: Arc<Mutex<bdk::Wallet<ElectrumBlockchain, bdk::sled::Tree>>>,
+    backend: Backend,
+    external_signer: Option<ExternalSigner>,
     pub network: bitcoin::Network,
-    pub http_url: Url,
-    pub rpc_url: Url,
+    pub fee_estimation: FeeEstimation,
 }
 
 impl Wallet {
     pub async fn new(
         electrum_rpc_url: Url,
-        electrum_http_url: Url,
         network: bitcoin::Network,
         wallet_dir: &Path,
-        private_key: PrivateKey,
+        master_key: ExtendedPrivKey,
+        fee_estimation: FeeEstimation,
     ) -> Result<Self> {
-        // Workaround for https://github.com/bitcoindevkit/rust-electrum-client/issues/47.
-        let config = electrum_client::ConfigBuilder::default().retry(2).build();
-
-        let client = Client::from_config(electrum_rpc_url.as_str(), config)
-            .map_err(|e| anyhow!("Failed to init electrum rpc client: {:?}", e))?;
+        let electrum = Electrum::new(electrum_rpc_url, network, wallet_dir, master_key).await?;
 
-        let db = bdk::sled::open(wallet_dir)?.open_tree(SLED_TREE_NAME)?;
-
-        let bdk_wallet = bdk::Wallet::new(
-            bdk::template::P2WPKH(private_key),
-            None,
+        Ok(Self {
+            backend: Backend::Electrum(electrum),
+            external_signer: None,
             network,
-            db,
-            ElectrumBlockchain::from(client),
-        )?;
+            fee_estimation,
+        })
+    }
+
+    /// Uses the wallet `wallet_name` of the Bitcoin Core node at
+    /// `bitcoind_rpc_url`, which holds the keys instead of our seed.
+    pub async fn new_bitcoind(
+        bitcoind_rpc_url: Url,
+        wallet_name: &str,
+        network: bitcoin::Network,
+        fee_estimation: FeeEstimation,
+    ) -> Result<Self> {
+        let bitcoind = Bitcoind::new(bitcoind_rpc_url, wallet_name, network).await?;
 
         Ok(Self {
-            inner: Arc::new(Mutex::new(bdk_wallet)),
+            backend: Backend::Bitcoind(bitcoind),
+            external_signer: None,
             network,
-            http_url: electrum_http_url,
-            rpc_url: electrum_rpc_url,
+            fee_estimation,
         })
     }
 
+    /// Leaves signing the lock transaction to an external wallet, so the
+    /// backend only needs to be able to fund it, e.g. a watch-only wallet.
+    pub fn with_external_signer(self, external_signer: ExternalSigner) -> Self {
+        Self {
+            external_signer: Some(external_signer),
+            ..self
+        }
+    }
+
     pub async fn balance(&self) -> Result<Amount> {
-        let balance = self.inner.lock().await.get_balance()?;
-        Ok(Amount::from_sat(balance))
+        match &self.backend {
+            Backend::Electrum(electrum) => electrum.balance().await,
+            Backend::Bitcoind(bitcoind) => bitcoind.balance().await,
+        }
     }
 
     pub async fn new_address(&self) -> Result<Address> {
-        self.inner
-            .lock()
-            .await
-            .get_new_address()
-            .map_err(Into::into)
+        match &self.backend {
+            Backend::Electrum(electrum) => electrum.new_address().await,
+            Backend::Bitcoind(bitcoind) => bitcoind.new_address().await,
+        }
     }
 
     pub async fn get_tx(&self, txid: Txid) -> Result<Option<Transaction>> {
-        let tx = self.inner.lock().await.client().get_tx(&txid)?;
-        Ok(tx)
+        match &self.backend {
+            Backend::Electrum(electrum) => electrum.get_tx(txid).await,
+            Backend::Bitcoind(bitcoind) => bitcoind.get_tx(txid).await,
+        }
     }
 
     pub async fn transaction_fee(&self, txid: Txid) -> Result<Amount> {
-        let fees = self
-            .inner
-            .lock()
+        match &self.backend {
+            Backend::Electrum(electrum) => electrum.transaction_fee(txid).await,
+            Backend::Bitcoind(bitcoind) => bitcoind.transaction_fee(txid).await,
+        }
+    }
+
+    /// Estimates the fee rate for a transaction to confirm within the
+    /// configured number of blocks.
+    pub async fn estimate_fee_rate(&self) -> FeeRate {
+        self.estimate_fee_rate_within(self.fee_estimation.target_block)
             .await
-            .list_transactions(true)?
-            .iter()
-            .find(|tx| tx.txid == txid)
-            .ok_or_else(|| {
-                anyhow!("Could not find tx in bdk wallet when trying to determine fees")
-            })?
-            .fees;
+    }
+
+    async fn estimate_fee_rate_within(&self, target_block: usize) -> FeeRate {
+        let estimate = match &self.backend {
+            Backend::Electrum(electrum) => electrum.estimate_fee(target_block).await,
+            Backend::Bitcoind(bitcoind) => bitcoind.estimate_fee(target_block).await,
+        };
+        let estimate = match estimate {
+            Ok(estimate) => estimate,
+            Err(e) => {
+                tracing::warn!("Failed to estimate fee rate, using fallback: {:?}", e);
+                None
+            }
+        };
+
+        self.fee_estimation.fee_rate(estimate)
+    }
+
+    /// Estimates the fee rate to propose for the presigned transactions.
+    pub async fn estimate_presigned_fee_rate(&self) -> crate::bitcoin::FeeRate {
+        presigned_fee_rate(self.estimate_fee_rate().await.as_sat_vb())
+    }
 
-        Ok(Amount::from_sat(fees))
+    /// The transaction that moves the funds of the single key wallet used by
+    /// earlier versions into this wallet, if it has any. Only wallets synced
+    /// through Electrum had one.
+    pub async fn legacy_sweep(&self) -> Result<Option<LegacySweep>> {
+        match &self.backend {
+            Backend::Electrum(electrum) => electrum.legacy_sweep().await,
+            Backend::Bitcoind(_) => Ok(None),
+        }
+    }
+
+    /// Moves the funds of the single key wallet of earlier versions into this
+    /// wallet. The transaction is only printed if this is a dry run.
+    pub async fn sweep_legacy_wallet(&self, dry_run: bool) -> Result<()> {
+        let sweep = match self.legacy_sweep().await? {
+            Some(sweep) => sweep,
+            None => {
+                tracing::info!("The single key wallet has no funds to move");
+                return Ok(());
+            }
+        };
+
+        tracing::info!(
+            "Moving {} to {} for a fee of {}",
+            sweep.amount,
+            sweep.address,
+            sweep.fee
+        );
+        tracing::info!("Transaction {}:", sweep.transaction.txid());
+        // Printed as is, so that it can be copied and published by other means
+        println!(
+            "{}",
+            ::bitcoin::consensus::encode::serialize_hex(&sweep.transaction)
+        );
+
+        if dry_run {
+            return Ok(());
+        }
+
+        let txid = self.broadcast_signed_transaction(sweep.transaction).await?;
+        tracing::info!("Published transaction {}", txid);
+
+        Ok(())
     }
 
     pub async fn sync_wallet(&self) -> Result<()> {
         tracing::debug!("syncing wallet");
-        self.inner.lock().await.sync(noop_progress(), None)?;
-        Ok(())
+        match &self.backend {
+            Backend::Electrum(electrum) => electrum.sync().await,
+            // The node keeps its wallet in sync
+            Backend::Bitcoind(_) => Ok(()),
+        }
+    }
+
+    /// The height and hash of the block that contains the transaction.
+    async fn confirmation(&self, txid: Txid) -> Result<Option<(BlockHeight, BlockHash)>> {
+        match &self.backend {
+            Backend::Electrum(electrum) => electrum.confirmation(txid).await,
+            Backend::Bitcoind(bitcoind) => bitcoind.confirmation(txid).await,
+        }
+    }
+
+    async fn is_confirmed(&self, txid: Txid) -> Result<bool> {
+        match &self.backend {
+            Backend::Electrum(electrum) => electrum.is_confirmed(txid).await,
+            Backend::Bitcoind(bitcoind) => bitcoind.is_confirmed(txid).await,
+        }
+    }
+
+    async fn broadcast_child(
+        &self,
+        package: &CpfpPackage,
+        previous_child: Option<Txid>,
+        fee: Amount,
+    ) -> Result<Txid> {
+        match &self.backend {
+            Backend::Electrum(electrum) => {
+                electrum.broadcast_child(package, previous_child, fee).await
+            }
+            Backend::Bitcoind(bitcoind) => bitcoind.broadcast_child(package, fee).await,
+        }
     }
 }
 
@@ -123,16 +270,54 @@ impl BuildTxLockPsbt for Wallet {
         &self,
         output_address: Address,
         output_amount: Amount,
+        coin_control: &CoinControl,
     ) -> Result<PartiallySignedTransaction> {
         tracing::debug!("building tx lock");
-        let (psbt, _details) = self.inner.lock().await.create_tx(
-            bdk::TxBuilder::with_recipients(vec![(
-                output_address.script_pubkey(),
-                output_amount.as_sat(),
-            )])
-            // todo: get actual fee
-            .fee_rate(FeeRate::from_sat_per_vb(5.0)),
-        )?;
+        let fee_rate = self.estimate_fee_rate().await;
+        let (psbt, fee) = match &self.backend {
+            Backend::Electrum(electrum) => {
+                electrum
+                    .build_tx_lock_psbt(
+                        output_address,
+                        output_amount,
+                        fee_rate.as_sat_vb(),
+                        coin_control,
+                    )
+                    .await?
+            }
+            Backend::Bitcoind(bitcoind) => {
+                bitcoind
+                    .build_tx_lock_psbt(
+                        output_address,
+                        output_amount,
+                        fee_rate.as_sat_vb(),
+                        coin_control,
+                    )
+                    .await?
+            }
+        };
+
+        // The backends apply the selection while funding, check it once more
+        // on what they actually funded
+        let unsigned_tx = &psbt.global.unsigned_tx;
+        coin_control.ensure_inputs_allowed(unsigned_tx)?;
+        if coin_control.confirmed_only {
+            for input in unsigned_tx.input.iter() {
+                if !self.is_confirmed(input.previous_output.txid).await? {
+                    bail!(
+                        "The lock transaction spends {} which is not confirmed",
+                        input.previous_output
+                    )
+                }
+            }
+        }
+
+        tracing::info!(
+            "Locking {} will cost a fee of {} ({} sat/vB)",
+            output_amount,
+            fee,
+            fee_rate.as_sat_vb()
+        );
         tracing::debug!("tx lock built");
         Ok(psbt)
     }
@@ -143,12 +328,11 @@ impl SignTxLock for Wallet {
     async fn sign_tx_lock(&self, tx_lock: TxLock) -> Result<Transaction> {
         let txid = tx_lock.txid();
         tracing::debug!("signing tx lock: {}", txid);
-        let psbt = PartiallySignedTransaction::from(tx_lock);
-        let (signed_psbt, finalized) = self.inner.lock().await.sign(psbt, None)?;
-        if !finalized {
-            bail!("Could not finalize TxLock psbt")
-        }
-        let tx = signed_psbt.extract_tx();
+        let tx = match (&self.external_signer, &self.backend) {
+            (Some(external_signer), _) => external_signer.sign_tx_lock(tx_lock).await?,
+            (None, Backend::Electrum(electrum)) => electrum.sign_psbt(tx_lock.into()).await?,
+            (None, Backend::Bitcoind(bitcoind)) => bitcoind.sign_psbt(tx_lock.into()).await?,
+        };
         tracing::debug!("signed tx lock: {}", txid);
         Ok(tx)
     }
@@ -158,7 +342,10 @@ impl SignTxLock for Wallet {
 impl BroadcastSignedTransaction for Wallet {
     async fn broadcast_signed_transaction(&self, transaction: Transaction) -> Result<Txid> {
         tracing::debug!("attempting to broadcast tx: {}", transaction.txid());
-        self.inner.lock().await.broadcast(transaction.clone())?;
+        match &self.backend {
+            Backend::Electrum(electrum) => electrum.broadcast(&transaction).await?,
+            Backend::Bitcoind(bitcoind) => bitcoind.broadcast(&transaction).await?,
+        }
         tracing::info!("Bitcoin tx broadcasted! TXID = {}", transaction.txid());
         Ok(transaction.txid())
     }
@@ -166,26 +353,12 @@ impl BroadcastSignedTransaction for Wallet {
 
 #[async_trait]
 impl WatchForRawTransaction for Wallet {
-    async fn watch_for_raw_transaction(&self, txid: Txid) -> Result<Transaction> {
+    async fn watch_for_raw_transaction(&self, txid: Txid, script: Script) -> Result<Transaction> {
         tracing::debug!("watching for tx: {}", txid);
-        let tx = retry(ConstantBackoff::new(Duration::from_secs(1)), || async {
-            let client = Client::new(self.rpc_url.as_ref())
-                .map_err(|err| backoff::Error::Permanent(Error::ElectrumClient(err)))?;
-
-            let tx = client.transaction_get(&txid).map_err(|err| match err {
-                electrum_client::Error::Protocol(err) => {
-                    tracing::debug!("Received protocol error {} from Electrum, retrying...", err);
-                    backoff::Error::Transient(Error::NotYetMined)
-                }
-                err => backoff::Error::Permanent(Error::ElectrumClient(err)),
-            })?;
-
-            Result::<_, backoff::Error<Error>>::Ok(tx)
-        })
-        .await
-        .context("transient errors to be retried")?;
-
-        Ok(tx)
+        match &self.backend {
+            Backend::Electrum(electrum) => electrum.watch_for_raw_transaction(txid, script).await,
+            Backend::Bitcoind(bitcoind) => bitcoind.watch_for_raw_transaction(txid).await,
+        }
     }
 }
 
@@ -201,58 +374,27 @@ impl GetRawTransaction for Wallet {
 #[async_trait]
 impl GetBlockHeight for Wallet {
     async fn get_block_height(&self) -> Result<BlockHeight> {
-        let url = blocks_tip_height_url(&self.http_url)?;
-        let height = retry(ConstantBackoff::new(Duration::from_secs(1)), || async {
-            let height = reqwest::Client::new()
-                .request(Method::GET, url.clone())
-                .send()
-                .await
-                .map_err(Error::Io)?
-                .text()
-                .await
-                .map_err(Error::Io)?
-                .parse::<u32>()
-                .map_err(|err| backoff::Error::Permanent(Error::Parse(err)))?;
-            Result::<_, backoff::Error<Error>>::Ok(height)
-        })
-        .await
-        .context("transient errors to be retried")?;
+        match &self.backend {
+            Backend::Electrum(electrum) => electrum.block_height().await,
+            Backend::Bitcoind(bitcoind) => bitcoind.block_height().await,
+        }
+    }
 
-        Ok(BlockHeight::new(height))
+    async fn wait_for_new_block(&self, height: BlockHeight) -> Result<BlockHeight> {
+        match &self.backend {
+            Backend::Electrum(electrum) => electrum.wait_for_new_block(height).await,
+            Backend::Bitcoind(bitcoind) => bitcoind.wait_for_new_block(height).await,
+        }
     }
 }
 
 #[async_trait]
 impl TransactionBlockHeight for Wallet {
     async fn transaction_block_height(&self, txid: Txid) -> Result<BlockHeight> {
-        let url = tx_status_url(txid, &self.http_url)?;
-        #[derive(Serialize, Deserialize, Debug, Clone)]
-        struct TransactionStatus {
-            block_height: Option<u32>,
-            confirmed: bool,
+        match &self.backend {
+            Backend::Electrum(electrum) => electrum.transaction_block_height(txid).await,
+            Backend::Bitcoind(bitcoind) => bitcoind.transaction_block_height(txid).await,
         }
-        let height = retry(ConstantBackoff::new(Duration::from_secs(1)), || async {
-            let resp = reqwest::Client::new()
-                .request(Method::GET, url.clone())
-                .send()
-                .await
-                .map_err(|err| backoff::Error::Transient(Error::Io(err)))?;
-
-            let tx_status: TransactionStatus = resp
-                .json()
-                .await
-                .map_err(|err| backoff::Error::Permanent(Error::JsonDeserialization(err)))?;
-
-            let block_height = tx_status
-                .block_height
-                .ok_or(backoff::Error::Transient(Error::NotYetMined))?;
-
-            Result::<_, backoff::Error<Error>>::Ok(block_height)
-        })
-        .await
-        .context("transient errors to be retried")?;
-
-        Ok(BlockHeight::new(height))
     }
 }
 
@@ -264,22 +406,58 @@ impl WaitForTransactionFinality for Wallet {
         execution_params: ExecutionParams,
     ) -> Result<()> {
         tracing::debug!("waiting for tx finality: {}", txid);
-        // Divide by 4 to not check too often yet still be aware of the new block early
-        // on.
-        let mut interval = interval(execution_params.bitcoin_avg_block_time / 4);
+        let mut block_height = self.get_block_height().await?;
+        // Kept to publish the transaction again if a reorg drops it
+        let mut transaction = None;
+        // The last block the transaction was seen in, kept while it is
+        // reorganised out so it is published again at every new block
+        let mut confirmed_in = None;
 
         loop {
-            let tx_block_height = self.transaction_block_height(txid).await?;
-            tracing::debug!("tx_block_height: {:?}", tx_block_height);
-            let block_height = self.get_block_height().await?;
-            tracing::debug!("latest_block_height: {:?}", block_height);
-            if let Some(confirmations) = block_height.checked_sub(tx_block_height) {
-                tracing::debug!("confirmations: {:?}", confirmations);
-                if u32::from(confirmations) >= execution_params.bitcoin_finality_confirmations {
-                    break;
+            let confirmation = self.confirmation(txid).await?;
+            match (confirmed_in, confirmation) {
+                (Some((height, _)), None) => {
+                    tracing::warn!(
+                        "Transaction {} was reorganised out of block {}, publishing it again",
+                        txid,
+                        height
+                    );
+                    if let Some(transaction) = transaction.clone() {
+                        if let Err(e) = self.broadcast_signed_transaction(transaction).await {
+                            tracing::warn!("Failed to publish transaction {}: {:#}", txid, e);
+                        }
+                    }
+                }
+                (Some((previous, previous_hash)), Some((height, hash)))
+                    if previous_hash != hash =>
+                {
+                    tracing::warn!(
+                        "Transaction {} moved from block {} to block {} in a reorganisation",
+                        txid,
+                        previous,
+                        height
+                    );
                 }
+                _ => {}
             }
-            interval.tick().await;
+
+            if let Some((tx_block_height, _)) = confirmation {
+                confirmed_in = confirmation;
+
+                if transaction.is_none() {
+                    transaction = self.get_tx(txid).await?;
+                }
+
+                tracing::debug!("tx_block_height: {:?}", tx_block_height);
+                tracing::debug!("latest_block_height: {:?}", block_height);
+                if let Some(confirmations) = block_height.checked_sub(tx_block_height) {
+                    tracing::debug!("confirmations: {:?}", confirmations);
+                    if u32::from(confirmations) >= execution_params.bitcoin_finality_confirmations {
+                        break;
+                    }
+                }
+            }
+            block_height = self.wait_for_new_block(block_height).await?;
         }
 
         Ok(())
@@ -287,47 +465,156 @@ impl WaitForTransactionFinality for Wallet {
 }
 
 #[async_trait]
-impl GetNetwork for Wallet {
-    async fn get_network(&self) -> bitcoin::Network {
-        self.inner.lock().await.network()
+impl BumpFeeUntilConfirmed for Wallet {
+    async fn bump_fee_until_confirmed(
+        &self,
+        package: CpfpPackage,
+        deadline: Deadline,
+    ) -> Result<()> {
+        tracing::debug!("waiting for confirmation of tx: {}", package.txid());
+        let mut block_height = self.get_block_height().await?;
+        let mut child: Option<(Txid, Amount)> = None;
+
+        loop {
+            match self
+                .bump_fee_if_unconfirmed(&package, deadline, block_height, &mut child)
+                .await
+            {
+                Ok(true) => break,
+                Ok(false) => {}
+                // The package is still in the mempool, so we check again in
+                // the next block
+                Err(e) => tracing::warn!(
+                    "Failed to check confirmation of tx {}: {:#}",
+                    package.txid(),
+                    e
+                ),
+            }
+
+            block_height = self.wait_for_new_block(block_height).await?;
+        }
+
+        Ok(())
     }
 }
 
-fn tx_status_url(txid: Txid, base_url: &Url) -> Result<Url> {
-    let url = base_url.join(&format!("tx/{}/status", txid))?;
-    Ok(url)
+impl Wallet {
+    /// Pays for the package with a child transaction if it is not confirmed
+    /// yet and the fee paid so far is too low to confirm it before the
+    /// deadline. Returns whether the package is confirmed.
+    async fn bump_fee_if_unconfirmed(
+        &self,
+        package: &CpfpPackage,
+        deadline: Deadline,
+        block_height: BlockHeight,
+        child: &mut Option<(Txid, Amount)>,
+    ) -> Result<bool> {
+        let mut unconfirmed = Vec::new();
+        for transaction in package.transactions() {
+            if !self.is_confirmed(transaction.txid()).await? {
+                unconfirmed.push(transaction.txid());
+            }
+        }
+        if !unconfirmed.contains(&package.txid()) {
+            return Ok(true);
+        }
+
+        let confirmed_in = match deadline {
+            Deadline::AfterConfirmation { txid, .. } => {
+                self.confirmation(txid).await?.map(|(height, _)| height)
+            }
+            Deadline::Height(_) => None,
+        };
+        let deadline = deadline.height(confirmed_in, block_height);
+
+        // Aim to confirm within half of the blocks left, so there is still
+        // time to bump again if the estimate turns out to be too low.
+        let blocks_left = deadline
+            .checked_sub(block_height)
+            .map(u32::from)
+            .unwrap_or_default();
+        let target_block = (blocks_left / 2).max(1) as usize;
+        let fee_rate = presigned_fee_rate(
+            self.estimate_fee_rate_within(target_block)
+                .await
+                .as_sat_vb(),
+        );
+
+        let is_unconfirmed = |txid: &Txid| unconfirmed.contains(txid);
+        // Packages that pay enough on their own are left alone, only once we
+        // paid for one do we keep up with the estimate
+        if child.is_none() && !package.needs_child(fee_rate, is_unconfirmed) {
+            return Ok(false);
+        }
+
+        let fee = package.child_fee(fee_rate, is_unconfirmed);
+        let paid = child.map(|(_, fee)| fee);
+
+        if fee > paid.unwrap_or(Amount::ZERO) {
+            // Every child spends the same output, so each one replaces the
+            // previous one
+            let fee = replacement_fee(fee, paid);
+            match self
+                .broadcast_child(package, child.map(|(txid, _)| txid), fee)
+                .await
+            {
+                Ok(txid) => {
+                    tracing::info!(
+                        "Bumped fee of tx {} to {} with child tx {}",
+                        package.txid(),
+                        fee_rate,
+                        txid
+                    );
+                    *child = Some((txid, fee));
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to bump fee of tx {}: {:#}", package.txid(), e)
+                }
+            }
+        }
+
+        Ok(false)
+    }
 }
 
-fn blocks_tip_height_url(base_url: &Url) -> Result<Url> {
-    let url = base_url.join("blocks/tip/height")?;
-    Ok(url)
+#[async_trait]
+impl GetNetwork for Wallet {
+    async fn get_network(&self) -> bitcoin::Network {
+        self.network
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{
-        bitcoin::{
-            wallet::{blocks_tip_height_url, tx_status_url},
-            Txid,
-        },
-        cli::config::DEFAULT_ELECTRUM_HTTP_URL,
-    };
-    use reqwest::Url;
+    use crate::bitcoin::wallet::FeeEstimation;
 
     #[test]
-    fn create_tx_status_url_from_default_base_url_success() {
-        let txid: Txid = Txid::default();
-        let base_url = Url::parse(DEFAULT_ELECTRUM_HTTP_URL).expect("Could not parse url");
-        let url = tx_status_url(txid, &base_url).expect("Could not create url");
-        let expected = format!("https://blockstream.info/testnet/api/tx/{}/status", txid);
-        assert_eq!(url.as_str(), expected);
+    fn estimated_fee_rate_is_bounded() {
+        let fee_estimation = FeeEstimation::default();
+
+        assert_eq!(
+            fee_estimation.fee_rate(Some(1000.0)).as_sat_vb(),
+            fee_estimation.max_sat_per_vb
+        );
+        assert_eq!(
+            fee_estimation.fee_rate(Some(0.5)).as_sat_vb(),
+            fee_estimation.min_sat_per_vb
+        );
+        assert_eq!(fee_estimation.fee_rate(Some(12.0)).as_sat_vb(), 12.0);
     }
 
     #[test]
-    fn create_block_tip_height_url_from_default_base_url_success() {
-        let base_url = Url::parse(DEFAULT_ELECTRUM_HTTP_URL).expect("Could not parse url");
-        let url = blocks_tip_height_url(&base_url).expect("Could not create url");
-        let expected = "https://blockstream.info/testnet/api/blocks/tip/height";
-        assert_eq!(url.as_str(), expected);
+    fn falls_back_if_fee_rate_cannot_be_estimated() {
+        let fee_estimation = FeeEstimation::default();
+
+        // Electrum servers return -1 if they do not have enough data
+        assert_eq!(
+            fee_estimation.fee_rate(Some(-0.1)).as_sat_vb(),
+            fee_estimation.fallback_sat_per_vb
+        );
+        assert_eq!(
+            fee_estimation.fee_rate(None).as_sat_vb(),
+            fee_estimation.fallback_sat_per_vb
+        );
     }
 }
@@ -1,4 +1,5 @@
-use crate::bitcoin;
+use crate::{bitcoin, monero};
+use ::bitcoin::OutPoint;
 use libp2p::{core::Multiaddr, PeerId};
 use std::path::PathBuf;
 use uuid::Uuid;
@@ -28,11 +29,42 @@ pub enum Command {
 
         #[structopt(long = "send-btc", help = "Bitcoin amount as floating point nr without denomination (e.g. 1.25)", parse(try_from_str = parse_btc))]
         send_bitcoin: bitcoin::Amount,
+
+        #[structopt(
+            long = "utxo",
+            help = "Fund the BTC lock transaction with exactly these outputs (txid:vout), can be given multiple times"
+        )]
+        utxos: Vec<OutPoint>,
+
+        #[structopt(
+            long = "confirmed-only",
+            help = "Do not fund the BTC lock transaction with unconfirmed outputs"
+        )]
+        confirmed_only: bool,
+
+        #[structopt(
+            long = "single-input",
+            help = "Fail instead of funding the BTC lock transaction with more than one output"
+        )]
+        single_input: bool,
+
+        #[structopt(
+            long = "receive-address",
+            help = "Send the redeemed XMR to this address once it is unlocked"
+        )]
+        receive_address: Option<monero::Address>,
     },
     History,
     Resume(Resume),
     Cancel(Cancel),
     Refund(Refund),
+    #[structopt(
+        about = "Move the funds of the single key Bitcoin wallet used by earlier versions"
+    )]
+    SweepLegacyWallet {
+        #[structopt(long, help = "Only print the transaction instead of publishing it")]
+        dry_run: bool,
+    },
 }
 
 #[derive(structopt::StructOpt, Debug)]
@@ -41,11 +73,17 @@ pub enum Resume {
         #[structopt(long = "swap-id")]
         swap_id: Uuid,
 
-        #[structopt(long = "counterpart-peer-id")]
-        alice_peer_id: PeerId,
+        #[structopt(
+            long = "counterpart-peer-id",
+            help = "Override the peer id of the counterparty stored for this swap"
+        )]
+        alice_peer_id: Option<PeerId>,
 
-        #[structopt(long = "counterpart-addr")]
-        alice_addr: Multiaddr,
+        #[structopt(
+            long = "counterpart-addr",
+            help = "Override the address of the counterparty stored for this swap"
+        )]
+        alice_addr: Option<Multiaddr>,
     },
 }
 
@@ -55,13 +93,6 @@ pub enum Cancel {
         #[structopt(long = "swap-id")]
         swap_id: Uuid,
 
-        // TODO: Remove Alice peer-id/address, it should be saved in the database when running swap
-        // and loaded from the database when running resume/cancel/refund
-        #[structopt(long = "counterpart-peer-id")]
-        alice_peer_id: PeerId,
-        #[structopt(long = "counterpart-addr")]
-        alice_addr: Multiaddr,
-
         #[structopt(short, long)]
         force: bool,
     },
@@ -73,13 +104,6 @@ pub enum Refund {
         #[structopt(long = "swap-id")]
         swap_id: Uuid,
 
-        // TODO: Remove Alice peer-id/address, it should be saved in the database when running swap
-        // and loaded from the database when running resume/cancel/refund
-        #[structopt(long = "counterpart-peer-id")]
-        alice_peer_id: PeerId,
-        #[structopt(long = "counterpart-addr")]
-        alice_addr: Multiaddr,
-
         #[structopt(short, long)]
         force: bool,
     },
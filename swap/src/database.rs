@@ -1,7 +1,8 @@
 pub use alice::Alice;
 pub use bob::Bob;
 
-use anyhow::{anyhow, bail, Context, Result};
+use anyhow::{anyhow, Context, Result};
+use libp2p::{Multiaddr, PeerId};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{fmt::Display, path::Path};
 use uuid::Uuid;
@@ -15,6 +16,20 @@ pub enum Swap {
     Bob(Bob),
 }
 
+/// Swap states as written by earlier versions, that [`Swap`] cannot read.
+#[derive(Deserialize)]
+enum LegacySwap {
+    Alice(alice::LegacyAlice),
+}
+
+impl From<LegacySwap> for Swap {
+    fn from(from: LegacySwap) -> Self {
+        match from {
+            LegacySwap::Alice(alice) => Swap::Alice(alice.into()),
+        }
+    }
+}
+
 impl From<Alice> for Swap {
     fn from(from: Alice) -> Self {
         Swap::Alice(from)
@@ -36,65 +51,174 @@ impl Display for Swap {
     }
 }
 
-pub struct Database(sled::Db);
+/// The counterparty of a swap, stored so that the swap can be resumed without
+/// the user having to provide the connection details again.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+struct Peer {
+    #[serde(with = "crate::serde_peer_id")]
+    peer_id: PeerId,
+    address: Multiaddr,
+}
+
+/// Everything stored for a swap under its id. Bob records the counterparty
+/// and his receive address before the swap has a state, so all of them are
+/// optional.
+///
+/// Earlier versions stored only the [`Swap`], such records are read as a
+/// record without a peer.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct SwapRecord {
+    state: Option<Swap>,
+    peer: Option<Peer>,
+    /// Where Bob sends the redeemed Monero, in its string encoding.
+    monero_address: Option<String>,
+}
+
+pub struct Database {
+    swaps: sled::Db,
+}
 
 impl Database {
     pub fn open(path: &Path) -> Result<Self> {
-        let db =
+        let swaps =
             sled::open(path).with_context(|| format!("Could not open the DB at {:?}", path))?;
 
-        Ok(Database(db))
+        Ok(Database { swaps })
     }
 
-    pub async fn insert_latest_state(&self, swap_id: Uuid, state: Swap) -> Result<()> {
+    pub async fn insert_peer(
+        &self,
+        swap_id: Uuid,
+        peer_id: PeerId,
+        address: Multiaddr,
+    ) -> Result<()> {
         let key = serialize(&swap_id)?;
-        let new_value = serialize(&state).context("Could not serialize new state value")?;
+        self.update_record(&key, |record| {
+            record.peer = Some(Peer { peer_id, address });
+        })?;
+
+        self.swaps
+            .flush_async()
+            .await
+            .map(|_| ())
+            .context("Could not flush db")
+    }
 
-        let old_value = self.0.get(&key)?;
+    pub fn get_peer(&self, swap_id: Uuid) -> Result<(PeerId, Multiaddr)> {
+        let peer = self
+            .get_record(swap_id)?
+            .and_then(|record| record.peer)
+            .ok_or_else(|| anyhow!("No peer stored for swap with id {}", swap_id))?;
 
-        self.0
-            .compare_and_swap(key, old_value, Some(new_value))
-            .context("Could not write in the DB")?
-            .context("Stored swap somehow changed, aborting saving")?;
+        Ok((peer.peer_id, peer.address))
+    }
 
-        // TODO: see if this can be done through sled config
-        self.0
+    /// Records where the Monero of a swap is sent to once it is redeemed.
+    pub async fn insert_monero_address(
+        &self,
+        swap_id: Uuid,
+        address: monero::Address,
+    ) -> Result<()> {
+        let key = serialize(&swap_id)?;
+        self.update_record(&key, |record| {
+            record.monero_address = Some(address.to_string());
+        })?;
+
+        self.swaps
             .flush_async()
             .await
             .map(|_| ())
             .context("Could not flush db")
     }
 
-    pub fn get_state(&self, swap_id: Uuid) -> anyhow::Result<Swap> {
+    /// Returns `None` if the Monero of the swap stays in the wallet it is
+    /// redeemed to.
+    pub fn get_monero_address(&self, swap_id: Uuid) -> Result<Option<monero::Address>> {
+        let address = match self
+            .get_record(swap_id)?
+            .and_then(|record| record.monero_address)
+        {
+            Some(address) => address,
+            None => return Ok(None),
+        };
+
+        let address = address
+            .parse()
+            .with_context(|| format!("Stored Monero address {} is invalid", address))?;
+        Ok(Some(address))
+    }
+
+    pub async fn insert_latest_state(&self, swap_id: Uuid, state: Swap) -> Result<()> {
         let key = serialize(&swap_id)?;
+        self.update_record(&key, |record| record.state = Some(state))?;
 
-        let encoded = self
-            .0
-            .get(&key)?
-            .ok_or_else(|| anyhow!("Swap with id {} not found in database", swap_id))?;
+        // TODO: see if this can be done through sled config
+        self.swaps
+            .flush_async()
+            .await
+            .map(|_| ())
+            .context("Could not flush db")
+    }
 
-        let state = deserialize(&encoded).context("Could not deserialize state")?;
-        Ok(state)
+    pub fn get_state(&self, swap_id: Uuid) -> anyhow::Result<Swap> {
+        self.get_record(swap_id)?
+            .and_then(|record| record.state)
+            .ok_or_else(|| anyhow!("Swap with id {} not found in database", swap_id))
     }
 
+    /// Swaps for which only the peer is stored are left out, they never got
+    /// past the execution setup.
     pub fn all(&self) -> Result<Vec<(Uuid, Swap)>> {
-        self.0
+        self.swaps
             .iter()
-            .map(|item| match item {
+            .filter_map(|item| match item {
                 Ok((key, value)) => {
                     let swap_id = deserialize::<Uuid>(&key);
-                    let swap = deserialize::<Swap>(&value).context("failed to deserialize swap");
+                    let record = deserialize_record(&value).context("failed to deserialize swap");
 
-                    match (swap_id, swap) {
-                        (Ok(swap_id), Ok(swap)) => Ok((swap_id, swap)),
-                        (Ok(_), Err(err)) => Err(err),
-                        _ => bail!("failed to deserialize swap"),
+                    match (swap_id, record) {
+                        (Ok(swap_id), Ok(record)) => record.state.map(|swap| Ok((swap_id, swap))),
+                        (Ok(_), Err(err)) => Some(Err(err)),
+                        _ => Some(Err(anyhow!("failed to deserialize swap"))),
                     }
                 }
-                Err(err) => Err(err).context("failed to retrieve swap from DB"),
+                Err(err) => Some(Err(err).context("failed to retrieve swap from DB")),
             })
             .collect()
     }
+
+    fn get_record(&self, swap_id: Uuid) -> Result<Option<SwapRecord>> {
+        let key = serialize(&swap_id)?;
+
+        match self.swaps.get(&key)? {
+            Some(encoded) => Ok(Some(
+                deserialize_record(&encoded).context("Could not deserialize swap")?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Changes the record stored under `key`, without flushing.
+    fn update_record<F>(&self, key: &[u8], f: F) -> Result<()>
+    where
+        F: FnOnce(&mut SwapRecord),
+    {
+        let old_value = self.swaps.get(key)?;
+        let mut record = match &old_value {
+            Some(encoded) => deserialize_record(encoded).context("Could not deserialize swap")?,
+            None => SwapRecord::default(),
+        };
+        f(&mut record);
+        let new_value = serialize(&record).context("Could not serialize swap")?;
+
+        self.swaps
+            .compare_and_swap(key, old_value, Some(new_value))
+            .context("Could not write in the DB")?
+            .context("Stored swap somehow changed, aborting saving")?;
+
+        Ok(())
+    }
 }
 
 pub fn serialize<T>(t: &T) -> anyhow::Result<Vec<u8>>
@@ -111,6 +235,30 @@ where
     Ok(serde_cbor::from_slice(&v)?)
 }
 
+fn deserialize_record(v: &[u8]) -> anyhow::Result<SwapRecord> {
+    match deserialize::<SwapRecord>(v) {
+        Ok(record) => Ok(record),
+        Err(e) => match deserialize_swap(v) {
+            Ok(swap) => Ok(SwapRecord {
+                state: Some(swap),
+                peer: None,
+                monero_address: None,
+            }),
+            Err(_) => Err(e),
+        },
+    }
+}
+
+fn deserialize_swap(v: &[u8]) -> anyhow::Result<Swap> {
+    match deserialize::<Swap>(v) {
+        Ok(swap) => Ok(swap),
+        Err(e) => match deserialize::<LegacySwap>(v) {
+            Ok(legacy) => Ok(legacy.into()),
+            Err(_) => Err(e),
+        },
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -118,6 +266,7 @@ mod tests {
         alice::{Alice, AliceEndState},
         bob::{Bob, BobEndState},
     };
+    use rand::rngs::OsRng;
 
     #[tokio::test]
     async fn can_write_and_read_to_multiple_keys() {
@@ -198,4 +347,115 @@ mod tests {
         assert!(swaps.contains(&(swap_id_1, state_1)));
         assert!(swaps.contains(&(swap_id_2, state_2)));
     }
+
+    #[tokio::test]
+    async fn can_write_and_read_peer() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+
+        let swap_id = Uuid::new_v4();
+        let peer_id = PeerId::random();
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/9876".parse().unwrap();
+
+        db.insert_peer(swap_id, peer_id, address.clone())
+            .await
+            .expect("Failed to save peer");
+
+        let (recovered_peer_id, recovered_address) =
+            db.get_peer(swap_id).expect("Failed to recover peer");
+
+        assert_eq!(recovered_peer_id, peer_id);
+        assert_eq!(recovered_address, address);
+    }
+
+    #[tokio::test]
+    async fn peers_are_not_listed_as_swaps() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+
+        let swap_id = Uuid::new_v4();
+        db.insert_latest_state(swap_id, Swap::Bob(Bob::Done(BobEndState::SafelyAborted)))
+            .await
+            .unwrap();
+        db.insert_peer(
+            swap_id,
+            PeerId::random(),
+            "/ip4/127.0.0.1/tcp/9876".parse().unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let swaps = db.all().unwrap();
+
+        assert_eq!(swaps.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn peer_is_kept_when_the_state_changes() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+
+        let swap_id = Uuid::new_v4();
+        let peer_id = PeerId::random();
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/9876".parse().unwrap();
+
+        db.insert_peer(swap_id, peer_id, address.clone())
+            .await
+            .unwrap();
+        assert!(db.all().unwrap().is_empty());
+        assert!(db.get_state(swap_id).is_err());
+
+        let state = Swap::Bob(Bob::Done(BobEndState::SafelyAborted));
+        db.insert_latest_state(swap_id, state.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(db.get_state(swap_id).unwrap(), state);
+        assert_eq!(db.get_peer(swap_id).unwrap(), (peer_id, address));
+    }
+
+    #[tokio::test]
+    async fn reads_swaps_stored_without_a_record() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let swap_id = Uuid::new_v4();
+        let state = Swap::Bob(Bob::Done(BobEndState::SafelyAborted));
+        {
+            let sled = sled::open(db_dir.path()).unwrap();
+            sled.insert(serialize(&swap_id).unwrap(), serialize(&state).unwrap())
+                .unwrap();
+            sled.flush().unwrap();
+        }
+
+        let db = Database::open(db_dir.path()).unwrap();
+
+        assert_eq!(db.get_state(swap_id).unwrap(), state);
+        assert!(db.get_peer(swap_id).is_err());
+        assert_eq!(db.all().unwrap(), vec![(swap_id, state)]);
+    }
+
+    #[tokio::test]
+    async fn can_write_and_read_monero_address() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+
+        let swap_id = Uuid::new_v4();
+        let key = || {
+            monero::PublicKey::from_private_key(&monero::PrivateKey::from_scalar(
+                crate::monero::Scalar::random(&mut OsRng),
+            ))
+        };
+        let address = monero::Address::standard(monero::Network::Stagenet, key(), key());
+
+        assert_eq!(db.get_monero_address(swap_id).unwrap(), None);
+
+        db.insert_monero_address(swap_id, address)
+            .await
+            .expect("Failed to save address");
+
+        let recovered = db
+            .get_monero_address(swap_id)
+            .expect("Failed to recover address");
+
+        assert_eq!(recovered, Some(address));
+    }
 }
@@ -1,7 +1,7 @@
 use crate::{
     bitcoin::{EncryptedSignature, TxCancel, TxRefund},
     monero,
-    monero::monero_private_key,
+    monero::{monero_private_key, BlockHeight},
     protocol::{alice, alice::AliceState},
 };
 use ::bitcoin::hashes::core::fmt::Display;
@@ -23,22 +23,76 @@ pub enum Alice {
         #[serde(with = "crate::serde_peer_id")]
         bob_peer_id: PeerId,
     },
-    XmrLocked(alice::State3),
+    XmrLocked {
+        state3: alice::State3,
+        #[serde(default)]
+        monero_wallet_restore_blockheight: Option<BlockHeight>,
+    },
     EncSigLearned {
         encrypted_signature: EncryptedSignature,
         state3: alice::State3,
+        #[serde(default)]
+        monero_wallet_restore_blockheight: Option<BlockHeight>,
+    },
+    CancelTimelockExpired {
+        state3: alice::State3,
+        #[serde(default)]
+        monero_wallet_restore_blockheight: Option<BlockHeight>,
+    },
+    BtcCancelled {
+        state3: alice::State3,
+        #[serde(default)]
+        monero_wallet_restore_blockheight: Option<BlockHeight>,
+    },
+    BtcPunishable {
+        state3: alice::State3,
+        #[serde(default)]
+        monero_wallet_restore_blockheight: Option<BlockHeight>,
     },
-    CancelTimelockExpired(alice::State3),
-    BtcCancelled(alice::State3),
-    BtcPunishable(alice::State3),
     BtcRefunded {
         state3: alice::State3,
         #[serde(with = "monero_private_key")]
         spend_key: monero::PrivateKey,
+        #[serde(default)]
+        monero_wallet_restore_blockheight: Option<BlockHeight>,
     },
     Done(AliceEndState),
 }
 
+/// The states that only held `State3` before we recorded the height of the
+/// Monero lock transaction. [`Alice`] itself reads the other old states.
+#[derive(Clone, Debug, Deserialize)]
+pub enum LegacyAlice {
+    XmrLocked(alice::State3),
+    CancelTimelockExpired(alice::State3),
+    BtcCancelled(alice::State3),
+    BtcPunishable(alice::State3),
+}
+
+impl From<LegacyAlice> for Alice {
+    fn from(legacy: LegacyAlice) -> Self {
+        // Without the height, a refund scans the whole chain for the Monero
+        match legacy {
+            LegacyAlice::XmrLocked(state3) => Alice::XmrLocked {
+                state3,
+                monero_wallet_restore_blockheight: None,
+            },
+            LegacyAlice::CancelTimelockExpired(state3) => Alice::CancelTimelockExpired {
+                state3,
+                monero_wallet_restore_blockheight: None,
+            },
+            LegacyAlice::BtcCancelled(state3) => Alice::BtcCancelled {
+                state3,
+                monero_wallet_restore_blockheight: None,
+            },
+            LegacyAlice::BtcPunishable(state3) => Alice::BtcPunishable {
+                state3,
+                monero_wallet_restore_blockheight: None,
+            },
+        }
+    }
+}
+
 #[derive(Copy, Clone, strum::Display, Debug, Deserialize, Serialize, PartialEq)]
 pub enum AliceEndState {
     SafelyAborted,
@@ -66,27 +120,56 @@ impl From<&AliceState> for Alice {
                 state3: state3.as_ref().clone(),
                 bob_peer_id: *bob_peer_id,
             },
-            AliceState::XmrLocked { state3 } => Alice::XmrLocked(state3.as_ref().clone()),
+            AliceState::XmrLocked {
+                monero_wallet_restore_blockheight,
+                state3,
+            } => Alice::XmrLocked {
+                state3: state3.as_ref().clone(),
+                monero_wallet_restore_blockheight: *monero_wallet_restore_blockheight,
+            },
             AliceState::EncSigLearned {
+                monero_wallet_restore_blockheight,
                 state3,
                 encrypted_signature,
             } => Alice::EncSigLearned {
                 state3: state3.as_ref().clone(),
                 encrypted_signature: *encrypted_signature.clone(),
+                monero_wallet_restore_blockheight: *monero_wallet_restore_blockheight,
             },
             AliceState::BtcRedeemed => Alice::Done(AliceEndState::BtcRedeemed),
-            AliceState::BtcCancelled { state3, .. } => Alice::BtcCancelled(state3.as_ref().clone()),
-            AliceState::BtcRefunded { spend_key, state3 } => Alice::BtcRefunded {
+            AliceState::BtcCancelled {
+                monero_wallet_restore_blockheight,
+                state3,
+                ..
+            } => Alice::BtcCancelled {
+                state3: state3.as_ref().clone(),
+                monero_wallet_restore_blockheight: *monero_wallet_restore_blockheight,
+            },
+            AliceState::BtcRefunded {
+                monero_wallet_restore_blockheight,
+                spend_key,
+                state3,
+            } => Alice::BtcRefunded {
                 spend_key: *spend_key,
                 state3: state3.as_ref().clone(),
+                monero_wallet_restore_blockheight: *monero_wallet_restore_blockheight,
+            },
+            AliceState::BtcPunishable {
+                monero_wallet_restore_blockheight,
+                state3,
+                ..
+            } => Alice::BtcPunishable {
+                state3: state3.as_ref().clone(),
+                monero_wallet_restore_blockheight: *monero_wallet_restore_blockheight,
             },
-            AliceState::BtcPunishable { state3, .. } => {
-                Alice::BtcPunishable(state3.as_ref().clone())
-            }
             AliceState::XmrRefunded => Alice::Done(AliceEndState::XmrRefunded),
-            AliceState::CancelTimelockExpired { state3 } => {
-                Alice::CancelTimelockExpired(state3.as_ref().clone())
-            }
+            AliceState::CancelTimelockExpired {
+                monero_wallet_restore_blockheight,
+                state3,
+            } => Alice::CancelTimelockExpired {
+                state3: state3.as_ref().clone(),
+                monero_wallet_restore_blockheight: *monero_wallet_restore_blockheight,
+            },
             AliceState::BtcPunished => Alice::Done(AliceEndState::BtcPunished),
             AliceState::SafelyAborted => Alice::Done(AliceEndState::SafelyAborted),
         }
@@ -110,48 +193,72 @@ impl From<Alice> for AliceState {
                 bob_peer_id,
                 state3: Box::new(state3),
             },
-            Alice::XmrLocked(state3) => AliceState::XmrLocked {
+            Alice::XmrLocked {
+                state3,
+                monero_wallet_restore_blockheight,
+            } => AliceState::XmrLocked {
+                monero_wallet_restore_blockheight,
                 state3: Box::new(state3),
             },
             Alice::EncSigLearned {
                 state3: state,
                 encrypted_signature,
+                monero_wallet_restore_blockheight,
             } => AliceState::EncSigLearned {
+                monero_wallet_restore_blockheight,
                 state3: Box::new(state),
                 encrypted_signature: Box::new(encrypted_signature),
             },
-            Alice::CancelTimelockExpired(state3) => AliceState::CancelTimelockExpired {
+            Alice::CancelTimelockExpired {
+                state3,
+                monero_wallet_restore_blockheight,
+            } => AliceState::CancelTimelockExpired {
+                monero_wallet_restore_blockheight,
                 state3: Box::new(state3),
             },
-            Alice::BtcCancelled(state) => {
+            Alice::BtcCancelled {
+                state3: state,
+                monero_wallet_restore_blockheight,
+            } => {
                 let tx_cancel = TxCancel::new(
                     &state.tx_lock,
                     state.cancel_timelock,
                     state.a.public(),
                     state.B,
+                    state.tx_fee_rate,
                 );
 
                 AliceState::BtcCancelled {
+                    monero_wallet_restore_blockheight,
                     state3: Box::new(state),
                     tx_cancel: Box::new(tx_cancel),
                 }
             }
-            Alice::BtcPunishable(state3) => {
+            Alice::BtcPunishable {
+                state3,
+                monero_wallet_restore_blockheight,
+            } => {
                 let tx_cancel = TxCancel::new(
                     &state3.tx_lock,
                     state3.cancel_timelock,
                     state3.a.public(),
                     state3.B,
+                    state3.tx_fee_rate,
                 );
-                let tx_refund = TxRefund::new(&tx_cancel, &state3.refund_address);
+                let tx_refund =
+                    TxRefund::new(&tx_cancel, &state3.refund_address, state3.tx_fee_rate);
                 AliceState::BtcPunishable {
+                    monero_wallet_restore_blockheight,
                     tx_refund,
                     state3: Box::new(state3),
                 }
             }
             Alice::BtcRefunded {
-                state3, spend_key, ..
+                state3,
+                spend_key,
+                monero_wallet_restore_blockheight,
             } => AliceState::BtcRefunded {
+                monero_wallet_restore_blockheight,
                 spend_key,
                 state3: Box::new(state3),
             },
@@ -170,13 +277,173 @@ impl Display for Alice {
         match self {
             Alice::Started { .. } => write!(f, "Started"),
             Alice::BtcLocked { .. } => f.write_str("Bitcoin locked"),
-            Alice::XmrLocked(_) => f.write_str("Monero locked"),
-            Alice::CancelTimelockExpired(_) => f.write_str("Cancel timelock is expired"),
-            Alice::BtcCancelled(_) => f.write_str("Bitcoin cancel transaction published"),
-            Alice::BtcPunishable(_) => f.write_str("Bitcoin punishable"),
+            Alice::XmrLocked { .. } => f.write_str("Monero locked"),
+            Alice::CancelTimelockExpired { .. } => f.write_str("Cancel timelock is expired"),
+            Alice::BtcCancelled { .. } => f.write_str("Bitcoin cancel transaction published"),
+            Alice::BtcPunishable { .. } => f.write_str("Bitcoin punishable"),
             Alice::BtcRefunded { .. } => f.write_str("Monero refundable"),
             Alice::Done(end_state) => write!(f, "Done: {}", end_state),
             Alice::EncSigLearned { .. } => f.write_str("Encrypted signature learned"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitcoin::{
+            self, BuildTxLockPsbt, CancelTimelock, CoinControl, FeeRate, GetNetwork,
+            PunishTimelock, TxLock,
+        },
+        database::{serialize, Database, Swap},
+    };
+    use ::bitcoin::{util::psbt::PartiallySignedTransaction, TxIn, TxOut};
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use rand::rngs::OsRng;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    /// Funds the lock transaction from a made up input.
+    struct StubWallet;
+
+    #[async_trait]
+    impl BuildTxLockPsbt for StubWallet {
+        async fn build_tx_lock_psbt(
+            &self,
+            output_address: bitcoin::Address,
+            output_amount: bitcoin::Amount,
+            _: &CoinControl,
+        ) -> Result<PartiallySignedTransaction> {
+            let tx = bitcoin::Transaction {
+                version: 2,
+                lock_time: 0,
+                input: vec![TxIn::default()],
+                output: vec![TxOut {
+                    value: output_amount.as_sat(),
+                    script_pubkey: output_address.script_pubkey(),
+                }],
+            };
+
+            Ok(PartiallySignedTransaction::from_unsigned_tx(tx)?)
+        }
+    }
+
+    #[async_trait]
+    impl GetNetwork for StubWallet {
+        async fn get_network(&self) -> bitcoin::Network {
+            bitcoin::Network::Regtest
+        }
+    }
+
+    async fn state3() -> alice::State3 {
+        let a = bitcoin::SecretKey::new_random(&mut OsRng);
+        let b = bitcoin::SecretKey::new_random(&mut OsRng);
+        let s_a = cross_curve_dleq::Scalar::random(&mut OsRng);
+        let s_b = cross_curve_dleq::Scalar::random(&mut OsRng);
+        let address = bitcoin::Address::from_str("bcrt1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5phstwt")
+            .expect("valid address");
+        let cancel_timelock = CancelTimelock::new(12);
+        let tx_fee_rate = FeeRate::from_sat_per_vb(10);
+
+        let tx_lock = TxLock::new(
+            &StubWallet,
+            bitcoin::Amount::from_sat(1_000_000),
+            a.public(),
+            b.public(),
+            &CoinControl::default(),
+        )
+        .await
+        .unwrap();
+        let tx_cancel = TxCancel::new(
+            &tx_lock,
+            cancel_timelock,
+            a.public(),
+            b.public(),
+            tx_fee_rate,
+        );
+        let signature = b.sign(tx_cancel.digest());
+
+        alice::State3 {
+            a,
+            B: b.public(),
+            s_a,
+            S_b_monero: monero::PublicKey::from_private_key(&monero::PrivateKey {
+                scalar: s_b.into_ed25519(),
+            }),
+            S_b_bitcoin: s_b.into_secp256k1().into(),
+            v: monero::PrivateViewKey::new_random(&mut OsRng),
+            btc: bitcoin::Amount::from_sat(1_000_000),
+            xmr: monero::Amount::from_piconero(1_000_000_000_000),
+            cancel_timelock,
+            punish_timelock: PunishTimelock::new(6),
+            refund_address: address.clone(),
+            redeem_address: address.clone(),
+            punish_address: address,
+            tx_lock,
+            tx_fee_rate,
+            tx_punish_sig_bob: signature.clone(),
+            tx_cancel_sig_bob: signature,
+        }
+    }
+
+    /// The layout of the Alice states before the height of the Monero lock
+    /// transaction was recorded.
+    #[derive(Serialize)]
+    enum OldAlice {
+        XmrLocked(alice::State3),
+        BtcRefunded {
+            state3: alice::State3,
+            #[serde(with = "monero_private_key")]
+            spend_key: monero::PrivateKey,
+        },
+    }
+
+    #[derive(Serialize)]
+    enum OldSwap {
+        Alice(OldAlice),
+    }
+
+    #[tokio::test]
+    async fn reads_states_written_before_restore_height_was_recorded() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+        let state3 = state3().await;
+        let spend_key = monero::PrivateKey::from_scalar(monero::Scalar::random(&mut OsRng));
+
+        let xmr_locked = Uuid::new_v4();
+        let btc_refunded = Uuid::new_v4();
+        for (swap_id, old_state) in vec![
+            (xmr_locked, OldAlice::XmrLocked(state3.clone())),
+            (btc_refunded, OldAlice::BtcRefunded {
+                state3: state3.clone(),
+                spend_key,
+            }),
+        ] {
+            db.swaps
+                .insert(
+                    serialize(&swap_id).unwrap(),
+                    serialize(&OldSwap::Alice(old_state)).unwrap(),
+                )
+                .unwrap();
+        }
+
+        assert_eq!(
+            db.get_state(xmr_locked).unwrap(),
+            Swap::Alice(Alice::XmrLocked {
+                state3: state3.clone(),
+                monero_wallet_restore_blockheight: None,
+            })
+        );
+        assert_eq!(
+            db.get_state(btc_refunded).unwrap(),
+            Swap::Alice(Alice::BtcRefunded {
+                state3,
+                spend_key,
+                monero_wallet_restore_blockheight: None,
+            })
+        );
+        assert_eq!(db.all().unwrap().len(), 2);
+    }
+}
@@ -1,9 +1,8 @@
 use crate::{
-    monero::TransferProof,
+    monero::{BlockHeight, TransferProof, TxHash},
     protocol::{bob, bob::BobState},
 };
 use ::bitcoin::hashes::core::fmt::Display;
-use monero_harness::rpc::wallet::BlockHeight;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
@@ -11,6 +10,8 @@ pub enum Bob {
     Started {
         #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
         btc_amount: bitcoin::Amount,
+        #[serde(default)]
+        coin_control: crate::bitcoin::CoinControl,
     },
     ExecutionSetupDone {
         state2: bob::State2,
@@ -38,15 +39,27 @@ pub enum Bob {
 #[derive(Clone, strum::Display, Debug, Deserialize, Serialize, PartialEq)]
 pub enum BobEndState {
     SafelyAborted,
-    XmrRedeemed { tx_lock_id: bitcoin::Txid },
+    XmrRedeemed {
+        tx_lock_id: bitcoin::Txid,
+        #[serde(default)]
+        sweep_tx_hashes: Vec<TxHash>,
+    },
     BtcRefunded(Box<bob::State4>),
-    BtcPunished { tx_lock_id: bitcoin::Txid },
+    BtcPunished {
+        tx_lock_id: bitcoin::Txid,
+    },
 }
 
 impl From<BobState> for Bob {
     fn from(bob_state: BobState) -> Self {
         match bob_state {
-            BobState::Started { btc_amount } => Bob::Started { btc_amount },
+            BobState::Started {
+                btc_amount,
+                coin_control,
+            } => Bob::Started {
+                btc_amount,
+                coin_control,
+            },
             BobState::ExecutionSetupDone(state2) => Bob::ExecutionSetupDone { state2 },
             BobState::BtcLocked(state3) => Bob::BtcLocked { state3 },
             BobState::XmrLockProofReceived {
@@ -64,9 +77,13 @@ impl From<BobState> for Bob {
             BobState::CancelTimelockExpired(state4) => Bob::CancelTimelockExpired(state4),
             BobState::BtcCancelled(state4) => Bob::BtcCancelled(state4),
             BobState::BtcRefunded(state4) => Bob::Done(BobEndState::BtcRefunded(Box::new(state4))),
-            BobState::XmrRedeemed { tx_lock_id } => {
-                Bob::Done(BobEndState::XmrRedeemed { tx_lock_id })
-            }
+            BobState::XmrRedeemed {
+                tx_lock_id,
+                sweep_tx_hashes,
+            } => Bob::Done(BobEndState::XmrRedeemed {
+                tx_lock_id,
+                sweep_tx_hashes,
+            }),
             BobState::BtcPunished { tx_lock_id } => {
                 Bob::Done(BobEndState::BtcPunished { tx_lock_id })
             }
@@ -78,7 +95,13 @@ impl From<BobState> for Bob {
 impl From<Bob> for BobState {
     fn from(db_state: Bob) -> Self {
         match db_state {
-            Bob::Started { btc_amount } => BobState::Started { btc_amount },
+            Bob::Started {
+                btc_amount,
+                coin_control,
+            } => BobState::Started {
+                btc_amount,
+                coin_control,
+            },
             Bob::ExecutionSetupDone { state2 } => BobState::ExecutionSetupDone(state2),
             Bob::BtcLocked { state3 } => BobState::BtcLocked(state3),
             Bob::XmrLockProofReceived {
@@ -97,7 +120,13 @@ impl From<Bob> for BobState {
             Bob::BtcCancelled(state4) => BobState::BtcCancelled(state4),
             Bob::Done(end_state) => match end_state {
                 BobEndState::SafelyAborted => BobState::SafelyAborted,
-                BobEndState::XmrRedeemed { tx_lock_id } => BobState::XmrRedeemed { tx_lock_id },
+                BobEndState::XmrRedeemed {
+                    tx_lock_id,
+                    sweep_tx_hashes,
+                } => BobState::XmrRedeemed {
+                    tx_lock_id,
+                    sweep_tx_hashes,
+                },
                 BobEndState::BtcRefunded(state4) => BobState::BtcRefunded(*state4),
                 BobEndState::BtcPunished { tx_lock_id } => BobState::BtcPunished { tx_lock_id },
             },
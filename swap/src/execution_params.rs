@@ -1,6 +1,11 @@
-use crate::bitcoin::{CancelTimelock, PunishTimelock};
+use crate::{
+    bitcoin,
+    bitcoin::{CancelTimelock, PunishTimelock},
+    monero,
+};
 use conquer_once::Lazy;
-use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
 
 #[derive(Debug, Copy, Clone)]
 pub struct ExecutionParams {
@@ -25,6 +30,62 @@ pub struct Testnet;
 #[derive(Clone, Copy)]
 pub struct Regtest;
 
+/// The pair of Bitcoin and Monero networks swaps are executed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Chain {
+    /// Bitcoin mainnet and Monero mainnet.
+    Mainnet,
+    /// Bitcoin testnet and Monero stagenet.
+    Testnet,
+    /// Bitcoin regtest and a local Monero chain using mainnet addresses.
+    Regtest,
+}
+
+impl Chain {
+    pub fn bitcoin_network(self) -> bitcoin::Network {
+        match self {
+            Chain::Mainnet => bitcoin::Network::Bitcoin,
+            Chain::Testnet => bitcoin::Network::Testnet,
+            Chain::Regtest => bitcoin::Network::Regtest,
+        }
+    }
+
+    pub fn monero_network(self) -> monero::Network {
+        match self {
+            Chain::Mainnet => monero::Network::Mainnet,
+            Chain::Testnet => monero::Network::Stagenet,
+            Chain::Regtest => monero::Network::Mainnet,
+        }
+    }
+
+    pub fn execution_params(self) -> ExecutionParams {
+        match self {
+            Chain::Mainnet => Mainnet::get_execution_params(),
+            Chain::Testnet => Testnet::get_execution_params(),
+            Chain::Regtest => Regtest::get_execution_params(),
+        }
+    }
+}
+
+/// Swaps used to be executed on testnet only, hence configurations that do
+/// not specify a chain default to it.
+impl Default for Chain {
+    fn default() -> Self {
+        Chain::Testnet
+    }
+}
+
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chain::Mainnet => write!(f, "mainnet"),
+            Chain::Testnet => write!(f, "testnet"),
+            Chain::Regtest => write!(f, "regtest"),
+        }
+    }
+}
+
 impl GetExecutionParams for Mainnet {
     fn get_execution_params() -> ExecutionParams {
         ExecutionParams {
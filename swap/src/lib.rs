@@ -23,6 +23,7 @@ pub mod execution_params;
 pub mod fs;
 pub mod monero;
 pub mod nectar;
+pub mod price;
 pub mod protocol;
 pub mod seed;
 pub mod trace;
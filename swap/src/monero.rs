@@ -1,8 +1,10 @@
 pub mod wallet;
+pub mod wallet_rpc;
 
-pub use ::monero::{Network, PrivateKey, PublicKey};
+pub use ::monero::{Address, Network, PrivateKey, PublicKey};
 pub use curve25519_dalek::scalar::Scalar;
-pub use wallet::Wallet;
+pub use wallet::{swap_wallet_name, Wallet};
+pub use wallet_rpc::WalletRpc;
 
 use crate::bitcoin;
 use ::bitcoin::hashes::core::fmt::Formatter;
@@ -20,6 +22,7 @@ use std::{
     ops::{Add, Mul, Sub},
     str::FromStr,
 };
+use uuid::Uuid;
 
 pub const PICONERO_OFFSET: u64 = 1_000_000_000_000;
 
@@ -149,6 +152,27 @@ impl Display for Amount {
     }
 }
 
+/// A height of the Monero blockchain, used to tell a wallet where to start
+/// scanning for the outputs of a swap.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BlockHeight {
+    pub height: u32,
+}
+
+impl From<monero_harness::rpc::wallet::BlockHeight> for BlockHeight {
+    fn from(from: monero_harness::rpc::wallet::BlockHeight) -> Self {
+        Self {
+            height: from.height,
+        }
+    }
+}
+
+impl Display for BlockHeight {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.height)
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct TransferProof {
     tx_hash: TxHash,
@@ -188,6 +212,7 @@ pub trait Transfer {
     ) -> anyhow::Result<(TransferProof, Amount)>;
 }
 
+/// Both methods return the height of the block that includes the transfer.
 #[async_trait]
 pub trait WatchForTransfer {
     async fn watch_for_transfer(
@@ -197,7 +222,20 @@ pub trait WatchForTransfer {
         transfer_proof: TransferProof,
         amount: Amount,
         expected_confirmations: u32,
-    ) -> Result<(), InsufficientFunds>;
+    ) -> Result<BlockHeight, InsufficientFunds>;
+
+    /// Waits for a transfer to the address of `public_spend_key` and
+    /// `private_view_key` by scanning the blockchain from `restore_height`
+    /// with the view key, so no transfer proof is needed.
+    async fn watch_for_transfer_with_view_key(
+        &self,
+        swap_id: Uuid,
+        public_spend_key: PublicKey,
+        private_view_key: PrivateViewKey,
+        restore_height: BlockHeight,
+        amount: Amount,
+        expected_confirmations: u32,
+    ) -> anyhow::Result<Result<(TxHash, BlockHeight), InsufficientFunds>>;
 }
 
 #[derive(Debug, Clone, Copy, thiserror::Error)]
@@ -207,10 +245,37 @@ pub struct InsufficientFunds {
     pub actual: Amount,
 }
 
+#[async_trait]
+pub trait CheckLockTransaction {
+    /// Checks properties of the lock transaction that `WatchForTransfer` does
+    /// not cover, by fetching the whole transaction from monerod.
+    async fn check_lock_transaction(
+        &self,
+        tx_hash: TxHash,
+        public_spend_key: PublicKey,
+        private_view_key: PrivateViewKey,
+        amount: Amount,
+    ) -> anyhow::Result<Result<(), InvalidLockTransaction>>;
+}
+
+/// Reasons for Bob not to accept the Monero lock transaction of Alice.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum InvalidLockTransaction {
+    #[error(transparent)]
+    InsufficientFunds(#[from] InsufficientFunds),
+    #[error("lock transaction {tx_hash} cannot be spent before {unlock_time}")]
+    UnlockTime { tx_hash: String, unlock_time: u64 },
+    #[error("lock transaction {tx_hash} does not pay to the lock address")]
+    NotToLockAddress { tx_hash: String },
+}
+
 #[async_trait]
 pub trait CreateWalletForOutput {
-    async fn create_and_load_wallet_for_output(
+    /// Creates the wallet of the swap `swap_id` that controls the output, or
+    /// keeps it if it already exists.
+    async fn create_wallet_for_output(
         &self,
+        swap_id: Uuid,
         private_spend_key: PrivateKey,
         private_view_key: PrivateViewKey,
         restore_height: Option<u32>,
@@ -218,13 +283,25 @@ pub trait CreateWalletForOutput {
 }
 
 #[async_trait]
-pub trait OpenWallet {
-    async fn open_wallet(&self, file_name: &str) -> anyhow::Result<()>;
+pub trait SweepAll {
+    /// Waits until the whole balance of the wallet of the swap `swap_id` is
+    /// unlocked and sends it to `address`.
+    async fn sweep_all(&self, swap_id: Uuid, address: Address) -> anyhow::Result<Vec<TxHash>>;
 }
 
 #[async_trait]
-pub trait CreateWallet {
-    async fn create_wallet(&self, file_name: &str) -> anyhow::Result<()>;
+pub trait SweepToMainWallet {
+    /// Sweeps the output controlled by the given keys into our main wallet and
+    /// waits until the sweep has `expected_confirmations`. Without a
+    /// `restore_height` the whole chain is scanned for the output.
+    async fn sweep_to_main_wallet(
+        &self,
+        swap_id: Uuid,
+        private_spend_key: PrivateKey,
+        private_view_key: PrivateViewKey,
+        restore_height: Option<BlockHeight>,
+        expected_confirmations: u32,
+    ) -> anyhow::Result<Vec<TxHash>>;
 }
 
 #[derive(thiserror::Error, Debug, Clone, PartialEq)]
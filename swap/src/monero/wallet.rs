@@ -1,41 +1,390 @@
 use crate::monero::{
-    Amount, CreateWallet, CreateWalletForOutput, InsufficientFunds, OpenWallet, PrivateViewKey,
-    PublicViewKey, Transfer, TransferProof, TxHash, WatchForTransfer,
+    Amount, BlockHeight, CheckLockTransaction, CreateWalletForOutput, InsufficientFunds,
+    InvalidLockTransaction, PrivateViewKey, PublicViewKey, SweepAll, SweepToMainWallet, Transfer,
+    TransferProof, TxHash, WatchForTransfer,
 };
-use ::monero::{Address, Network, PrivateKey, PublicKey};
-use anyhow::Result;
+use ::bitcoin::hashes::hex::FromHex;
+use ::monero::{util::key::ViewPair, Address, Network, PrivateKey, PublicKey, Transaction};
+use anyhow::{bail, Result};
 use async_trait::async_trait;
 use backoff::{backoff::Constant as ConstantBackoff, tokio::retry};
 use bitcoin::hashes::core::sync::atomic::AtomicU32;
-use monero_harness::rpc::wallet;
+use monero_harness::rpc::{monerod, wallet};
 use std::{
+    future::Future,
     str::FromStr,
     sync::{atomic::Ordering, Arc},
     time::Duration,
 };
-use tracing::info;
+use tokio::{
+    sync::{Mutex, MutexGuard},
+    time::sleep,
+};
+use tracing::{info, warn};
 use url::Url;
+use uuid::Uuid;
+
+/// How often we check whether received outputs have unlocked, which takes 10
+/// blocks.
+const UNLOCK_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often we scan for the lock transaction with the view key. Every scan
+/// switches away from the main wallet and opens it again afterwards, which
+/// blocks all other swaps in the meantime. Monero blocks are two minutes
+/// apart, so scanning a few times per block is enough, and the transfer proof
+/// usually finds the lock sooner anyway.
+const VIEW_KEY_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The wallet in which we control the Monero of the swap `swap_id`.
+pub fn swap_wallet_name(swap_id: Uuid) -> String {
+    format!("swap-{}", swap_id)
+}
+
+/// The wallet with which Bob looks for the Monero lock of the swap `swap_id`.
+fn view_only_wallet_name(swap_id: Uuid) -> String {
+    format!("swap-{}-view-only", swap_id)
+}
+
+/// The fees of the sent transfers, including those not in a block yet.
+fn total_fee(transfers: wallet::GetTransfers) -> Amount {
+    let fee = transfers
+        .outgoing
+        .iter()
+        .chain(transfers.pending.iter())
+        .map(|transfer| transfer.fee)
+        .sum();
+
+    Amount::from_piconero(fee)
+}
 
 #[derive(Debug)]
 pub struct Wallet {
-    pub inner: wallet::Client,
+    inner: wallet::Client,
     pub network: Network,
+    /// Needed to inspect transactions that are not our own.
+    pub daemon: Option<monerod::Client>,
+    /// The file name of the wallet that holds our funds.
+    main_wallet: String,
+    /// monero-wallet-rpc only has one wallet open at a time, so swaps that run
+    /// concurrently take turns through this lock.
+    session: Mutex<Session>,
+}
+
+/// What we know about the wallet that monero-wallet-rpc has open.
+#[derive(Debug, Default)]
+struct Session {
+    main_wallet_open: bool,
 }
 
 impl Wallet {
-    pub fn new(url: Url, network: Network) -> Self {
+    pub fn new(url: Url, network: Network, main_wallet: &str) -> Self {
         Self {
             inner: wallet::Client::new(url),
             network,
+            daemon: None,
+            main_wallet: main_wallet.to_owned(),
+            session: Mutex::new(Session::default()),
+        }
+    }
+
+    /// Uses the monerod at `url` to check transactions before accepting them.
+    pub fn with_daemon(self, url: Url) -> Self {
+        Self {
+            daemon: Some(monerod::Client::new(url)),
+            ..self
+        }
+    }
+
+    /// Authenticates with `login` to a monero-wallet-rpc that requires one.
+    pub fn with_login(self, login: wallet::Login) -> Self {
+        Self {
+            inner: self.inner.with_login(login),
+            ..self
+        }
+    }
+
+    /// Opens the main wallet, and creates it first if it does not exist yet.
+    pub async fn open_or_create_main_wallet(&self) -> Result<()> {
+        let mut session = self.session.lock().await;
+
+        if self.inner.open_wallet(&self.main_wallet).await.is_err() {
+            self.inner.create_wallet(&self.main_wallet).await?;
+            info!("Created Monero wallet {}", self.main_wallet);
+        } else {
+            info!("Opened Monero wallet {}", self.main_wallet);
+        }
+        session.main_wallet_open = true;
+
+        Ok(())
+    }
+
+    /// Waits until no other wallet is in use and opens the main wallet if it
+    /// is not open.
+    async fn main_wallet(&self) -> Result<MutexGuard<'_, Session>> {
+        let mut session = self.session.lock().await;
+
+        if !session.main_wallet_open {
+            self.inner.open_wallet(&self.main_wallet).await?;
+            session.main_wallet_open = true;
+        }
+
+        Ok(session)
+    }
+
+    /// Runs `f`, which may open any wallet, while no one else uses
+    /// monero-wallet-rpc. The main wallet is opened again afterwards, even if
+    /// `f` failed.
+    async fn switch_wallet<F, Fut, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(wallet::Client) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut session = self.session.lock().await;
+        session.main_wallet_open = false;
+
+        let result = f(self.inner.clone()).await;
+
+        match self.inner.open_wallet(&self.main_wallet).await {
+            Ok(()) => session.main_wallet_open = true,
+            // The next user of the main wallet tries again
+            Err(e) => warn!(
+                "Failed to open Monero wallet {} again: {:#}",
+                self.main_wallet, e
+            ),
         }
+
+        result
+    }
+
+    /// The transactions that emptied the wallet `file_name`, if it is empty.
+    async fn previous_sweep(&self, file_name: &str) -> Result<Vec<TxHash>> {
+        self.switch_wallet(|wallet| async move {
+            wallet.open_wallet(file_name).await?;
+            let _ = wallet.refresh().await?;
+            if wallet.get_balance(0).await? != 0 {
+                return Ok(vec![]);
+            }
+
+            let transfers = wallet.get_outgoing_transfers().await?;
+            let tx_hashes = transfers
+                .outgoing
+                .into_iter()
+                .chain(transfers.pending)
+                .map(|transfer| TxHash(transfer.txid))
+                .collect::<Vec<_>>();
+
+            Ok(tx_hashes)
+        })
+        .await
+    }
+
+    /// Waits until the whole balance of the wallet `file_name` is unlocked
+    /// and sends it to `address`. If the wallet was already swept before we
+    /// were interrupted, the transactions of that sweep are returned instead.
+    async fn sweep_wallet(&self, file_name: &str, address: Address) -> Result<Vec<TxHash>> {
+        let previous_sweep = self.previous_sweep(file_name).await?;
+        if !previous_sweep.is_empty() {
+            info!("Monero was already swept to {}", address);
+            return Ok(previous_sweep);
+        }
+
+        let mut logged = false;
+        loop {
+            let (balance, unlocked_balance) = self
+                .switch_wallet(|wallet| async move {
+                    wallet.open_wallet(file_name).await?;
+                    let _ = wallet.refresh().await?;
+                    let balance = wallet.get_balance(0).await?;
+                    let unlocked_balance = wallet.get_unlocked_balance(0).await?;
+
+                    Ok((
+                        Amount::from_piconero(balance),
+                        Amount::from_piconero(unlocked_balance),
+                    ))
+                })
+                .await?;
+
+            if balance == Amount::ZERO {
+                bail!("There is nothing to sweep to {}", address)
+            }
+            if unlocked_balance == balance {
+                break;
+            }
+
+            if !logged {
+                info!("Waiting for {} to unlock before sweeping it", balance);
+                logged = true;
+            }
+            sleep(UNLOCK_POLL_INTERVAL).await;
+        }
+
+        let sweep = self
+            .switch_wallet(|wallet| async move {
+                wallet.open_wallet(file_name).await?;
+                wallet.sweep_all(0, &address.to_string()).await
+            })
+            .await?;
+        let tx_hashes = sweep.tx_hash_list.into_iter().map(TxHash).collect();
+
+        Ok(tx_hashes)
+    }
+
+    /// Ensures the wallet loaded in monero-wallet-rpc is on the network we
+    /// are configured for, by checking the network of its primary address.
+    pub async fn ensure_network(&self) -> Result<()> {
+        let _session = self.main_wallet().await?;
+        let address = self.inner.get_address(0).await?;
+        let address = Address::from_str(&address.address)?;
+
+        if address.network != self.network {
+            bail!(
+                "monero-wallet-rpc is on Monero {:?} but {:?} was expected",
+                address.network,
+                self.network
+            )
+        }
+
+        Ok(())
     }
 
     /// Get the balance of the primary account.
     pub async fn get_balance(&self) -> Result<Amount> {
+        let _session = self.main_wallet().await?;
         let amount = self.inner.get_balance(0).await?;
 
         Ok(Amount::from_piconero(amount))
     }
+
+    /// Get the unlocked balance of the primary account.
+    pub async fn get_unlocked_balance(&self) -> Result<Amount> {
+        let _session = self.main_wallet().await?;
+        let amount = self.inner.get_unlocked_balance(0).await?;
+
+        Ok(Amount::from_piconero(amount))
+    }
+
+    /// Get the balance of the wallet of the swap `swap_id`.
+    pub async fn get_swap_wallet_balance(&self, swap_id: Uuid) -> Result<Amount> {
+        let file_name = swap_wallet_name(swap_id);
+
+        self.switch_wallet(|wallet| async move {
+            wallet.open_wallet(&file_name).await?;
+            let _ = wallet.refresh().await?;
+            let amount = wallet.get_balance(0).await?;
+
+            Ok(Amount::from_piconero(amount))
+        })
+        .await
+    }
+
+    /// Get the fees the main wallet and the wallet of the swap `swap_id` paid
+    /// for the transfers they sent.
+    pub async fn get_fees_paid(&self, swap_id: Uuid) -> Result<Amount> {
+        let main_wallet_fees = {
+            let _session = self.main_wallet().await?;
+            total_fee(self.inner.get_outgoing_transfers().await?)
+        };
+
+        let file_name = swap_wallet_name(swap_id);
+        let swap_wallet_fees = self
+            .switch_wallet(|wallet| async move {
+                wallet.open_wallet(&file_name).await?;
+                let _ = wallet.refresh().await?;
+
+                Ok(total_fee(wallet.get_outgoing_transfers().await?))
+            })
+            .await?;
+
+        Ok(main_wallet_fees + swap_wallet_fees)
+    }
+
+    /// Get the height of the main wallet, which might be behind monerod.
+    pub async fn block_height(&self) -> Result<BlockHeight> {
+        let _session = self.main_wallet().await?;
+        let height = self.inner.block_height().await?;
+
+        Ok(height.into())
+    }
+
+    /// Writes the main wallet to disk, so nothing is lost if
+    /// monero-wallet-rpc does not exit cleanly.
+    pub async fn store(&self) -> Result<()> {
+        let _session = self.main_wallet().await?;
+        self.inner.store().await
+    }
+
+    /// Waits until our transfer `tx_hash` is mined and returns the height of
+    /// its block.
+    pub async fn wait_for_transfer_height(&self, tx_hash: &TxHash) -> Result<BlockHeight> {
+        retry(ConstantBackoff::new(Duration::from_secs(1)), || async {
+            let _session = self
+                .main_wallet()
+                .await
+                .map_err(backoff::Error::Transient)?;
+            let _ = self
+                .inner
+                .refresh()
+                .await
+                .map_err(backoff::Error::Transient)?;
+            let transfers = self
+                .inner
+                .get_outgoing_transfers()
+                .await
+                .map_err(backoff::Error::Transient)?;
+
+            transfers
+                .outgoing
+                .into_iter()
+                .find(|transfer| transfer.txid == tx_hash.0)
+                .map(|transfer| BlockHeight {
+                    height: transfer.height,
+                })
+                .ok_or_else(|| {
+                    backoff::Error::Transient(anyhow::anyhow!(
+                        "Transaction {} is not mined yet",
+                        tx_hash.0
+                    ))
+                })
+        })
+        .await
+    }
+
+    /// Looks for a transfer from our main wallet to `address`. Returns the
+    /// height it was mined at, or our current height while it is still
+    /// pending, and `None` if we never sent anything to `address`.
+    pub async fn find_transfer_to(&self, address: Address) -> Result<Option<BlockHeight>> {
+        let address = address.to_string();
+        let pays_address = |transfer: &wallet::TransferEntry| {
+            transfer
+                .destinations
+                .iter()
+                .any(|destination| destination.address == address)
+        };
+
+        let _session = self.main_wallet().await?;
+        let _ = self.inner.refresh().await?;
+        let transfers = self.inner.get_outgoing_transfers().await?;
+
+        if let Some(transfer) = transfers.outgoing.iter().find(|t| pays_address(t)) {
+            return Ok(Some(BlockHeight {
+                height: transfer.height,
+            }));
+        }
+        if transfers.pending.iter().any(|t| pays_address(t)) {
+            let height = self.inner.block_height().await?;
+            return Ok(Some(height.into()));
+        }
+
+        Ok(None)
+    }
+
+    /// Scans the blockchain for transfers of the main wallet.
+    pub async fn refresh(&self) -> Result<()> {
+        let _session = self.main_wallet().await?;
+        let _ = self.inner.refresh().await?;
+
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -49,6 +398,7 @@ impl Transfer for Wallet {
         let destination_address =
             Address::standard(self.network, public_spend_key, public_view_key.into());
 
+        let _session = self.main_wallet().await?;
         let res = self
             .inner
             .transfer(0, amount.as_piconero(), &destination_address.to_string())
@@ -69,8 +419,9 @@ impl Transfer for Wallet {
 
 #[async_trait]
 impl CreateWalletForOutput for Wallet {
-    async fn create_and_load_wallet_for_output(
+    async fn create_wallet_for_output(
         &self,
+        swap_id: Uuid,
         private_spend_key: PrivateKey,
         private_view_key: PrivateViewKey,
         restore_height: Option<u32>,
@@ -79,34 +430,205 @@ impl CreateWalletForOutput for Wallet {
         let public_view_key = PublicKey::from_private_key(&private_view_key.into());
 
         let address = Address::standard(self.network, public_spend_key, public_view_key);
+        let file_name = swap_wallet_name(swap_id);
 
-        let _ = self
-            .inner
-            .generate_from_keys(
-                &address.to_string(),
-                &private_spend_key.to_string(),
-                &PrivateKey::from(private_view_key).to_string(),
-                restore_height,
-            )
-            .await?;
+        self.switch_wallet(|wallet| async move {
+            // The wallet still exists if we were interrupted before
+            if wallet.open_wallet(&file_name).await.is_err() {
+                let _ = wallet
+                    .generate_from_keys(
+                        &file_name,
+                        &address.to_string(),
+                        &private_spend_key.to_string(),
+                        &PrivateKey::from(private_view_key).to_string(),
+                        restore_height,
+                    )
+                    .await?;
+            }
 
-        Ok(())
+            Ok(())
+        })
+        .await
     }
 }
 
 #[async_trait]
-impl OpenWallet for Wallet {
-    async fn open_wallet(&self, file_name: &str) -> Result<()> {
-        self.inner.open_wallet(file_name).await?;
+impl CheckLockTransaction for Wallet {
+    async fn check_lock_transaction(
+        &self,
+        tx_hash: TxHash,
+        public_spend_key: PublicKey,
+        private_view_key: PrivateViewKey,
+        expected_amount: Amount,
+    ) -> Result<Result<(), InvalidLockTransaction>> {
+        let daemon = match &self.daemon {
+            Some(daemon) => daemon,
+            None => {
+                warn!(
+                    "No monerod configured, the unlock time and outputs of lock transaction {} \
+                     are NOT checked, only its transfer proof",
+                    tx_hash.0
+                );
+                return Ok(Ok(()));
+            }
+        };
+
+        let entry = daemon.get_transaction(&tx_hash.0).await?;
+        let tx =
+            ::monero::consensus::deserialize::<Transaction>(&Vec::<u8>::from_hex(&entry.as_hex)?)?;
+
+        let view_pair = ViewPair {
+            view: private_view_key.into(),
+            spend: public_spend_key,
+        };
+        // Only the primary address (major and minor index 0) is the lock address
+        let amounts = tx
+            .check_outputs(&view_pair, 0..1, 0..1)?
+            .iter()
+            .map(|output| output.amount())
+            .collect();
+
+        let lock_transaction = LockTransaction {
+            tx_hash,
+            unlock_time: tx.prefix.unlock_time.0,
+            amounts,
+        };
+
+        Ok(lock_transaction.check(expected_amount))
+    }
+}
+
+/// What we see of a lock transaction with the shared view key.
+#[derive(Debug)]
+struct LockTransaction {
+    tx_hash: TxHash,
+    unlock_time: u64,
+    /// The amounts of the outputs to the lock address, `None` for an amount
+    /// that cannot be decrypted.
+    amounts: Vec<Option<u64>>,
+}
+
+impl LockTransaction {
+    fn check(self, expected_amount: Amount) -> Result<(), InvalidLockTransaction> {
+        // Monero outputs can be made unspendable until a block height or time far in
+        // the future, which would lock up the funds we are about to redeem
+        if self.unlock_time != 0 {
+            return Err(InvalidLockTransaction::UnlockTime {
+                tx_hash: self.tx_hash.0,
+                unlock_time: self.unlock_time,
+            });
+        }
+
+        if self.amounts.is_empty() {
+            return Err(InvalidLockTransaction::NotToLockAddress {
+                tx_hash: self.tx_hash.0,
+            });
+        }
+
+        let actual = self
+            .amounts
+            .iter()
+            .fold(0u64, |sum, amount| sum.saturating_add(amount.unwrap_or(0)));
+        if actual != expected_amount.as_piconero() {
+            return Err(InsufficientFunds {
+                expected: expected_amount,
+                actual: Amount::from_piconero(actual),
+            }
+            .into());
+        }
+
         Ok(())
     }
 }
 
 #[async_trait]
-impl CreateWallet for Wallet {
-    async fn create_wallet(&self, file_name: &str) -> Result<()> {
-        self.inner.create_wallet(file_name).await?;
-        Ok(())
+impl SweepAll for Wallet {
+    async fn sweep_all(&self, swap_id: Uuid, address: Address) -> Result<Vec<TxHash>> {
+        self.sweep_wallet(&swap_wallet_name(swap_id), address).await
+    }
+}
+
+#[async_trait]
+impl SweepToMainWallet for Wallet {
+    async fn sweep_to_main_wallet(
+        &self,
+        swap_id: Uuid,
+        private_spend_key: PrivateKey,
+        private_view_key: PrivateViewKey,
+        restore_height: Option<BlockHeight>,
+        expected_confirmations: u32,
+    ) -> Result<Vec<TxHash>> {
+        let main_address = {
+            let _session = self.main_wallet().await?;
+            self.inner.get_address(0).await?
+        };
+        let main_address = Address::from_str(&main_address.address)?;
+
+        self.create_wallet_for_output(
+            swap_id,
+            private_spend_key,
+            private_view_key,
+            restore_height.map(|restore_height| restore_height.height),
+        )
+        .await?;
+
+        let file_name = swap_wallet_name(swap_id);
+        let tx_hashes = self.sweep_wallet(&file_name, main_address).await?;
+
+        let confirmations = Arc::new(AtomicU32::new(0u32));
+
+        retry(ConstantBackoff::new(Duration::from_secs(1)), || async {
+            let _session = self
+                .main_wallet()
+                .await
+                .map_err(backoff::Error::Transient)?;
+            let _ = self
+                .inner
+                .refresh()
+                .await
+                .map_err(backoff::Error::Transient)?;
+            let transfers = self
+                .inner
+                .get_incoming_transfers()
+                .await
+                .map_err(backoff::Error::Transient)?;
+            let transfers = transfers
+                .incoming
+                .into_iter()
+                .chain(transfers.pool)
+                .collect::<Vec<_>>();
+
+            // The sweep is only as final as its least confirmed transaction
+            let least_confirmations = tx_hashes
+                .iter()
+                .map(|tx_hash| {
+                    transfers
+                        .iter()
+                        .find(|transfer| transfer.txid == tx_hash.0)
+                        .map_or(0, |transfer| transfer.confirmations)
+                })
+                .min()
+                .unwrap_or(0);
+
+            if least_confirmations > confirmations.load(Ordering::SeqCst) {
+                confirmations.store(least_confirmations, Ordering::SeqCst);
+                info!(
+                    "Monero refund sweep received {} out of {} confirmations",
+                    least_confirmations, expected_confirmations
+                );
+            }
+
+            if least_confirmations < expected_confirmations {
+                return Err(backoff::Error::Transient(anyhow::anyhow!(
+                    "Monero refund sweep is not final yet"
+                )));
+            }
+
+            Ok(())
+        })
+        .await?;
+
+        Ok(tx_hashes)
     }
 }
 
@@ -121,7 +643,7 @@ impl WatchForTransfer for Wallet {
         transfer_proof: TransferProof,
         expected_amount: Amount,
         expected_confirmations: u32,
-    ) -> Result<(), InsufficientFunds> {
+    ) -> Result<BlockHeight, InsufficientFunds> {
         enum Error {
             TxNotFound,
             InsufficientConfirmations,
@@ -137,6 +659,10 @@ impl WatchForTransfer for Wallet {
             // NOTE: Currently, this is conflicting IO errors with the transaction not being
             // in the blockchain yet, or not having enough confirmations on it. All these
             // errors warrant a retry, but the strategy should probably differ per case
+            let _session = self
+                .main_wallet()
+                .await
+                .map_err(|_| backoff::Error::Transient(Error::TxNotFound))?;
             let proof = wallet
                 .check_tx_key(
                     &String::from(transfer_proof.tx_hash()),
@@ -165,14 +691,264 @@ impl WatchForTransfer for Wallet {
                 return Err(backoff::Error::Transient(Error::InsufficientConfirmations));
             }
 
-            Ok(proof)
+            // Our wallet can lag behind the daemon that counted the confirmations, which
+            // only makes the height lower than it is
+            let height = wallet
+                .block_height()
+                .await
+                .map_err(|_| backoff::Error::Transient(Error::TxNotFound))?;
+
+            Ok(BlockHeight {
+                height: height.height.saturating_sub(proof.confirmations),
+            })
+        })
+        .await;
+
+        match res {
+            Ok(height) => Ok(height),
+            Err(Error::InsufficientFunds { expected, actual }) => {
+                Err(InsufficientFunds { expected, actual })
+            }
+            Err(Error::TxNotFound) | Err(Error::InsufficientConfirmations) => {
+                unreachable!("transient errors are retried forever")
+            }
+        }
+    }
+
+    async fn watch_for_transfer_with_view_key(
+        &self,
+        swap_id: Uuid,
+        public_spend_key: PublicKey,
+        private_view_key: PrivateViewKey,
+        restore_height: BlockHeight,
+        expected_amount: Amount,
+        expected_confirmations: u32,
+    ) -> Result<Result<(TxHash, BlockHeight), InsufficientFunds>> {
+        enum Error {
+            TxNotFound,
+            InsufficientConfirmations,
+            InsufficientFunds { expected: Amount, actual: Amount },
+        }
+
+        let address = Address::standard(
+            self.network,
+            public_spend_key,
+            private_view_key.public().into(),
+        );
+
+        let file_name = view_only_wallet_name(swap_id);
+        let file_name = file_name.as_str();
+        self.switch_wallet(|wallet| async move {
+            // The view-only wallet may still exist from a previous run
+            if wallet.open_wallet(file_name).await.is_err() {
+                let _ = wallet
+                    .generate_view_only_from_keys(
+                        file_name,
+                        &address.to_string(),
+                        &PrivateKey::from(private_view_key).to_string(),
+                        restore_height.height,
+                    )
+                    .await?;
+            }
+
+            Ok(())
+        })
+        .await?;
+        info!(
+            "Scanning for Monero lock transaction to {} from block {}",
+            address, restore_height
+        );
+
+        let confirmations = Arc::new(AtomicU32::new(0u32));
+
+        let res = retry(ConstantBackoff::new(VIEW_KEY_POLL_INTERVAL), || async {
+            let transfers = self
+                .switch_wallet(|wallet| async move {
+                    wallet.open_wallet(file_name).await?;
+                    let _ = wallet.refresh().await?;
+                    wallet.get_incoming_transfers().await
+                })
+                .await
+                .map_err(|_| backoff::Error::Transient(Error::TxNotFound))?;
+
+            // Alice locks with a single transaction, anything sent later does
+            // not count towards the lock
+            let transfer = transfers
+                .incoming
+                .into_iter()
+                .chain(transfers.pool)
+                .min_by_key(|transfer| (transfer.confirmations == 0, transfer.height))
+                .ok_or(backoff::Error::Transient(Error::TxNotFound))?;
+
+            if transfer.amount != expected_amount.as_piconero() {
+                return Err(backoff::Error::Permanent(Error::InsufficientFunds {
+                    expected: expected_amount,
+                    actual: Amount::from_piconero(transfer.amount),
+                }));
+            }
+
+            if transfer.confirmations > confirmations.load(Ordering::SeqCst) {
+                confirmations.store(transfer.confirmations, Ordering::SeqCst);
+                info!(
+                    "Monero lock tx received {} out of {} confirmations",
+                    transfer.confirmations, expected_confirmations
+                );
+            }
+
+            if transfer.confirmations < expected_confirmations {
+                return Err(backoff::Error::Transient(Error::InsufficientConfirmations));
+            }
+
+            Ok((TxHash(transfer.txid), BlockHeight {
+                height: transfer.height,
+            }))
         })
         .await;
 
-        if let Err(Error::InsufficientFunds { expected, actual }) = res {
-            return Err(InsufficientFunds { expected, actual });
+        match res {
+            Ok(lock) => Ok(Ok(lock)),
+            Err(Error::InsufficientFunds { expected, actual }) => {
+                Ok(Err(InsufficientFunds { expected, actual }))
+            }
+            Err(Error::TxNotFound) | Err(Error::InsufficientConfirmations) => {
+                unreachable!("transient errors are retried forever")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monero::{wallet_rpc::fake::FakeWalletRpc, Scalar};
+    use rand::rngs::OsRng;
+    use serde_json::json;
+    use tokio::time::timeout;
+
+    const EXPECTED_AMOUNT: u64 = 1_000_000_000_000;
+
+    fn lock_transaction(unlock_time: u64, amounts: Vec<Option<u64>>) -> LockTransaction {
+        LockTransaction {
+            tx_hash: TxHash("lock".to_owned()),
+            unlock_time,
+            amounts,
+        }
+    }
+
+    #[test]
+    fn accepts_lock_transaction_paying_expected_amount() {
+        let tx = lock_transaction(0, vec![Some(EXPECTED_AMOUNT)]);
+
+        assert!(tx.check(Amount::from_piconero(EXPECTED_AMOUNT)).is_ok());
+    }
+
+    #[test]
+    fn rejects_lock_transaction_with_unlock_time() {
+        let tx = lock_transaction(2_000_000, vec![Some(EXPECTED_AMOUNT)]);
+
+        assert!(matches!(
+            tx.check(Amount::from_piconero(EXPECTED_AMOUNT)),
+            Err(InvalidLockTransaction::UnlockTime {
+                unlock_time: 2_000_000,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_lock_transaction_without_output_to_lock_address() {
+        let tx = lock_transaction(0, vec![]);
+
+        assert!(matches!(
+            tx.check(Amount::from_piconero(EXPECTED_AMOUNT)),
+            Err(InvalidLockTransaction::NotToLockAddress { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_lock_transaction_with_wrong_amount() {
+        let too_little = lock_transaction(0, vec![Some(EXPECTED_AMOUNT - 1)]);
+        let undecryptable = lock_transaction(0, vec![None]);
+
+        for tx in vec![too_little, undecryptable] {
+            assert!(matches!(
+                tx.check(Amount::from_piconero(EXPECTED_AMOUNT)),
+                Err(InvalidLockTransaction::InsufficientFunds(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn sums_amounts_of_all_outputs_to_lock_address() {
+        let tx = lock_transaction(0, vec![
+            Some(EXPECTED_AMOUNT / 2),
+            Some(EXPECTED_AMOUNT / 2),
+        ]);
+
+        assert!(tx.check(Amount::from_piconero(EXPECTED_AMOUNT)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn concurrent_view_key_scans_take_turns() {
+        // The transfer that each wallet sees is named after the wallet, so a scan
+        // that reads the wallet of the other swap gets the wrong one.
+        let open_wallet = Arc::new(std::sync::Mutex::new(String::new()));
+        let fake = {
+            let open_wallet = open_wallet.clone();
+            FakeWalletRpc::start_with(move |method, params| {
+                let mut open_wallet = open_wallet.lock().unwrap();
+                match method {
+                    "open_wallet" => {
+                        *open_wallet = params["filename"].as_str().unwrap().to_owned();
+                        json!({})
+                    }
+                    "refresh" => json!({ "blocks_fetched": 0, "received_money": false }),
+                    "get_transfers" => json!({
+                        "in": [{
+                            "txid": format!("lock-to-{}", open_wallet),
+                            "address": "",
+                            "amount": EXPECTED_AMOUNT,
+                            "confirmations": 1,
+                            "height": 5,
+                            "unlock_time": 0,
+                        }]
+                    }),
+                    _ => json!({}),
+                }
+            })
         };
+        let wallet = Wallet::new(fake.url(), Network::Stagenet, "main");
 
-        Ok(())
+        let scan = |swap_id| {
+            let public_spend_key =
+                PublicKey::from_private_key(&PrivateKey::from_scalar(Scalar::random(&mut OsRng)));
+            wallet.watch_for_transfer_with_view_key(
+                swap_id,
+                public_spend_key,
+                PrivateViewKey::new_random(&mut OsRng),
+                BlockHeight { height: 0 },
+                Amount::from_piconero(EXPECTED_AMOUNT),
+                1,
+            )
+        };
+        let (swap_a, swap_b) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let (lock_a, lock_b) = timeout(Duration::from_secs(10), async {
+            tokio::join!(scan(swap_a), scan(swap_b))
+        })
+        .await
+        .expect("both scans to finish without waiting for the next poll");
+
+        let lock_a = lock_a.unwrap().unwrap().0;
+        let lock_b = lock_b.unwrap().unwrap().0;
+        assert_eq!(
+            lock_a.0,
+            format!("lock-to-{}", view_only_wallet_name(swap_a))
+        );
+        assert_eq!(
+            lock_b.0,
+            format!("lock-to-{}", view_only_wallet_name(swap_b))
+        );
+        assert_eq!(*open_wallet.lock().unwrap(), "main");
     }
 }
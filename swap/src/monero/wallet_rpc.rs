@@ -0,0 +1,634 @@
+use ::monero::Network;
+use anyhow::{bail, Context, Result};
+use monero_harness::rpc::wallet::{self, Login};
+use rand::{distributions::Alphanumeric, Rng};
+use std::{
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    time::Duration,
+};
+use tokio::{
+    process::{Child, Command},
+    sync::oneshot,
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
+use url::Url;
+
+/// How long monero-wallet-rpc may take to answer requests after it was
+/// started.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long monero-wallet-rpc may take to store the wallet and exit.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long we wait before trying again if monero-wallet-rpc cannot be
+/// started.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// How often we try to start monero-wallet-rpc on a fresh port before giving
+/// up, in case another process took the port we picked.
+const START_ATTEMPTS: usize = 3;
+
+/// A monero-wallet-rpc process that we run ourselves.
+///
+/// It listens on a random local port and requires a random login, both of
+/// which stay the same across restarts, so clients created from
+/// [`WalletRpc::url`] and [`WalletRpc::login`] keep working if it has to be
+/// restarted.
+#[derive(Debug)]
+pub struct WalletRpc {
+    url: Url,
+    login: Login,
+    shutdown: oneshot::Sender<()>,
+    supervisor: JoinHandle<()>,
+}
+
+#[derive(Debug, Clone)]
+struct Launcher {
+    binary: PathBuf,
+    daemon_address: String,
+    wallet_dir: PathBuf,
+    network: Network,
+    port: u16,
+    login: Login,
+}
+
+/// How we tell that a running monero-wallet-rpc hangs.
+///
+/// The wallet does not answer while it scans the chain, which can take a
+/// while, so it is only restarted after several checks in a row failed.
+#[derive(Debug, Clone, Copy)]
+struct HealthCheck {
+    interval: Duration,
+    timeout: Duration,
+    max_failures: usize,
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(60),
+            max_failures: 3,
+        }
+    }
+}
+
+impl WalletRpc {
+    /// Starts `binary` connected to the monerod at `daemon_address`, with
+    /// its wallets in `wallet_dir`, and waits until it answers requests.
+    ///
+    /// If the process dies it is started again and `reopen_wallet` is opened,
+    /// as a fresh process has no wallet loaded.
+    pub async fn start(
+        binary: PathBuf,
+        daemon_address: String,
+        wallet_dir: &Path,
+        network: Network,
+        reopen_wallet: &str,
+    ) -> Result<Self> {
+        std::fs::create_dir_all(wallet_dir).with_context(|| {
+            format!(
+                "Could not create Monero wallet directory {}",
+                wallet_dir.display()
+            )
+        })?;
+
+        let mut launcher = Launcher {
+            binary,
+            daemon_address,
+            wallet_dir: wallet_dir.to_path_buf(),
+            network,
+            port: 0,
+            login: random_login(),
+        };
+
+        // The port we picked may be taken by someone else before the process
+        // binds it, in which case it fails to start and we pick another one.
+        let mut attempt = 1;
+        let (child, client) = loop {
+            launcher.port = free_port()?;
+            let client = launcher.client();
+
+            match launcher.spawn(&client).await {
+                Ok(child) => break (child, client),
+                Err(e) if attempt < START_ATTEMPTS => {
+                    tracing::warn!(
+                        "Failed to start monero-wallet-rpc on port {}, trying another one: {:#}",
+                        launcher.port,
+                        e
+                    );
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        let url = launcher.url();
+        let login = launcher.login.clone();
+        tracing::info!("Started monero-wallet-rpc listening on {}", url);
+
+        let (shutdown, shutdown_receiver) = oneshot::channel();
+        let supervisor = tokio::spawn(supervise(
+            child,
+            launcher,
+            client,
+            reopen_wallet.to_owned(),
+            HealthCheck::default(),
+            shutdown_receiver,
+        ));
+
+        Ok(Self {
+            url,
+            login,
+            shutdown,
+            supervisor,
+        })
+    }
+
+    pub fn url(&self) -> Url {
+        self.url.clone()
+    }
+
+    /// The login that monero-wallet-rpc requires for all requests.
+    pub fn login(&self) -> Login {
+        self.login.clone()
+    }
+
+    /// Stores the open wallet and stops the process.
+    ///
+    /// Dropping the handle does the same in the background, but the process
+    /// is only killed if the runtime shuts down before it exits.
+    pub async fn shutdown(self) -> Result<()> {
+        let _ = self.shutdown.send(());
+        self.supervisor.await?;
+
+        Ok(())
+    }
+}
+
+impl Launcher {
+    fn url(&self) -> Url {
+        Url::parse(&format!("http://127.0.0.1:{}/json_rpc", self.port)).expect("url is well formed")
+    }
+
+    fn client(&self) -> wallet::Client {
+        wallet::Client::new(self.url()).with_login(self.login.clone())
+    }
+
+    fn log_file(&self) -> PathBuf {
+        self.wallet_dir.join("monero-wallet-rpc.log")
+    }
+
+    async fn spawn(&self, client: &wallet::Client) -> Result<Child> {
+        let mut command = Command::new(&self.binary);
+        match self.network {
+            Network::Mainnet => {}
+            Network::Stagenet => {
+                command.arg("--stagenet");
+            }
+            Network::Testnet => {
+                command.arg("--testnet");
+            }
+        }
+        command
+            .arg("--daemon-address")
+            .arg(&self.daemon_address)
+            .arg("--rpc-bind-ip")
+            .arg("127.0.0.1")
+            .arg("--rpc-bind-port")
+            .arg(self.port.to_string())
+            .arg("--rpc-login")
+            .arg(format!("{}:{}", self.login.username, self.login.password))
+            .arg("--wallet-dir")
+            .arg(&self.wallet_dir)
+            .arg("--log-file")
+            .arg(self.log_file())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("Failed to start {}", self.binary.display()))?;
+
+        let log_file = self.log_file();
+        let ready = timeout(STARTUP_TIMEOUT, async {
+            loop {
+                if client.block_height().await.is_ok() {
+                    return Ok(());
+                }
+                if let Some(status) = child.try_wait()? {
+                    bail!(
+                        "monero-wallet-rpc exited with {}, see {} for details",
+                        status,
+                        log_file.display()
+                    )
+                }
+                sleep(Duration::from_millis(500)).await;
+            }
+        })
+        .await;
+
+        match ready {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(e),
+            Err(_) => {
+                let _ = child.kill().await;
+                bail!(
+                    "monero-wallet-rpc did not respond within {:?}, see {} for details",
+                    STARTUP_TIMEOUT,
+                    log_file.display()
+                )
+            }
+        }
+
+        Ok(child)
+    }
+}
+
+enum Event {
+    Exited(std::io::Result<ExitStatus>),
+    Unresponsive,
+    Shutdown,
+}
+
+/// Restarts monero-wallet-rpc whenever it exits or stops answering until we
+/// are told to shut it down.
+async fn supervise(
+    mut child: Child,
+    launcher: Launcher,
+    client: wallet::Client,
+    reopen_wallet: String,
+    health_check: HealthCheck,
+    mut shutdown: oneshot::Receiver<()>,
+) {
+    loop {
+        let event = tokio::select! {
+            status = child.wait() => Event::Exited(status),
+            _ = unresponsive(&client, health_check) => Event::Unresponsive,
+            // Also taken if the handle was dropped
+            _ = &mut shutdown => Event::Shutdown,
+        };
+
+        match event {
+            Event::Exited(status) => {
+                tracing::warn!(
+                    "monero-wallet-rpc exited unexpectedly ({}), restarting it",
+                    describe(status)
+                );
+            }
+            Event::Unresponsive => {
+                tracing::warn!("monero-wallet-rpc stopped responding, restarting it");
+                let _ = child.kill().await;
+            }
+            Event::Shutdown => {
+                stop(child, &client).await;
+                return;
+            }
+        }
+
+        // A half started process is killed when the restart is dropped
+        child = tokio::select! {
+            child = restart(&launcher, &client, &reopen_wallet) => child,
+            _ = &mut shutdown => return,
+        };
+    }
+}
+
+/// Resolves once monero-wallet-rpc failed `health_check.max_failures` checks
+/// in a row.
+async fn unresponsive(client: &wallet::Client, health_check: HealthCheck) {
+    let mut failures = 0;
+
+    loop {
+        sleep(health_check.interval).await;
+
+        match timeout(health_check.timeout, client.block_height()).await {
+            Ok(Ok(_)) => {
+                failures = 0;
+                continue;
+            }
+            Ok(Err(e)) => {
+                tracing::debug!("monero-wallet-rpc health check failed: {:#}", e);
+            }
+            Err(_) => {
+                tracing::debug!(
+                    "monero-wallet-rpc did not answer the health check within {:?}",
+                    health_check.timeout
+                );
+            }
+        }
+
+        failures += 1;
+        if failures >= health_check.max_failures {
+            return;
+        }
+    }
+}
+
+async fn restart(launcher: &Launcher, client: &wallet::Client, reopen_wallet: &str) -> Child {
+    loop {
+        match launcher.spawn(client).await {
+            Ok(child) => {
+                if let Err(e) = client.open_wallet(reopen_wallet).await {
+                    tracing::warn!(
+                        "Failed to open Monero wallet {} after restart: {:#}",
+                        reopen_wallet,
+                        e
+                    );
+                }
+                tracing::info!("Restarted monero-wallet-rpc");
+                return child;
+            }
+            Err(e) => {
+                tracing::error!("Failed to restart monero-wallet-rpc: {:#}", e);
+                sleep(RESTART_DELAY).await;
+            }
+        }
+    }
+}
+
+async fn stop(mut child: Child, client: &wallet::Client) {
+    if let Ok(Some(_)) = child.try_wait() {
+        return;
+    }
+
+    if let Err(e) = client.stop_wallet().await {
+        tracing::warn!("Failed to stop monero-wallet-rpc gracefully: {:#}", e);
+    }
+
+    if timeout(SHUTDOWN_TIMEOUT, child.wait()).await.is_err() {
+        tracing::warn!("monero-wallet-rpc did not exit in time, killing it");
+        let _ = child.kill().await;
+    }
+}
+
+fn describe(status: std::io::Result<ExitStatus>) -> String {
+    match status {
+        Ok(status) => status.to_string(),
+        Err(e) => e.to_string(),
+    }
+}
+
+/// Generates credentials for a single monero-wallet-rpc so that other local
+/// processes cannot use our wallets.
+fn random_login() -> Login {
+    let random = |len| {
+        rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(len)
+            .collect::<String>()
+    };
+
+    Login {
+        username: format!("swap-{}", random(8)),
+        password: random(32),
+    }
+}
+
+/// Asks the OS for a port that is currently unused.
+///
+/// Nothing stops another process from taking it before we bind it, see
+/// [`WalletRpc::start`] for how we deal with that.
+fn free_port() -> Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0").context("Failed to find a free port")?;
+
+    Ok(listener.local_addr()?.port())
+}
+
+/// Stands in for monero-wallet-rpc in tests.
+#[cfg(test)]
+pub(crate) mod fake {
+    use serde_json::{json, Value};
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        thread,
+        time::Duration,
+    };
+
+    type Respond = dyn Fn(&str, &Value) -> Value + Send + Sync;
+
+    /// Answers JSON-RPC requests in place of monero-wallet-rpc, and records
+    /// which methods were called.
+    #[derive(Clone)]
+    pub(crate) struct FakeWalletRpc {
+        pub port: u16,
+        pub responsive: Arc<AtomicBool>,
+        calls: Arc<Mutex<Vec<String>>>,
+        respond: Arc<Respond>,
+    }
+
+    impl FakeWalletRpc {
+        /// Answers every request with an empty result, except `get_height`.
+        pub fn start() -> Self {
+            Self::start_with(|method, _| match method {
+                "get_height" => json!({ "height": 1 }),
+                _ => json!({}),
+            })
+        }
+
+        /// Answers requests with the result `respond` returns for their
+        /// method and params.
+        pub fn start_with<F>(respond: F) -> Self
+        where
+            F: Fn(&str, &Value) -> Value + Send + Sync + 'static,
+        {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let fake = Self {
+                port: listener.local_addr().unwrap().port(),
+                responsive: Arc::new(AtomicBool::new(true)),
+                calls: Arc::new(Mutex::new(Vec::new())),
+                respond: Arc::new(respond),
+            };
+
+            let server = fake.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let server = server.clone();
+                    thread::spawn(move || server.answer(stream.unwrap()));
+                }
+            });
+
+            fake
+        }
+
+        pub fn url(&self) -> url::Url {
+            format!("http://127.0.0.1:{}/json_rpc", self.port)
+                .parse()
+                .unwrap()
+        }
+
+        fn answer(&self, mut stream: TcpStream) {
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 1024];
+            let body = loop {
+                let read = stream.read(&mut buffer).unwrap();
+                if read == 0 {
+                    return;
+                }
+                request.extend_from_slice(&buffer[..read]);
+
+                let request = String::from_utf8_lossy(&request).into_owned();
+                if let Some(end_of_headers) = request.find("\r\n\r\n") {
+                    let content_length = request
+                        .lines()
+                        .find_map(|line| {
+                            let line = line.to_ascii_lowercase();
+                            line.strip_prefix("content-length:")
+                                .map(|length| length.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    let body = &request[end_of_headers + 4..];
+                    if body.len() >= content_length {
+                        break body.to_owned();
+                    }
+                }
+            };
+
+            if !self.responsive.load(Ordering::SeqCst) {
+                // Hang up without an answer once the client gave up
+                thread::sleep(Duration::from_secs(5));
+                return;
+            }
+
+            let request: Value = serde_json::from_str(&body).unwrap();
+            let method = request["method"].as_str().unwrap().to_owned();
+            let result = (self.respond)(&method, &request["params"]);
+            self.calls.lock().unwrap().push(method);
+
+            let body = json!({ "id": "1", "jsonrpc": "2.0", "result": result }).to_string();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        }
+
+        pub fn was_called(&self, method: &str) -> bool {
+            self.calls().iter().any(|call| call == method)
+        }
+
+        /// The methods called so far, in the order they were answered.
+        pub fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{fake::FakeWalletRpc, *};
+    use std::{os::unix::fs::PermissionsExt, sync::atomic::Ordering};
+    use tempfile::TempDir;
+
+    /// Writes a script that stands in for the monero-wallet-rpc binary. It
+    /// counts how often it was started and then runs `body`.
+    fn fake_binary(dir: &Path, body: &str) -> PathBuf {
+        let binary = dir.join("monero-wallet-rpc");
+        std::fs::write(
+            &binary,
+            format!(
+                "#!/bin/sh\necho started >> {}\n{}\n",
+                dir.join("starts").display(),
+                body
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        binary
+    }
+
+    fn starts(dir: &Path) -> usize {
+        std::fs::read_to_string(dir.join("starts"))
+            .map(|starts| starts.lines().count())
+            .unwrap_or(0)
+    }
+
+    async fn wait_until(condition: impl Fn() -> bool) {
+        timeout(Duration::from_secs(20), async {
+            while !condition() {
+                sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("condition to become true in time")
+    }
+
+    async fn supervise_fake(
+        dir: &Path,
+        binary: PathBuf,
+        fake: &FakeWalletRpc,
+    ) -> oneshot::Sender<()> {
+        let launcher = Launcher {
+            binary,
+            daemon_address: "127.0.0.1:1".to_owned(),
+            wallet_dir: dir.to_path_buf(),
+            network: Network::Stagenet,
+            port: fake.port,
+            login: random_login(),
+        };
+        let client = launcher.client();
+        let child = launcher.spawn(&client).await.unwrap();
+
+        let (shutdown, shutdown_receiver) = oneshot::channel();
+        tokio::spawn(supervise(
+            child,
+            launcher,
+            client,
+            "main".to_owned(),
+            HealthCheck {
+                interval: Duration::from_millis(100),
+                timeout: Duration::from_millis(200),
+                max_failures: 2,
+            },
+            shutdown_receiver,
+        ));
+
+        shutdown
+    }
+
+    #[tokio::test]
+    async fn restarts_exited_process_and_reopens_wallet() {
+        let dir = TempDir::new().unwrap();
+        let binary = fake_binary(
+            dir.path(),
+            // Only the first process dies
+            &format!(
+                "if [ $(wc -l < {}) -eq 1 ]; then sleep 1; exit 1; fi\nexec sleep 600",
+                dir.path().join("starts").display()
+            ),
+        );
+        let fake = FakeWalletRpc::start();
+
+        let _shutdown = supervise_fake(dir.path(), binary, &fake).await;
+
+        wait_until(|| starts(dir.path()) == 2 && fake.was_called("open_wallet")).await;
+    }
+
+    #[tokio::test]
+    async fn restarts_unresponsive_process() {
+        let dir = TempDir::new().unwrap();
+        let binary = fake_binary(dir.path(), "exec sleep 600");
+        let fake = FakeWalletRpc::start();
+
+        let _shutdown = supervise_fake(dir.path(), binary, &fake).await;
+        wait_until(|| starts(dir.path()) == 1).await;
+
+        fake.responsive.store(false, Ordering::SeqCst);
+        wait_until(|| starts(dir.path()) == 2).await;
+
+        fake.responsive.store(true, Ordering::SeqCst);
+        wait_until(|| fake.was_called("open_wallet")).await;
+    }
+}
@@ -1,4 +1,5 @@
 use std::path::PathBuf;
+use uuid::Uuid;
 
 #[derive(structopt::StructOpt, Debug)]
 pub struct Arguments {
@@ -18,4 +19,36 @@ pub struct Arguments {
 pub enum Command {
     Start,
     History,
+    Resume {
+        #[structopt(long = "swap-id")]
+        swap_id: Uuid,
+    },
+    Cancel {
+        #[structopt(long = "swap-id")]
+        swap_id: Uuid,
+
+        #[structopt(short, long)]
+        force: bool,
+    },
+    Punish {
+        #[structopt(long = "swap-id")]
+        swap_id: Uuid,
+
+        #[structopt(short, long)]
+        force: bool,
+    },
+    Redeem {
+        #[structopt(long = "swap-id")]
+        swap_id: Uuid,
+
+        #[structopt(short, long)]
+        force: bool,
+    },
+    #[structopt(
+        about = "Move the funds of the single key Bitcoin wallet used by earlier versions"
+    )]
+    SweepLegacyWallet {
+        #[structopt(long, help = "Only print the transaction instead of publishing it")]
+        dry_run: bool,
+    },
 }
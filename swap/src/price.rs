@@ -0,0 +1,173 @@
+//! Price sources used by Alice to quote XMR for a requested BTC amount.
+pub mod file;
+pub mod ticker;
+
+use crate::{bitcoin, monero};
+use anyhow::{anyhow, bail, Context, Result};
+use rust_decimal::{
+    prelude::{FromPrimitive, ToPrimitive},
+    Decimal,
+};
+use std::{
+    convert::TryFrom,
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+
+/// The price of one XMR, expressed in BTC.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub ask: bitcoin::Amount,
+}
+
+impl Rate {
+    pub const fn new(ask: bitcoin::Amount) -> Self {
+        Self { ask }
+    }
+
+    /// Increase the asking price by the given spread.
+    pub fn with_spread(self, spread: Spread) -> Result<Self> {
+        let ask = Decimal::from(self.ask.as_sat());
+        let ask = (ask * (Decimal::from(1) + spread.0))
+            .round()
+            .to_u64()
+            .context("Ask including spread does not fit into u64")?;
+
+        Ok(Self {
+            ask: bitcoin::Amount::from_sat(ask),
+        })
+    }
+
+    /// Calculate the amount of XMR that is sold for the given amount of BTC.
+    pub fn sell_quote(&self, quote: bitcoin::Amount) -> Result<monero::Amount> {
+        if self.ask == bitcoin::Amount::ZERO {
+            bail!("Cannot quote against an ask of zero")
+        }
+
+        let piconero = u128::from(quote.as_sat()) * u128::from(monero::PICONERO_OFFSET)
+            / u128::from(self.ask.as_sat());
+        let piconero = u64::try_from(piconero)
+            .map_err(|_| anyhow!("Quote for {} does not fit into a Monero amount", quote))?;
+
+        Ok(monero::Amount::from_piconero(piconero))
+    }
+}
+
+/// The relative amount added on top of the market price, e.g. `0.02` for 2%.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spread(Decimal);
+
+impl Spread {
+    pub fn new(spread: f64) -> Result<Self> {
+        let spread = Decimal::from_f64(spread).context("Spread is not a valid decimal")?;
+
+        if spread.is_sign_negative() || spread >= Decimal::from(1) {
+            bail!("Spread must be in the range [0, 1), got {}", spread)
+        }
+
+        Ok(Self(spread))
+    }
+
+    pub fn zero() -> Self {
+        Self(Decimal::from(0))
+    }
+}
+
+/// Provides the latest rate at which Alice is willing to sell XMR.
+pub trait PriceProvider: Send + Sync {
+    /// Returns an error if no sufficiently fresh rate is available.
+    fn latest_rate(&self) -> Result<Rate>;
+}
+
+/// A rate that never changes, mostly useful for testing.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedRate(Rate);
+
+impl FixedRate {
+    pub const fn new(rate: Rate) -> Self {
+        Self(rate)
+    }
+}
+
+impl PriceProvider for FixedRate {
+    fn latest_rate(&self) -> Result<Rate> {
+        Ok(self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("no rate has been received within the last {max_age_secs} seconds")]
+pub struct StaleRate {
+    pub max_age_secs: u64,
+}
+
+/// The latest rate together with the point in time it was current at, `None`
+/// if no rate is available.
+type LatestRate = Option<(Rate, Instant)>;
+
+/// The rates published by a price source that runs in the background.
+#[derive(Debug, Clone)]
+pub struct PriceUpdates {
+    receiver: watch::Receiver<LatestRate>,
+    max_age: Duration,
+}
+
+impl PriceProvider for PriceUpdates {
+    fn latest_rate(&self) -> Result<Rate> {
+        match *self.receiver.borrow() {
+            Some((rate, received_at)) if received_at.elapsed() <= self.max_age => Ok(rate),
+            _ => Err(StaleRate {
+                max_age_secs: self.max_age.as_secs(),
+            }
+            .into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sell_quote_at_one_hundred_xmr_per_btc() {
+        let rate = Rate::new(bitcoin::Amount::from_sat(1_000_000));
+
+        let xmr = rate
+            .sell_quote(bitcoin::Amount::from_sat(1_000_000))
+            .unwrap();
+
+        assert_eq!(xmr, monero::Amount::from_monero(1.0).unwrap());
+    }
+
+    #[test]
+    fn sell_quote_rounds_down_to_piconero() {
+        let rate = Rate::new(bitcoin::Amount::from_sat(3));
+
+        let xmr = rate.sell_quote(bitcoin::Amount::from_sat(1)).unwrap();
+
+        assert_eq!(xmr, monero::Amount::from_piconero(333_333_333_333));
+    }
+
+    #[test]
+    fn sell_quote_fails_for_zero_ask() {
+        let rate = Rate::new(bitcoin::Amount::ZERO);
+
+        assert!(rate.sell_quote(bitcoin::Amount::from_sat(1)).is_err());
+    }
+
+    #[test]
+    fn spread_increases_ask() {
+        let rate = Rate::new(bitcoin::Amount::from_sat(1_000_000));
+
+        let rate = rate.with_spread(Spread::new(0.02).unwrap()).unwrap();
+
+        assert_eq!(rate.ask, bitcoin::Amount::from_sat(1_020_000));
+    }
+
+    #[test]
+    fn spread_must_be_a_fraction() {
+        assert!(Spread::new(-0.01).is_err());
+        assert!(Spread::new(1.0).is_err());
+        assert!(Spread::new(0.0).is_ok());
+    }
+}
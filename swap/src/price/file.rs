@@ -0,0 +1,82 @@
+use crate::{
+    bitcoin,
+    price::{LatestRate, PriceUpdates, Rate},
+};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{fs, sync::watch};
+use tracing::{debug, warn};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Deserialize)]
+struct RateFile {
+    #[serde(with = "::bitcoin::util::amount::serde::as_btc")]
+    ask: bitcoin::Amount,
+}
+
+/// Reads the rate from a JSON file of the form `{ "ask": 0.0067 }`, where
+/// `ask` is the price of one XMR in BTC.
+///
+/// The file is read in the background every second. It is considered stale
+/// if it has not been modified within `max_age`, this allows an external
+/// process to keep the file up to date.
+pub fn poll(path: PathBuf, max_age: Duration) -> PriceUpdates {
+    let (sender, receiver) = watch::channel(None);
+
+    tokio::spawn(async move {
+        let mut failing = false;
+
+        loop {
+            let latest = match read_rate(&path).await {
+                Ok(latest) => {
+                    failing = false;
+                    latest
+                }
+                Err(e) => {
+                    // Only warn once, the file is read again every second
+                    if !failing {
+                        warn!("{:#}", e);
+                    }
+                    failing = true;
+                    None
+                }
+            };
+
+            if sender.send(latest).is_err() {
+                debug!("Price updates are no longer consumed, stop reading rate file");
+                return;
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+
+    PriceUpdates { receiver, max_age }
+}
+
+async fn read_rate(path: &Path) -> Result<LatestRate> {
+    let modified = fs::metadata(path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .with_context(|| format!("Failed to read rate file {}", path.display()))?;
+    let age = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default();
+
+    let content = fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read rate file {}", path.display()))?;
+    let file = serde_json::from_slice::<RateFile>(&content)
+        .with_context(|| format!("Failed to parse rate file {}", path.display()))?;
+
+    // The rate is as old as the file. If that is before our clock started it
+    // is stale in any case.
+    Ok(Instant::now()
+        .checked_sub(age)
+        .map(|modified| (Rate::new(file.ask), modified)))
+}
@@ -0,0 +1,234 @@
+use crate::{
+    bitcoin,
+    price::{LatestRate, PriceUpdates, Rate},
+};
+use ::bitcoin::Denomination;
+use anyhow::{anyhow, bail, Context, Result};
+use futures::{SinkExt, StreamExt};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, warn};
+use url::Url;
+
+const SUBSCRIBE_XMR_BTC_TICKER: &str =
+    r#"{ "event": "subscribe", "pair": [ "XMR/XBT" ], "subscription": { "name": "ticker" } }"#;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Connects to a Kraken compatible websocket ticker in the background and
+/// keeps track of the latest ask for XMR/BTC.
+///
+/// The connection is re-established whenever it drops. While disconnected no
+/// rate is available, hence quotes are rejected instead of using an outdated
+/// price.
+pub fn connect(url: Url, max_age: Duration) -> PriceUpdates {
+    let (sender, receiver) = watch::channel(None);
+
+    tokio::spawn(async move {
+        loop {
+            match stream_rates(&url, &sender).await {
+                Ok(()) => {
+                    debug!("Price updates are no longer consumed, closing ticker connection");
+                    return;
+                }
+                Err(e) => warn!("Price ticker connection failed: {:#}", e),
+            }
+
+            if sender.send(None).is_err() {
+                return;
+            }
+
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+
+    PriceUpdates { receiver, max_age }
+}
+
+/// Returns `Ok(())` once nobody is interested in the updates anymore.
+async fn stream_rates(url: &Url, sender: &watch::Sender<LatestRate>) -> Result<()> {
+    let (mut stream, _) = tokio_tungstenite::connect_async(url.as_str())
+        .await
+        .with_context(|| format!("Failed to connect to price ticker at {}", url))?;
+
+    stream
+        .send(Message::Text(SUBSCRIBE_XMR_BTC_TICKER.to_string()))
+        .await
+        .context("Failed to subscribe to XMR/BTC ticker")?;
+
+    let mut latest = None;
+
+    while let Some(message) = stream.next().await {
+        let text = match message? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        match parse_event(&text)? {
+            Event::Rate(rate) => latest = Some(rate),
+            // Kraken only publishes a ticker update if the price changed, a
+            // heartbeat tells us that the last rate is still current.
+            Event::Heartbeat => {}
+            Event::Other => continue,
+        }
+
+        if let Some(rate) = latest {
+            if sender.send(Some((rate, Instant::now()))).is_err() {
+                return Ok(());
+            }
+        }
+    }
+
+    Err(anyhow!("Price ticker closed the connection"))
+}
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    Rate(Rate),
+    Heartbeat,
+    Other,
+}
+
+fn parse_event(text: &str) -> Result<Event> {
+    let value = serde_json::from_str::<Value>(text)
+        .with_context(|| format!("Failed to parse ticker message: {}", text))?;
+
+    match &value {
+        Value::Array(_) => {
+            let ask = value[1]["a"][0]
+                .as_str()
+                .with_context(|| format!("Ticker update without ask price: {}", text))?;
+            let ask = bitcoin::Amount::from_str_in(ask, Denomination::Bitcoin)
+                .with_context(|| format!("Invalid ask price {}", ask))?;
+
+            Ok(Event::Rate(Rate::new(ask)))
+        }
+        Value::Object(_) => match value["event"].as_str() {
+            Some("heartbeat") => Ok(Event::Heartbeat),
+            Some("subscriptionStatus") if value["status"] == "error" => {
+                bail!("Ticker subscription failed: {}", value["errorMessage"])
+            }
+            _ => Ok(Event::Other),
+        },
+        _ => Ok(Event::Other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::price::{PriceProvider, StaleRate};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::WebSocketStream;
+
+    #[test]
+    fn parses_ticker_update() {
+        let text = r#"[980,{"a":["0.00658100",12,"12.48100000"],"b":["0.00657600",1,"1.71020000"],"c":["0.00657900","0.50000000"],"v":["1213.10537460","2048.51591938"],"p":["0.00659478","0.00661297"],"t":[412,703],"l":["0.00652900","0.00652900"],"h":["0.00668800","0.00671800"],"o":["0.00663900","0.00667800"]},"ticker","XMR/XBT"]"#;
+
+        let event = parse_event(text).unwrap();
+
+        assert_eq!(
+            event,
+            Event::Rate(Rate::new(bitcoin::Amount::from_sat(658_100)))
+        );
+    }
+
+    #[test]
+    fn parses_heartbeat() {
+        let event = parse_event(r#"{"event":"heartbeat"}"#).unwrap();
+
+        assert_eq!(event, Event::Heartbeat);
+    }
+
+    #[test]
+    fn fails_on_subscription_error() {
+        let text = r#"{"errorMessage":"Currency pair not supported XMR/XBT","event":"subscriptionStatus","pair":"XMR/XBT","status":"error","subscription":{"name":"ticker"}}"#;
+
+        assert!(parse_event(text).is_err());
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_connection_drops() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("ws://{}", listener.local_addr().unwrap())).unwrap();
+        let updates = connect(url, Duration::from_secs(60));
+
+        let mut ticker = accept(&listener).await;
+        send_ask(&mut ticker, "0.00658100").await;
+        wait_until(|| updates.latest_rate().ok() == Some(rate(658_100))).await;
+
+        // No rate is available while disconnected
+        ticker.close(None).await.unwrap();
+        wait_until(|| is_stale(updates.latest_rate())).await;
+
+        let mut ticker = accept(&listener).await;
+        send_ask(&mut ticker, "0.00700000").await;
+        wait_until(|| updates.latest_rate().ok() == Some(rate(700_000))).await;
+    }
+
+    #[tokio::test]
+    async fn rate_goes_stale_without_updates() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("ws://{}", listener.local_addr().unwrap())).unwrap();
+        let updates = connect(url, Duration::from_secs(1));
+
+        let mut ticker = accept(&listener).await;
+        send_ask(&mut ticker, "0.00658100").await;
+        wait_until(|| updates.latest_rate().ok() == Some(rate(658_100))).await;
+
+        // The connection stays open, but neither updates nor heartbeats arrive
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        assert!(is_stale(updates.latest_rate()));
+    }
+
+    /// Accepts the next connection of the ticker client and waits for it to
+    /// subscribe.
+    async fn accept(listener: &TcpListener) -> WebSocketStream<TcpStream> {
+        let (stream, _) = tokio::time::timeout(Duration::from_secs(10), listener.accept())
+            .await
+            .expect("ticker client did not connect")
+            .unwrap();
+        let mut ticker = tokio_tungstenite::accept_async(stream).await.unwrap();
+
+        let subscription = ticker.next().await.unwrap().unwrap();
+        assert_eq!(
+            subscription,
+            Message::Text(SUBSCRIBE_XMR_BTC_TICKER.to_string())
+        );
+
+        ticker
+    }
+
+    async fn send_ask(ticker: &mut WebSocketStream<TcpStream>, ask: &str) {
+        let update = format!(
+            r#"[980,{{"a":["{}",12,"12.48100000"]}},"ticker","XMR/XBT"]"#,
+            ask
+        );
+        ticker.send(Message::Text(update)).await.unwrap();
+    }
+
+    async fn wait_until<F>(condition: F)
+    where
+        F: Fn() -> bool,
+    {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !condition() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("condition was not met in time")
+    }
+
+    fn rate(ask_sat: u64) -> Rate {
+        Rate::new(bitcoin::Amount::from_sat(ask_sat))
+    }
+
+    fn is_stale(rate: Result<Rate>) -> bool {
+        matches!(rate, Err(e) if e.downcast_ref::<StaleRate>().is_some())
+    }
+}
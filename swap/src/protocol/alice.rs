@@ -8,9 +8,12 @@ use uuid::Uuid;
 
 pub use self::{
     behaviour::{Behaviour, OutEvent},
+    cancel::cancel,
     event_loop::{EventLoop, EventLoopHandle},
-    execution_setup::Message1,
+    execution_setup::{Message1, SetupId},
+    punish::punish,
     quote_response::*,
+    redeem::redeem,
     state::*,
     swap::{run, run_until},
     transfer_proof::TransferProof,
@@ -18,10 +21,13 @@ pub use self::{
 pub use execution_setup::Message3;
 
 mod behaviour;
+pub mod cancel;
 mod encrypted_signature;
 pub mod event_loop;
 mod execution_setup;
+pub mod punish;
 mod quote_response;
+pub mod redeem;
 pub mod state;
 mod steps;
 pub mod swap;
@@ -1,16 +1,19 @@
 use crate::{
+    database::Database,
     network::{peer_tracker, peer_tracker::PeerTracker},
     protocol::{
         alice::{
-            encrypted_signature, execution_setup, quote_response, transfer_proof, QuoteResponse,
-            State0, State3, TransferProof,
+            encrypted_signature, execution_setup, execution_setup::SetupId, quote_response,
+            transfer_proof, QuoteResponse, State0, State3, TransferProof,
         },
         bob::{EncryptedSignature, QuoteRequest},
     },
 };
 use anyhow::{Error, Result};
 use libp2p::{request_response::ResponseChannel, NetworkBehaviour, PeerId};
+use std::sync::Arc;
 use tracing::{debug, info};
+use uuid::Uuid;
 
 #[derive(Debug)]
 pub enum OutEvent {
@@ -22,8 +25,15 @@ pub enum OutEvent {
     },
     ExecutionSetupDone {
         bob_peer_id: PeerId,
+        setup_id: SetupId,
+        swap_id: Uuid,
         state3: Box<State3>,
     },
+    ExecutionSetupFailed {
+        bob_peer_id: PeerId,
+        setup_id: SetupId,
+        error: Error,
+    },
     TransferProofAcknowledged,
     EncryptedSignature {
         msg: Box<EncryptedSignature>,
@@ -68,12 +78,24 @@ impl From<execution_setup::OutEvent> for OutEvent {
         match event {
             Done {
                 bob_peer_id,
+                setup_id,
+                swap_id,
                 state3,
             } => OutEvent::ExecutionSetupDone {
                 bob_peer_id,
+                setup_id,
+                swap_id,
                 state3: Box::new(state3),
             },
-            Failure(err) => OutEvent::Failure(err),
+            Failure {
+                bob_peer_id,
+                setup_id,
+                error,
+            } => OutEvent::ExecutionSetupFailed {
+                bob_peer_id,
+                setup_id,
+                error,
+            },
         }
     }
 }
@@ -125,8 +147,14 @@ impl Behaviour {
         Ok(())
     }
 
-    pub fn start_execution_setup(&mut self, bob_peer_id: PeerId, state0: State0) {
-        self.execution_setup.run(bob_peer_id, state0);
+    pub fn start_execution_setup(
+        &mut self,
+        bob_peer_id: PeerId,
+        setup_id: SetupId,
+        state0: State0,
+        db: Arc<Database>,
+    ) {
+        self.execution_setup.run(bob_peer_id, setup_id, state0, db);
         info!("Start execution setup with {}", bob_peer_id);
     }
 
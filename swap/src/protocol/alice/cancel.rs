@@ -0,0 +1,114 @@
+use crate::{
+    bitcoin::{ExpiredTimelocks, GetRawTransaction, TxCancel, Txid, Wallet},
+    database::{Database, Swap},
+    monero,
+    protocol::alice::{steps::publish_cancel_transaction, AliceState},
+};
+use anyhow::{bail, Result};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error, Clone, Copy)]
+pub enum CancelError {
+    #[error("The cancel timelock has not expired yet.")]
+    CancelTimelockNotExpiredYet,
+    #[error("The cancel transaction has already been published.")]
+    CancelTxAlreadyPublished,
+}
+
+pub async fn cancel(
+    swap_id: Uuid,
+    state: AliceState,
+    bitcoin_wallet: Arc<Wallet>,
+    monero_wallet: Arc<monero::Wallet>,
+    db: Arc<Database>,
+    force: bool,
+) -> Result<Result<(Txid, AliceState), CancelError>> {
+    let (monero_wallet_restore_blockheight, state3) = match state {
+        // We might have locked the Monero without recording it, so we look for the lock
+        // transaction in our wallet
+        AliceState::Started { state3, .. } | AliceState::BtcLocked { state3, .. } => {
+            let lock_address = state3.monero_lock_address(monero_wallet.network);
+            let monero_wallet_restore_blockheight =
+                match monero_wallet.find_transfer_to(lock_address).await? {
+                    Some(lock_height) => lock_height,
+                    // Whatever we lock from now on cannot be in an earlier block
+                    None => monero_wallet.block_height().await?,
+                };
+
+            (Some(monero_wallet_restore_blockheight), state3)
+        }
+        AliceState::XmrLocked {
+            monero_wallet_restore_blockheight,
+            state3,
+        } => (monero_wallet_restore_blockheight, state3),
+        AliceState::EncSigLearned {
+            monero_wallet_restore_blockheight,
+            state3,
+            ..
+        } => (monero_wallet_restore_blockheight, state3),
+        AliceState::CancelTimelockExpired {
+            monero_wallet_restore_blockheight,
+            state3,
+        } => (monero_wallet_restore_blockheight, state3),
+        _ => bail!(
+            "Cannot cancel swap {} because it is in state {} which is not cancelable.",
+            swap_id,
+            state
+        ),
+    };
+
+    if !force {
+        if let ExpiredTimelocks::None = state3.expired_timelocks(bitcoin_wallet.as_ref()).await? {
+            return Ok(Err(CancelError::CancelTimelockNotExpiredYet));
+        }
+
+        let tx_cancel = TxCancel::new(
+            &state3.tx_lock,
+            state3.cancel_timelock,
+            state3.a.public(),
+            state3.B,
+            state3.tx_fee_rate,
+        );
+
+        if bitcoin_wallet
+            .get_raw_transaction(tx_cancel.txid())
+            .await
+            .is_ok()
+        {
+            let state = AliceState::BtcCancelled {
+                monero_wallet_restore_blockheight,
+                tx_cancel: Box::new(tx_cancel),
+                state3,
+            };
+            let db_state = (&state).into();
+            db.insert_latest_state(swap_id, Swap::Alice(db_state))
+                .await?;
+
+            return Ok(Err(CancelError::CancelTxAlreadyPublished));
+        }
+    }
+
+    let tx_cancel = publish_cancel_transaction(
+        state3.tx_lock.clone(),
+        state3.a.clone(),
+        state3.B,
+        state3.cancel_timelock,
+        state3.tx_cancel_sig_bob.clone(),
+        state3.tx_fee_rate,
+        bitcoin_wallet,
+    )
+    .await?;
+    let txid = tx_cancel.txid();
+
+    let state = AliceState::BtcCancelled {
+        monero_wallet_restore_blockheight,
+        tx_cancel: Box::new(tx_cancel),
+        state3,
+    };
+    let db_state = (&state).into();
+    db.insert_latest_state(swap_id, Swap::Alice(db_state))
+        .await?;
+
+    Ok(Ok((txid, state)))
+}
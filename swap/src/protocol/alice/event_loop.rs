@@ -1,31 +1,41 @@
 use crate::{
-    bitcoin,
+    bitcoin, database,
     database::Database,
     execution_params::ExecutionParams,
     monero, network,
     network::{transport, TokioExecutor},
+    price::{PriceProvider, Spread},
     protocol::{
         alice,
         alice::{
-            AliceState, Behaviour, Builder, OutEvent, QuoteResponse, State0, State3, TransferProof,
+            AliceState, Behaviour, Builder, OutEvent, QuoteResponse, RejectReason, SetupId, State0,
+            State3, TransferProof,
         },
         bob::{EncryptedSignature, QuoteRequest},
     },
     seed::Seed,
 };
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use futures::future::RemoteHandle;
 use libp2p::{
     core::Multiaddr, futures::FutureExt, request_response::ResponseChannel, PeerId, Swarm,
 };
 use rand::rngs::OsRng;
-use std::sync::Arc;
-use tokio::sync::{broadcast, mpsc, mpsc::error::SendError};
-use tracing::{debug, error, trace};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::{
+    mpsc,
+    mpsc::error::{SendError, TrySendError},
+};
+use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
 
-// TODO: Use dynamic
-pub const RATE: u32 = 100;
+/// How long the XMR of an accepted quote stays reserved if Bob does not
+/// complete the execution setup.
+const QUOTE_RESERVATION_TIMEOUT: Duration = Duration::from_secs(5 * 60);
 
 #[allow(missing_debug_implementations)]
 pub struct MpscChannels<T> {
@@ -40,27 +50,10 @@ impl<T> Default for MpscChannels<T> {
     }
 }
 
-#[allow(missing_debug_implementations)]
-pub struct BroadcastChannels<T>
-where
-    T: Clone,
-{
-    sender: broadcast::Sender<T>,
-}
-
-impl<T> Default for BroadcastChannels<T>
-where
-    T: Clone,
-{
-    fn default() -> Self {
-        let (sender, _receiver) = broadcast::channel(100);
-        BroadcastChannels { sender }
-    }
-}
-
 #[derive(Debug)]
 pub struct EventLoopHandle {
-    recv_encrypted_signature: broadcast::Receiver<EncryptedSignature>,
+    swap_id: Uuid,
+    recv_encrypted_signature: mpsc::Receiver<EncryptedSignature>,
     send_transfer_proof: mpsc::Sender<(PeerId, TransferProof)>,
 }
 
@@ -71,7 +64,15 @@ impl EventLoopHandle {
             .await
             .context("Failed to receive Bitcoin encrypted signature from Bob")
     }
-    pub async fn send_transfer_proof(&mut self, bob: PeerId, msg: TransferProof) -> Result<()> {
+    pub async fn send_transfer_proof(
+        &mut self,
+        bob: PeerId,
+        tx_lock_proof: monero::TransferProof,
+    ) -> Result<()> {
+        let msg = TransferProof {
+            swap_id: self.swap_id,
+            tx_lock_proof,
+        };
         let _ = self.send_transfer_proof.send((bob, msg)).await?;
 
         Ok(())
@@ -87,17 +88,31 @@ pub struct EventLoop {
     monero_wallet: Arc<monero::Wallet>,
     db: Arc<Database>,
     listen_address: Multiaddr,
+    price_provider: Box<dyn PriceProvider>,
+    spread: Spread,
+    min_buy: bitcoin::Amount,
+    max_buy: bitcoin::Amount,
+
+    /// The XMR of quotes whose execution setup is still running, which is
+    /// not in the database yet.
+    reservations: HashMap<SetupId, Reservation>,
 
-    recv_encrypted_signature: broadcast::Sender<EncryptedSignature>,
+    /// Routes incoming encrypted signatures to the swap they belong to.
+    recv_encrypted_signature: HashMap<Uuid, mpsc::Sender<EncryptedSignature>>,
     send_transfer_proof: mpsc::Receiver<(PeerId, TransferProof)>,
 
     // Only used to produce new handles
     send_transfer_proof_sender: mpsc::Sender<(PeerId, TransferProof)>,
 
+    /// The ids of swaps that stopped running, to forget their routes.
+    swap_finished: mpsc::Receiver<Uuid>,
+    swap_finished_sender: mpsc::Sender<Uuid>,
+
     swap_handle_sender: mpsc::Sender<RemoteHandle<Result<AliceState>>>,
 }
 
 impl EventLoop {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         listen_address: Multiaddr,
         seed: Seed,
@@ -105,6 +120,10 @@ impl EventLoop {
         bitcoin_wallet: Arc<bitcoin::Wallet>,
         monero_wallet: Arc<monero::Wallet>,
         db: Arc<Database>,
+        price_provider: Box<dyn PriceProvider>,
+        spread: Spread,
+        min_buy: bitcoin::Amount,
+        max_buy: bitcoin::Amount,
     ) -> Result<(Self, mpsc::Receiver<RemoteHandle<Result<AliceState>>>)> {
         let identity = network::Seed::new(seed).derive_libp2p_identity();
         let behaviour = Behaviour::default();
@@ -120,8 +139,8 @@ impl EventLoop {
         Swarm::listen_on(&mut swarm, listen_address.clone())
             .with_context(|| format!("Address is not supported: {:#}", listen_address))?;
 
-        let recv_encrypted_signature = BroadcastChannels::default();
         let send_transfer_proof = MpscChannels::default();
+        let swap_finished = MpscChannels::default();
         let swap_handle = MpscChannels::default();
 
         let event_loop = EventLoop {
@@ -132,17 +151,29 @@ impl EventLoop {
             monero_wallet,
             db,
             listen_address,
-            recv_encrypted_signature: recv_encrypted_signature.sender,
+            price_provider,
+            spread,
+            min_buy,
+            max_buy,
+            reservations: HashMap::new(),
+            recv_encrypted_signature: HashMap::new(),
             send_transfer_proof: send_transfer_proof.receiver,
             send_transfer_proof_sender: send_transfer_proof.sender,
+            swap_finished: swap_finished.receiver,
+            swap_finished_sender: swap_finished.sender,
             swap_handle_sender: swap_handle.sender,
         };
         Ok((event_loop, swap_handle.receiver))
     }
 
-    pub fn new_handle(&self) -> EventLoopHandle {
+    pub fn new_handle(&mut self, swap_id: Uuid) -> EventLoopHandle {
+        let recv_encrypted_signature = MpscChannels::default();
+        self.recv_encrypted_signature
+            .insert(swap_id, recv_encrypted_signature.sender);
+
         EventLoopHandle {
-            recv_encrypted_signature: self.recv_encrypted_signature.subscribe(),
+            swap_id,
+            recv_encrypted_signature: recv_encrypted_signature.receiver,
             send_transfer_proof: self.send_transfer_proof_sender.clone(),
         }
     }
@@ -151,6 +182,43 @@ impl EventLoop {
         self.peer_id
     }
 
+    /// Loads all swaps from the database that have not reached a final state
+    /// and continues executing them alongside new swaps.
+    pub async fn resume_unfinished_swaps(&mut self) -> Result<()> {
+        let unfinished_swaps = self
+            .db
+            .all()?
+            .into_iter()
+            .filter_map(|(swap_id, state)| match state {
+                database::Swap::Alice(database::Alice::Done(_)) => None,
+                database::Swap::Alice(_) => Some(swap_id),
+                database::Swap::Bob(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        for swap_id in unfinished_swaps {
+            let handle = self.new_handle(swap_id);
+
+            let swap = Builder::new(
+                self.peer_id,
+                self.execution_params,
+                swap_id,
+                self.bitcoin_wallet.clone(),
+                self.monero_wallet.clone(),
+                self.db.clone(),
+                self.listen_address.clone(),
+                handle,
+            )
+            .build()
+            .await?;
+
+            info!("Resuming swap {} in state: {}", swap_id, swap.state);
+            self.spawn_swap(swap).await;
+        }
+
+        Ok(())
+    }
+
     pub async fn run(&mut self) {
         loop {
             tokio::select! {
@@ -160,20 +228,26 @@ impl EventLoop {
                             debug!("Connection Established with {}", alice);
                         }
                         OutEvent::QuoteRequest { msg, channel, bob_peer_id } => {
-                            let _ = self.handle_quote_request(msg, channel, bob_peer_id).await;
+                            if let Err(e) = self.handle_quote_request(msg, channel, bob_peer_id).await {
+                                error!("Failed to handle quote request from {}: {:#}", bob_peer_id, e);
+                            }
+                        }
+                        OutEvent::ExecutionSetupDone{bob_peer_id, setup_id, swap_id, state3} => {
+                            // From here on the swap in the database reserves the XMR
+                            self.reservations.remove(&setup_id);
+                            if let Err(e) = self.handle_execution_setup_done(bob_peer_id, swap_id, *state3).await {
+                                error!("Failed to start swap {}: {:#}", swap_id, e);
+                            }
                         }
-                        OutEvent::ExecutionSetupDone{bob_peer_id, state3} => {
-                            let _ = self.handle_execution_setup_done(bob_peer_id, *state3).await;
+                        OutEvent::ExecutionSetupFailed{bob_peer_id, setup_id, error} => {
+                            self.reservations.remove(&setup_id);
+                            error!("Execution setup with {} failed: {:#}", bob_peer_id, error);
                         }
                         OutEvent::TransferProofAcknowledged => {
                             trace!("Bob acknowledged transfer proof");
                         }
                         OutEvent::EncryptedSignature{ msg, channel } => {
-                            let _ = self.recv_encrypted_signature.send(*msg);
-                            // Send back empty response so that the request/response protocol completes.
-                            if let Err(error) = self.swarm.send_encrypted_signature_ack(channel) {
-                                error!("Failed to send Encrypted Signature ack: {:?}", error);
-                            }
+                            self.handle_encrypted_signature(*msg, channel);
                         }
                         OutEvent::ResponseSent => {}
                         OutEvent::Failure(err) => {
@@ -186,6 +260,11 @@ impl EventLoop {
                       self.swarm.send_transfer_proof(bob_peer_id, msg);
                     }
                 },
+                swap_id = self.swap_finished.recv().fuse() => {
+                    if let Some(swap_id) = swap_id {
+                        self.recv_encrypted_signature.remove(&swap_id);
+                    }
+                },
             }
         }
     }
@@ -196,41 +275,138 @@ impl EventLoop {
         channel: ResponseChannel<QuoteResponse>,
         bob_peer_id: PeerId,
     ) -> Result<()> {
-        // 1. Check if acceptable request
-        // 2. Send response
-
         let btc_amount = quote_request.btc_amount;
-        let xmr_amount = btc_amount.as_btc() * RATE as f64;
-        let xmr_amount = monero::Amount::from_monero(xmr_amount)?;
-        let quote_response = QuoteResponse { xmr_amount };
+        let quote = match self.make_quote(btc_amount).await {
+            Ok(quote) => quote,
+            Err(e) => {
+                error!("Failed to make a quote for {}: {:#}", btc_amount, e);
+                Err(RejectReason::Unavailable)
+            }
+        };
+        let xmr_amount = match quote {
+            Ok(xmr_amount) => xmr_amount,
+            Err(reason) => {
+                warn!(
+                    "Rejecting quote request for {} from {}: {}",
+                    btc_amount, bob_peer_id, reason
+                );
+
+                self.swarm
+                    .send_quote_response(channel, QuoteResponse::Rejected { reason })
+                    .context("Failed to send quote response")?;
+
+                return Ok(());
+            }
+        };
 
         self.swarm
-            .send_quote_response(channel, quote_response)
+            .send_quote_response(channel, QuoteResponse::Quote { xmr_amount })
             .context("Failed to send quote response")?;
 
         // 3. Start setup execution
 
-        let state0 = State0::new(
+        let setup_id = SetupId::new_v4();
+        self.reservations.insert(setup_id, Reservation {
+            xmr: xmr_amount,
+            expires: Instant::now() + QUOTE_RESERVATION_TIMEOUT,
+        });
+
+        let state0 = match State0::new(
             btc_amount,
             xmr_amount,
             self.execution_params,
             self.bitcoin_wallet.as_ref(),
             &mut OsRng,
         )
-        .await?;
+        .await
+        {
+            Ok(state0) => state0,
+            Err(e) => {
+                self.reservations.remove(&setup_id);
+                return Err(e);
+            }
+        };
 
-        self.swarm.start_execution_setup(bob_peer_id, state0);
+        self.swarm
+            .start_execution_setup(bob_peer_id, setup_id, state0, self.db.clone());
         // Continues once the execution setup protocol is done
         Ok(())
     }
 
+    async fn make_quote(
+        &mut self,
+        btc_amount: bitcoin::Amount,
+    ) -> Result<Result<monero::Amount, RejectReason>> {
+        if btc_amount < self.min_buy {
+            return Ok(Err(RejectReason::AmountTooLow { min: self.min_buy }));
+        }
+
+        if btc_amount > self.max_buy {
+            return Ok(Err(RejectReason::AmountTooHigh { max: self.max_buy }));
+        }
+
+        let rate = match self.price_provider.latest_rate() {
+            Ok(rate) => rate,
+            Err(e) => {
+                warn!("No rate available: {:#}", e);
+                return Ok(Err(RejectReason::PriceUnavailable));
+            }
+        };
+        let xmr_amount = rate.with_spread(self.spread)?.sell_quote(btc_amount)?;
+
+        let unlocked_balance = self.monero_wallet.get_unlocked_balance().await?;
+        self.reservations
+            .retain(|_, reservation| reservation.expires > Instant::now());
+        let reserved = self.reserved_xmr()?;
+
+        if unlocked_balance < reserved + xmr_amount {
+            debug!(
+                "Unlocked balance of {} does not cover {} reserved for ongoing swaps and {} requested",
+                unlocked_balance, reserved, xmr_amount
+            );
+            return Ok(Err(RejectReason::InsufficientLiquidity));
+        }
+
+        Ok(Ok(xmr_amount))
+    }
+
+    /// The XMR of accepted quotes and of swaps that have not been locked yet.
+    /// This amount is still part of the unlocked balance but must not be
+    /// promised to anyone else.
+    fn reserved_xmr(&self) -> Result<monero::Amount> {
+        let quoted = self
+            .reservations
+            .values()
+            .fold(monero::Amount::ZERO, |acc, reservation| {
+                acc + reservation.xmr
+            });
+        let reserved = self
+            .db
+            .all()?
+            .into_iter()
+            .filter_map(|(_, swap)| match swap {
+                database::Swap::Alice(database::Alice::Started { state3, .. })
+                | database::Swap::Alice(database::Alice::BtcLocked { state3, .. }) => {
+                    Some(state3.xmr)
+                }
+                _ => None,
+            })
+            .fold(quoted, |acc, xmr| acc + xmr);
+
+        Ok(reserved)
+    }
+
     async fn handle_execution_setup_done(
         &mut self,
         bob_peer_id: PeerId,
+        swap_id: Uuid,
         state3: State3,
     ) -> Result<()> {
-        let swap_id = Uuid::new_v4();
-        let handle = self.new_handle();
+        if self.db.get_state(swap_id).is_ok() {
+            bail!("Swap {} already exists", swap_id)
+        }
+
+        let handle = self.new_handle(swap_id);
 
         let swap = Builder::new(
             self.peer_id,
@@ -246,7 +422,61 @@ impl EventLoop {
         .build()
         .await?;
 
-        let (swap, swap_handle) = alice::run(swap).remote_handle();
+        // Persist the swap right away so that its XMR is reserved until it is
+        // locked.
+        let db_state = (&swap.state).into();
+        self.db
+            .insert_latest_state(swap_id, database::Swap::Alice(db_state))
+            .await?;
+
+        self.spawn_swap(swap).await;
+
+        Ok(())
+    }
+
+    fn handle_encrypted_signature(
+        &mut self,
+        msg: EncryptedSignature,
+        channel: ResponseChannel<()>,
+    ) {
+        let swap_id = msg.swap_id;
+
+        // The swap may be busy, blocking on it here would stall all other swaps
+        let sent = self
+            .recv_encrypted_signature
+            .get(&swap_id)
+            .map(|sender| sender.try_send(msg));
+        match sent {
+            Some(Ok(())) => {}
+            Some(Err(TrySendError::Full(_))) => warn!(
+                "Dropping encrypted signature for swap {}, it did not process earlier ones yet",
+                swap_id
+            ),
+            Some(Err(TrySendError::Closed(_))) => {
+                warn!(
+                    "Received encrypted signature for swap {} which is no longer running",
+                    swap_id
+                );
+                self.recv_encrypted_signature.remove(&swap_id);
+            }
+            None => warn!("Received encrypted signature for unknown swap {}", swap_id),
+        }
+
+        // Send back empty response so that the request/response protocol completes.
+        if let Err(error) = self.swarm.send_encrypted_signature_ack(channel) {
+            error!("Failed to send Encrypted Signature ack: {:?}", error);
+        }
+    }
+
+    async fn spawn_swap(&mut self, swap: alice::Swap) {
+        let swap_id = swap.swap_id;
+        let swap_finished = self.swap_finished_sender.clone();
+        let swap = async move {
+            let result = alice::run(swap).await;
+            let _ = swap_finished.send(swap_id).await;
+            result
+        };
+        let (swap, swap_handle) = swap.remote_handle();
         tokio::spawn(swap);
 
         // For testing purposes the handle is currently sent via a channel so we can
@@ -256,7 +486,11 @@ impl EventLoop {
         if let Err(SendError(handle)) = self.swap_handle_sender.send(swap_handle).await {
             handle.forget();
         }
-
-        Ok(())
     }
 }
+
+#[derive(Debug, Clone, Copy)]
+struct Reservation {
+    xmr: monero::Amount,
+    expires: Instant,
+}
@@ -1,6 +1,7 @@
 use crate::{
     bitcoin,
     bitcoin::{EncryptedSignature, Signature},
+    database::Database,
     monero,
     network::request_response::BUF_SIZE,
     protocol::{
@@ -8,10 +9,12 @@ use crate::{
         bob::{Message0, Message2, Message4},
     },
 };
-use anyhow::{Context, Error};
+use anyhow::{bail, Context, Error, Result};
 use libp2p::PeerId;
 use libp2p_async_await::BehaviourOutEvent;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Message1 {
@@ -22,6 +25,7 @@ pub struct Message1 {
     pub(crate) v_a: monero::PrivateViewKey,
     pub(crate) redeem_address: bitcoin::Address,
     pub(crate) punish_address: bitcoin::Address,
+    pub(crate) tx_fee_rate: bitcoin::FeeRate,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -30,20 +34,46 @@ pub struct Message3 {
     pub(crate) tx_refund_encsig: EncryptedSignature,
 }
 
+/// Identifies a run of the execution setup. Bob only tells us the swap id
+/// once the setup is underway, so it cannot be used for that.
+pub type SetupId = Uuid;
+
 #[derive(Debug)]
 pub enum OutEvent {
-    Done { bob_peer_id: PeerId, state3: State3 },
-    Failure(Error),
+    Done {
+        bob_peer_id: PeerId,
+        setup_id: SetupId,
+        swap_id: Uuid,
+        state3: State3,
+    },
+    Failure {
+        bob_peer_id: PeerId,
+        setup_id: SetupId,
+        error: Error,
+    },
 }
 
-impl From<BehaviourOutEvent<(PeerId, State3), (), Error>> for OutEvent {
-    fn from(event: BehaviourOutEvent<(PeerId, State3), (), Error>) -> Self {
+type Done = (PeerId, SetupId, Uuid, State3);
+type Failure = (PeerId, SetupId, Error);
+
+impl From<BehaviourOutEvent<Done, (), Failure>> for OutEvent {
+    fn from(event: BehaviourOutEvent<Done, (), Failure>) -> Self {
         match event {
-            BehaviourOutEvent::Inbound(_, Ok((bob_peer_id, state3))) => OutEvent::Done {
-                bob_peer_id,
-                state3,
-            },
-            BehaviourOutEvent::Inbound(_, Err(e)) => OutEvent::Failure(e),
+            BehaviourOutEvent::Inbound(_, Ok((bob_peer_id, setup_id, swap_id, state3))) => {
+                OutEvent::Done {
+                    bob_peer_id,
+                    setup_id,
+                    swap_id,
+                    state3,
+                }
+            }
+            BehaviourOutEvent::Inbound(_, Err((bob_peer_id, setup_id, error))) => {
+                OutEvent::Failure {
+                    bob_peer_id,
+                    setup_id,
+                    error,
+                }
+            }
             BehaviourOutEvent::Outbound(..) => unreachable!("Alice only supports inbound"),
         }
     }
@@ -52,51 +82,68 @@ impl From<BehaviourOutEvent<(PeerId, State3), (), Error>> for OutEvent {
 #[derive(libp2p::NetworkBehaviour)]
 #[behaviour(out_event = "OutEvent", event_process = false)]
 pub struct Behaviour {
-    inner: libp2p_async_await::Behaviour<(PeerId, State3), (), anyhow::Error>,
+    inner: libp2p_async_await::Behaviour<Done, (), Failure>,
 }
 
 impl Default for Behaviour {
     fn default() -> Self {
         Self {
-            inner: libp2p_async_await::Behaviour::new(b"/comit/xmr/btc/execution_setup/1.0.0"),
+            inner: libp2p_async_await::Behaviour::new(b"/comit/xmr/btc/execution_setup/3.0.0"),
         }
     }
 }
 
 impl Behaviour {
-    pub fn run(&mut self, bob: PeerId, state0: State0) {
+    pub fn run(&mut self, bob: PeerId, setup_id: SetupId, state0: State0, db: Arc<Database>) {
         self.inner
             .do_protocol_listener(bob, move |mut substream| async move {
-                let message0 =
-                    serde_cbor::from_slice::<Message0>(&substream.read_message(BUF_SIZE).await?)
-                        .context("failed to deserialize message0")?;
-                let state1 = state0.receive(message0)?;
+                // Tell the event loop which setup failed, so it can release
+                // what it reserved for it
+                let result: Result<(Uuid, State3)> = async {
+                    let message0 = serde_cbor::from_slice::<Message0>(
+                        &substream.read_message(BUF_SIZE).await?,
+                    )
+                    .context("failed to deserialize message0")?;
+                    let swap_id = message0.swap_id;
+                    // Bob chooses the swap id, refuse it before he can lock his Bitcoin
+                    if db.get_state(swap_id).is_ok() {
+                        bail!("Swap {} already exists", swap_id)
+                    }
+                    let state1 = state0.receive(message0)?;
+
+                    substream
+                        .write_message(
+                            &serde_cbor::to_vec(&state1.next_message())
+                                .context("failed to serialize message1")?,
+                        )
+                        .await?;
 
-                substream
-                    .write_message(
-                        &serde_cbor::to_vec(&state1.next_message())
-                            .context("failed to serialize message1")?,
+                    let message2 = serde_cbor::from_slice::<Message2>(
+                        &substream.read_message(BUF_SIZE).await?,
                     )
-                    .await?;
+                    .context("failed to deserialize message2")?;
+                    let state2 = state1.receive(message2);
 
-                let message2 =
-                    serde_cbor::from_slice::<Message2>(&substream.read_message(BUF_SIZE).await?)
-                        .context("failed to deserialize message2")?;
-                let state2 = state1.receive(message2);
+                    substream
+                        .write_message(
+                            &serde_cbor::to_vec(&state2.next_message())
+                                .context("failed to serialize message3")?,
+                        )
+                        .await?;
 
-                substream
-                    .write_message(
-                        &serde_cbor::to_vec(&state2.next_message())
-                            .context("failed to serialize message3")?,
+                    let message4 = serde_cbor::from_slice::<Message4>(
+                        &substream.read_message(BUF_SIZE).await?,
                     )
-                    .await?;
+                    .context("failed to deserialize message4")?;
+                    let state3 = state2.receive(message4)?;
 
-                let message4 =
-                    serde_cbor::from_slice::<Message4>(&substream.read_message(BUF_SIZE).await?)
-                        .context("failed to deserialize message4")?;
-                let state3 = state2.receive(message4)?;
+                    Ok((swap_id, state3))
+                }
+                .await;
 
-                Ok((bob, state3))
+                result
+                    .map(|(swap_id, state3)| (bob, setup_id, swap_id, state3))
+                    .map_err(|error| (bob, setup_id, error))
             })
     }
 }
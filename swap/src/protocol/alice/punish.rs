@@ -0,0 +1,85 @@
+use crate::{
+    bitcoin::{ExpiredTimelocks, Txid, Wallet},
+    database::{Database, Swap},
+    execution_params::ExecutionParams,
+    protocol::alice::{
+        steps::{build_bitcoin_punish_transaction, publish_bitcoin_punish_transaction},
+        AliceState,
+    },
+};
+use anyhow::{bail, Result};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error, Clone, Copy)]
+pub enum PunishError {
+    #[error("Cannot punish because the swap was not cancelled yet. Make sure to cancel the swap before trying to punish.")]
+    SwapNotCancelledYet,
+    #[error("The punish timelock has not expired yet.")]
+    PunishTimelockNotExpiredYet,
+}
+
+pub async fn punish(
+    swap_id: Uuid,
+    state: AliceState,
+    execution_params: ExecutionParams,
+    bitcoin_wallet: Arc<Wallet>,
+    db: Arc<Database>,
+    force: bool,
+) -> Result<Result<(Txid, AliceState), PunishError>> {
+    let state3 = if force {
+        match state {
+            AliceState::BtcLocked { state3, .. } => state3,
+            AliceState::XmrLocked { state3, .. } => state3,
+            AliceState::EncSigLearned { state3, .. } => state3,
+            AliceState::CancelTimelockExpired { state3, .. } => state3,
+            AliceState::BtcCancelled { state3, .. } => state3,
+            AliceState::BtcPunishable { state3, .. } => state3,
+            _ => bail!(
+                "Cannot punish swap {} because it is in state {} which is not punishable.",
+                swap_id,
+                state
+            ),
+        }
+    } else {
+        let state3 = match state {
+            AliceState::BtcCancelled { state3, .. } => state3,
+            AliceState::BtcPunishable { state3, .. } => state3,
+            _ => return Ok(Err(PunishError::SwapNotCancelledYet)),
+        };
+
+        let expired_timelocks = state3.expired_timelocks(bitcoin_wallet.as_ref()).await?;
+        if !matches!(expired_timelocks, ExpiredTimelocks::Punish) {
+            return Ok(Err(PunishError::PunishTimelockNotExpiredYet));
+        }
+
+        state3
+    };
+
+    let signed_tx_punish = build_bitcoin_punish_transaction(
+        &state3.tx_lock,
+        state3.cancel_timelock,
+        &state3.punish_address,
+        state3.punish_timelock,
+        state3.tx_punish_sig_bob.clone(),
+        state3.a.clone(),
+        state3.B,
+        state3.tx_fee_rate,
+    )?;
+
+    let txid = publish_bitcoin_punish_transaction(
+        signed_tx_punish,
+        &state3.punish_address,
+        state3.tx_fee_rate,
+        bitcoin_wallet,
+        execution_params,
+    )
+    .await?;
+
+    let state = AliceState::BtcPunished;
+    let db_state = (&state).into();
+    db.insert_latest_state(swap_id, Swap::Alice(db_state))
+        .await?;
+
+    Ok(Ok((txid, state)))
+}
@@ -1,5 +1,5 @@
 use crate::{
-    monero,
+    bitcoin, monero,
     network::request_response::{CborCodec, Swap, TIMEOUT},
     protocol::bob::QuoteRequest,
 };
@@ -27,8 +27,30 @@ pub enum OutEvent {
 }
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
-pub struct QuoteResponse {
-    pub xmr_amount: monero::Amount,
+pub enum QuoteResponse {
+    Quote { xmr_amount: monero::Amount },
+    Rejected { reason: RejectReason },
+}
+
+/// The reason why Alice refused to quote for a request.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, thiserror::Error)]
+pub enum RejectReason {
+    #[error("no price is currently available")]
+    PriceUnavailable,
+    #[error("amount is below the minimum of {min}")]
+    AmountTooLow {
+        #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+        min: bitcoin::Amount,
+    },
+    #[error("amount is above the maximum of {max}")]
+    AmountTooHigh {
+        #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+        max: bitcoin::Amount,
+    },
+    #[error("not enough XMR available to fill the order")]
+    InsufficientLiquidity,
+    #[error("the quote could not be computed")]
+    Unavailable,
 }
 
 impl From<RequestResponseEvent<QuoteRequest, QuoteResponse>> for OutEvent {
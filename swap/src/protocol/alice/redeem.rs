@@ -0,0 +1,79 @@
+use crate::{
+    bitcoin::{ExpiredTimelocks, Txid, Wallet},
+    database::{Database, Swap},
+    execution_params::ExecutionParams,
+    protocol::alice::{
+        steps::{
+            build_bitcoin_redeem_transaction, publish_bitcoin_redeem_transaction,
+            wait_for_bitcoin_redeem_transaction_finality,
+        },
+        AliceState,
+    },
+};
+use anyhow::{bail, Result};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error, Clone, Copy)]
+pub enum RedeemError {
+    #[error("The cancel timelock has already expired, Bob may cancel the swap at any time.")]
+    CancelTimelockExpired,
+}
+
+pub async fn redeem(
+    swap_id: Uuid,
+    state: AliceState,
+    execution_params: ExecutionParams,
+    bitcoin_wallet: Arc<Wallet>,
+    db: Arc<Database>,
+    force: bool,
+) -> Result<Result<(Txid, AliceState), RedeemError>> {
+    let (state3, encrypted_signature) = match state {
+        AliceState::EncSigLearned {
+            state3,
+            encrypted_signature,
+            ..
+        } => (state3, encrypted_signature),
+        _ => bail!(
+            "Cannot redeem swap {} because it is in state {} which is not redeemable.",
+            swap_id,
+            state
+        ),
+    };
+
+    if !force {
+        let expired_timelocks = state3.expired_timelocks(bitcoin_wallet.as_ref()).await?;
+        if !matches!(expired_timelocks, ExpiredTimelocks::None) {
+            return Ok(Err(RedeemError::CancelTimelockExpired));
+        }
+    }
+
+    let tx = build_bitcoin_redeem_transaction(
+        *encrypted_signature,
+        &state3.tx_lock,
+        state3.a.clone(),
+        state3.s_a,
+        state3.B,
+        &state3.redeem_address,
+        state3.tx_fee_rate,
+    )?;
+
+    let txid = publish_bitcoin_redeem_transaction(tx.clone(), bitcoin_wallet.clone()).await?;
+    wait_for_bitcoin_redeem_transaction_finality(
+        tx,
+        &state3.tx_lock,
+        state3.cancel_timelock,
+        &state3.redeem_address,
+        state3.tx_fee_rate,
+        bitcoin_wallet,
+        execution_params,
+    )
+    .await?;
+
+    let state = AliceState::BtcRedeemed;
+    let db_state = (&state).into();
+    db.insert_latest_state(swap_id, Swap::Alice(db_state))
+        .await?;
+
+    Ok(Ok((txid, state)))
+}
@@ -19,6 +19,7 @@ use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use std::fmt;
+use uuid::Uuid;
 
 #[derive(Debug)]
 pub enum AliceState {
@@ -31,27 +32,35 @@ pub enum AliceState {
         state3: Box<State3>,
     },
     XmrLocked {
+        /// The height of the Monero lock transaction, `None` for swaps that
+        /// locked before we recorded it, whose refund scans the whole chain.
+        monero_wallet_restore_blockheight: Option<monero::BlockHeight>,
         state3: Box<State3>,
     },
     EncSigLearned {
+        monero_wallet_restore_blockheight: Option<monero::BlockHeight>,
         encrypted_signature: Box<bitcoin::EncryptedSignature>,
         state3: Box<State3>,
     },
     BtcRedeemed,
     BtcCancelled {
+        monero_wallet_restore_blockheight: Option<monero::BlockHeight>,
         tx_cancel: Box<TxCancel>,
         state3: Box<State3>,
     },
     BtcRefunded {
+        monero_wallet_restore_blockheight: Option<monero::BlockHeight>,
         spend_key: monero::PrivateKey,
         state3: Box<State3>,
     },
     BtcPunishable {
+        monero_wallet_restore_blockheight: Option<monero::BlockHeight>,
         tx_refund: TxRefund,
         state3: Box<State3>,
     },
     XmrRefunded,
     CancelTimelockExpired {
+        monero_wallet_restore_blockheight: Option<monero::BlockHeight>,
         state3: Box<State3>,
     },
     BtcPunished,
@@ -90,6 +99,7 @@ pub struct State0 {
     pub punish_timelock: PunishTimelock,
     pub redeem_address: bitcoin::Address,
     pub punish_address: bitcoin::Address,
+    pub tx_fee_rate_bounds: bitcoin::FeeRateBounds,
 }
 
 impl State0 {
@@ -121,6 +131,7 @@ impl State0 {
             xmr,
             cancel_timelock: execution_params.bitcoin_cancel_timelock,
             punish_timelock: execution_params.bitcoin_punish_timelock,
+            tx_fee_rate_bounds: bitcoin_wallet.fee_estimation.presigned_fee_rate_bounds(),
         })
     }
 
@@ -133,6 +144,16 @@ impl State0 {
                 .ok_or_else(|| anyhow!("S_b is not a monero curve point"))?,
         )?;
 
+        let tx_fee_rate = self.tx_fee_rate_bounds.clamp(msg.tx_fee_rate);
+        if tx_fee_rate != msg.tx_fee_rate {
+            tracing::debug!(
+                "Bob proposed a fee rate of {} for the presigned transactions, using {}",
+                msg.tx_fee_rate,
+                tx_fee_rate
+            );
+        }
+        bitcoin::ensure_lock_amount_covers_fees(self.btc, &msg.refund_address, tx_fee_rate)?;
+
         let v = self.v_a + msg.v_b;
 
         Ok(State1 {
@@ -151,6 +172,7 @@ impl State0 {
             refund_address: msg.refund_address,
             redeem_address: self.redeem_address,
             punish_address: self.punish_address,
+            tx_fee_rate,
         })
     }
 }
@@ -173,6 +195,7 @@ pub struct State1 {
     refund_address: bitcoin::Address,
     redeem_address: bitcoin::Address,
     punish_address: bitcoin::Address,
+    tx_fee_rate: bitcoin::FeeRate,
 }
 
 impl State1 {
@@ -187,6 +210,7 @@ impl State1 {
             v_a: self.v_a,
             redeem_address: self.redeem_address.clone(),
             punish_address: self.punish_address.clone(),
+            tx_fee_rate: self.tx_fee_rate,
         }
     }
 
@@ -206,6 +230,7 @@ impl State1 {
             redeem_address: self.redeem_address,
             punish_address: self.punish_address,
             tx_lock: msg.tx_lock,
+            tx_fee_rate: self.tx_fee_rate,
         }
     }
 }
@@ -227,14 +252,20 @@ pub struct State2 {
     redeem_address: bitcoin::Address,
     punish_address: bitcoin::Address,
     tx_lock: bitcoin::TxLock,
+    tx_fee_rate: bitcoin::FeeRate,
 }
 
 impl State2 {
     pub fn next_message(&self) -> Message3 {
-        let tx_cancel =
-            bitcoin::TxCancel::new(&self.tx_lock, self.cancel_timelock, self.a.public(), self.B);
+        let tx_cancel = bitcoin::TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.a.public(),
+            self.B,
+            self.tx_fee_rate,
+        );
 
-        let tx_refund = bitcoin::TxRefund::new(&tx_cancel, &self.refund_address);
+        let tx_refund = bitcoin::TxRefund::new(&tx_cancel, &self.refund_address, self.tx_fee_rate);
         // Alice encsigns the refund transaction(bitcoin) digest with Bob's monero
         // pubkey(S_b). The refund transaction spends the output of
         // tx_lock_bitcoin to Bob's refund address.
@@ -250,12 +281,21 @@ impl State2 {
     }
 
     pub fn receive(self, msg: Message4) -> Result<State3> {
-        let tx_cancel =
-            bitcoin::TxCancel::new(&self.tx_lock, self.cancel_timelock, self.a.public(), self.B);
+        let tx_cancel = bitcoin::TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.a.public(),
+            self.B,
+            self.tx_fee_rate,
+        );
         bitcoin::verify_sig(&self.B, &tx_cancel.digest(), &msg.tx_cancel_sig)
             .context("Failed to verify cancel transaction")?;
-        let tx_punish =
-            bitcoin::TxPunish::new(&tx_cancel, &self.punish_address, self.punish_timelock);
+        let tx_punish = bitcoin::TxPunish::new(
+            &tx_cancel,
+            &self.punish_address,
+            self.punish_timelock,
+            self.tx_fee_rate,
+        );
         bitcoin::verify_sig(&self.B, &tx_punish.digest(), &msg.tx_punish_sig)
             .context("Failed to verify punish transaction")?;
 
@@ -274,6 +314,7 @@ impl State2 {
             redeem_address: self.redeem_address,
             punish_address: self.punish_address,
             tx_lock: self.tx_lock,
+            tx_fee_rate: self.tx_fee_rate,
             tx_punish_sig_bob: msg.tx_punish_sig,
             tx_cancel_sig_bob: msg.tx_cancel_sig,
         })
@@ -297,11 +338,22 @@ pub struct State3 {
     pub redeem_address: bitcoin::Address,
     pub punish_address: bitcoin::Address,
     pub tx_lock: bitcoin::TxLock,
+    #[serde(default = "bitcoin::FeeRate::legacy")]
+    pub tx_fee_rate: bitcoin::FeeRate,
     pub tx_punish_sig_bob: bitcoin::Signature,
     pub tx_cancel_sig_bob: bitcoin::Signature,
 }
 
 impl State3 {
+    /// The address that Monero is locked to.
+    pub fn monero_lock_address(&self, network: monero::Network) -> monero::Address {
+        let S_a = monero::PublicKey::from_private_key(&monero::PrivateKey {
+            scalar: self.s_a.into_ed25519(),
+        });
+
+        monero::Address::standard(network, S_a + self.S_b_monero, self.v.public().into())
+    }
+
     pub async fn wait_for_cancel_timelock_to_expire<W>(&self, bitcoin_wallet: &W) -> Result<()>
     where
         W: WatchForRawTransaction + TransactionBlockHeight + GetBlockHeight,
@@ -343,6 +395,8 @@ pub struct State4 {
     redeem_address: bitcoin::Address,
     punish_address: bitcoin::Address,
     tx_lock: bitcoin::TxLock,
+    #[serde(default = "bitcoin::FeeRate::legacy")]
+    tx_fee_rate: bitcoin::FeeRate,
     tx_punish_sig_bob: bitcoin::Signature,
     tx_cancel_sig_bob: bitcoin::Signature,
 }
@@ -374,6 +428,7 @@ impl State4 {
             redeem_address: self.redeem_address,
             punish_address: self.punish_address,
             tx_lock: self.tx_lock,
+            tx_fee_rate: self.tx_fee_rate,
             tx_lock_proof,
             tx_punish_sig_bob: self.tx_punish_sig_bob,
             tx_cancel_sig_bob: self.tx_cancel_sig_bob,
@@ -385,10 +440,19 @@ impl State4 {
         &self,
         bitcoin_wallet: &W,
     ) -> Result<()> {
-        let tx_cancel =
-            bitcoin::TxCancel::new(&self.tx_lock, self.cancel_timelock, self.a.public(), self.B);
-        let tx_punish =
-            bitcoin::TxPunish::new(&tx_cancel, &self.punish_address, self.punish_timelock);
+        let tx_cancel = bitcoin::TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.a.public(),
+            self.B,
+            self.tx_fee_rate,
+        );
+        let tx_punish = bitcoin::TxPunish::new(
+            &tx_cancel,
+            &self.punish_address,
+            self.punish_timelock,
+            self.tx_fee_rate,
+        );
 
         {
             let sig_a = self.a.sign(tx_cancel.digest());
@@ -435,6 +499,8 @@ pub struct State5 {
     redeem_address: bitcoin::Address,
     punish_address: bitcoin::Address,
     tx_lock: bitcoin::TxLock,
+    #[serde(default = "bitcoin::FeeRate::legacy")]
+    tx_fee_rate: bitcoin::FeeRate,
     tx_lock_proof: monero::TransferProof,
 
     tx_punish_sig_bob: bitcoin::Signature,
@@ -444,8 +510,9 @@ pub struct State5 {
 }
 
 impl State5 {
-    pub fn next_message(&self) -> TransferProof {
+    pub fn next_message(&self, swap_id: Uuid) -> TransferProof {
         TransferProof {
+            swap_id,
             tx_lock_proof: self.tx_lock_proof.clone(),
         }
     }
@@ -464,6 +531,7 @@ impl State5 {
             redeem_address: self.redeem_address,
             punish_address: self.punish_address,
             tx_lock: self.tx_lock,
+            tx_fee_rate: self.tx_fee_rate,
             tx_punish_sig_bob: self.tx_punish_sig_bob,
             tx_redeem_encsig: msg.tx_redeem_encsig,
             lock_xmr_fee: self.lock_xmr_fee,
@@ -485,6 +553,8 @@ pub struct State6 {
     redeem_address: bitcoin::Address,
     punish_address: bitcoin::Address,
     tx_lock: bitcoin::TxLock,
+    #[serde(default = "bitcoin::FeeRate::legacy")]
+    tx_fee_rate: bitcoin::FeeRate,
 
     tx_punish_sig_bob: bitcoin::Signature,
     tx_redeem_encsig: bitcoin::EncryptedSignature,
@@ -498,7 +568,8 @@ impl State6 {
     ) -> Result<()> {
         let adaptor = Adaptor::<Sha256, Deterministic<Sha256>>::default();
 
-        let tx_redeem = bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address);
+        let tx_redeem =
+            bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address, self.tx_fee_rate);
 
         let sig_a = self.a.sign(tx_redeem.digest());
         let sig_b =
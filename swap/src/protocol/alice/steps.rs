@@ -1,18 +1,15 @@
 use crate::{
     bitcoin,
     bitcoin::{
-        poll_until_block_height_is_gte, BlockHeight, BroadcastSignedTransaction, CancelTimelock,
-        EncryptedSignature, GetBlockHeight, GetRawTransaction, PunishTimelock,
-        TransactionBlockHeight, TxCancel, TxLock, TxRefund, WaitForTransactionFinality,
-        WatchForRawTransaction,
+        poll_until_relative_timelock_is_expired, BroadcastSignedTransaction, BumpFeeUntilConfirmed,
+        CancelTimelock, CpfpPackage, Deadline, EncryptedSignature, GetBlockHeight,
+        GetRawTransaction, PunishTimelock, TransactionBlockHeight, TxCancel, TxLock, TxRefund,
+        WaitForTransactionFinality, WatchForRawTransaction,
     },
     execution_params::ExecutionParams,
     monero,
     monero::Transfer,
-    protocol::{
-        alice,
-        alice::{event_loop::EventLoopHandle, TransferProof},
-    },
+    protocol::{alice, alice::event_loop::EventLoopHandle},
 };
 use anyhow::{Context, Result};
 use ecdsa_fun::{adaptor::Adaptor, nonce::Deterministic};
@@ -29,17 +26,19 @@ use tracing::info;
 // TODO(Franck): Use helper functions from xmr-btc instead of re-writing them
 // here
 pub async fn wait_for_locked_bitcoin<W>(
-    lock_bitcoin_txid: bitcoin::Txid,
+    tx_lock: &bitcoin::TxLock,
     bitcoin_wallet: Arc<W>,
     execution_params: ExecutionParams,
 ) -> Result<()>
 where
     W: WatchForRawTransaction + WaitForTransactionFinality,
 {
+    let lock_bitcoin_txid = tx_lock.txid();
+
     // We assume we will see Bob's transaction in the mempool first.
     timeout(
         execution_params.bob_time_to_act,
-        bitcoin_wallet.watch_for_raw_transaction(lock_bitcoin_txid),
+        bitcoin_wallet.watch_for_raw_transaction(lock_bitcoin_txid, tx_lock.script_pubkey()),
     )
     .await
     .context("Failed to find lock Bitcoin tx")??;
@@ -57,7 +56,7 @@ pub async fn lock_xmr<W>(
     state3: alice::State3,
     event_loop_handle: &mut EventLoopHandle,
     monero_wallet: Arc<W>,
-) -> Result<()>
+) -> Result<monero::TransferProof>
 where
     W: Transfer,
 {
@@ -78,12 +77,10 @@ where
     //  Otherwise Alice might publish the lock tx twice!
 
     event_loop_handle
-        .send_transfer_proof(bob_peer_id, TransferProof {
-            tx_lock_proof: transfer_proof,
-        })
+        .send_transfer_proof(bob_peer_id, transfer_proof.clone())
         .await?;
 
-    Ok(())
+    Ok(transfer_proof)
 }
 
 pub async fn wait_for_bitcoin_encrypted_signature(
@@ -106,10 +103,11 @@ pub fn build_bitcoin_redeem_transaction(
     s_a: cross_curve_dleq::Scalar,
     B: bitcoin::PublicKey,
     redeem_address: &bitcoin::Address,
+    tx_fee_rate: bitcoin::FeeRate,
 ) -> Result<bitcoin::Transaction> {
     let adaptor = Adaptor::<Sha256, Deterministic<Sha256>>::default();
 
-    let tx_redeem = bitcoin::TxRedeem::new(tx_lock, redeem_address);
+    let tx_redeem = bitcoin::TxRedeem::new(tx_lock, redeem_address, tx_fee_rate);
 
     bitcoin::verify_encsig(
         B,
@@ -144,25 +142,61 @@ where
     Ok(txid)
 }
 
+/// Waits for the redeem transaction to be final, bumping its fee if it is
+/// unlikely to be confirmed before Bob can cancel the swap.
+pub async fn wait_for_bitcoin_redeem_transaction_finality<W>(
+    redeem_tx: bitcoin::Transaction,
+    tx_lock: &TxLock,
+    cancel_timelock: CancelTimelock,
+    redeem_address: &bitcoin::Address,
+    tx_fee_rate: bitcoin::FeeRate,
+    bitcoin_wallet: Arc<W>,
+    execution_params: ExecutionParams,
+) -> Result<()>
+where
+    W: BumpFeeUntilConfirmed + TransactionBlockHeight + WaitForTransactionFinality,
+{
+    let txid = redeem_tx.txid();
+    let deadline = bitcoin_wallet
+        .transaction_block_height(tx_lock.txid())
+        .await?
+        + cancel_timelock;
+    let package = CpfpPackage::new(
+        redeem_tx,
+        bitcoin::TxRedeem::fee(tx_fee_rate, redeem_address),
+    );
+
+    bitcoin_wallet
+        .bump_fee_until_confirmed(package, Deadline::Height(deadline))
+        .await?;
+    bitcoin_wallet
+        .wait_for_transaction_finality(txid, execution_params)
+        .await?;
+
+    Ok(())
+}
+
 pub async fn publish_cancel_transaction<W>(
     tx_lock: TxLock,
     a: bitcoin::SecretKey,
     B: bitcoin::PublicKey,
     cancel_timelock: CancelTimelock,
     tx_cancel_sig_bob: bitcoin::Signature,
+    tx_fee_rate: bitcoin::FeeRate,
     bitcoin_wallet: Arc<W>,
 ) -> Result<bitcoin::TxCancel>
 where
     W: GetRawTransaction + TransactionBlockHeight + GetBlockHeight + BroadcastSignedTransaction,
 {
     // First wait for cancel timelock to expire
-    let tx_lock_height = bitcoin_wallet
-        .transaction_block_height(tx_lock.txid())
-        .await?;
-    poll_until_block_height_is_gte(bitcoin_wallet.as_ref(), tx_lock_height + cancel_timelock)
-        .await?;
+    poll_until_relative_timelock_is_expired(
+        bitcoin_wallet.as_ref(),
+        tx_lock.txid(),
+        cancel_timelock,
+    )
+    .await?;
 
-    let tx_cancel = bitcoin::TxCancel::new(&tx_lock, cancel_timelock, a.public(), B);
+    let tx_cancel = bitcoin::TxCancel::new(&tx_lock, cancel_timelock, a.public(), B, tx_fee_rate);
 
     // If Bob hasn't yet broadcasted the tx cancel, we do it
     if bitcoin_wallet
@@ -195,22 +229,26 @@ where
 
 pub async fn wait_for_bitcoin_refund<W>(
     tx_cancel: &TxCancel,
-    cancel_tx_height: BlockHeight,
     punish_timelock: PunishTimelock,
     refund_address: &bitcoin::Address,
+    tx_fee_rate: bitcoin::FeeRate,
     bitcoin_wallet: Arc<W>,
 ) -> Result<(bitcoin::TxRefund, Option<bitcoin::Transaction>)>
 where
-    W: GetBlockHeight + WatchForRawTransaction,
+    W: GetBlockHeight + TransactionBlockHeight + WatchForRawTransaction,
 {
-    let punish_timelock_expired =
-        poll_until_block_height_is_gte(bitcoin_wallet.as_ref(), cancel_tx_height + punish_timelock);
+    let punish_timelock_expired = poll_until_relative_timelock_is_expired(
+        bitcoin_wallet.as_ref(),
+        tx_cancel.txid(),
+        punish_timelock,
+    );
 
-    let tx_refund = bitcoin::TxRefund::new(tx_cancel, refund_address);
+    let tx_refund = bitcoin::TxRefund::new(tx_cancel, refund_address, tx_fee_rate);
 
     // TODO(Franck): This only checks the mempool, need to cater for the case where
     // the transaction goes directly in a block
-    let seen_refund_tx = bitcoin_wallet.watch_for_raw_transaction(tx_refund.txid());
+    let seen_refund_tx =
+        bitcoin_wallet.watch_for_raw_transaction(tx_refund.txid(), refund_address.script_pubkey());
 
     pin_mut!(punish_timelock_expired);
     pin_mut!(seen_refund_tx);
@@ -254,9 +292,11 @@ pub fn build_bitcoin_punish_transaction(
     tx_punish_sig_bob: bitcoin::Signature,
     a: bitcoin::SecretKey,
     B: bitcoin::PublicKey,
+    tx_fee_rate: bitcoin::FeeRate,
 ) -> Result<bitcoin::Transaction> {
-    let tx_cancel = bitcoin::TxCancel::new(&tx_lock, cancel_timelock, a.public(), B);
-    let tx_punish = bitcoin::TxPunish::new(&tx_cancel, &punish_address, punish_timelock);
+    let tx_cancel = bitcoin::TxCancel::new(&tx_lock, cancel_timelock, a.public(), B, tx_fee_rate);
+    let tx_punish =
+        bitcoin::TxPunish::new(&tx_cancel, &punish_address, punish_timelock, tx_fee_rate);
 
     let sig_a = a.sign(tx_punish.digest());
     let sig_b = tx_punish_sig_bob;
@@ -270,14 +310,30 @@ pub fn build_bitcoin_punish_transaction(
 
 pub async fn publish_bitcoin_punish_transaction<W>(
     punish_tx: bitcoin::Transaction,
+    punish_address: &bitcoin::Address,
+    tx_fee_rate: bitcoin::FeeRate,
     bitcoin_wallet: Arc<W>,
     execution_params: ExecutionParams,
 ) -> Result<bitcoin::Txid>
 where
-    W: BroadcastSignedTransaction + WaitForTransactionFinality,
+    W: BroadcastSignedTransaction
+        + BumpFeeUntilConfirmed
+        + GetBlockHeight
+        + WaitForTransactionFinality,
 {
     let txid = bitcoin_wallet
-        .broadcast_signed_transaction(punish_tx)
+        .broadcast_signed_transaction(punish_tx.clone())
+        .await?;
+
+    // Bob can publish the refund transaction at any time, so aim for the next
+    // block.
+    let deadline = bitcoin_wallet.get_block_height().await?;
+    let package = CpfpPackage::new(
+        punish_tx,
+        bitcoin::TxPunish::fee(tx_fee_rate, punish_address),
+    );
+    bitcoin_wallet
+        .bump_fee_until_confirmed(package, Deadline::Height(deadline))
         .await?;
 
     bitcoin_wallet
@@ -2,15 +2,12 @@
 //! Alice holds XMR and wishes receive BTC.
 use crate::{
     bitcoin,
-    bitcoin::{
-        ExpiredTimelocks, TransactionBlockHeight, WaitForTransactionFinality,
-        WatchForRawTransaction,
-    },
+    bitcoin::{ExpiredTimelocks, WatchForRawTransaction},
     database,
     database::Database,
     execution_params::ExecutionParams,
     monero,
-    monero::CreateWalletForOutput,
+    monero::SweepToMainWallet,
     protocol::{
         alice,
         alice::{
@@ -19,8 +16,8 @@ use crate::{
                 build_bitcoin_punish_transaction, build_bitcoin_redeem_transaction,
                 extract_monero_private_key, lock_xmr, publish_bitcoin_punish_transaction,
                 publish_bitcoin_redeem_transaction, publish_cancel_transaction,
-                wait_for_bitcoin_encrypted_signature, wait_for_bitcoin_refund,
-                wait_for_locked_bitcoin,
+                wait_for_bitcoin_encrypted_signature, wait_for_bitcoin_redeem_transaction_finality,
+                wait_for_bitcoin_refund, wait_for_locked_bitcoin,
             },
             AliceState,
         },
@@ -96,7 +93,7 @@ async fn run_until_internal(
                 bob_peer_id,
             } => {
                 let _ = wait_for_locked_bitcoin(
-                    state3.tx_lock.txid(),
+                    &state3.tx_lock,
                     bitcoin_wallet.clone(),
                     execution_params,
                 )
@@ -126,7 +123,7 @@ async fn run_until_internal(
                 bob_peer_id,
                 state3,
             } => {
-                lock_xmr(
+                let transfer_proof = lock_xmr(
                     bob_peer_id,
                     *state3.clone(),
                     &mut event_loop_handle,
@@ -134,7 +131,16 @@ async fn run_until_internal(
                 )
                 .await?;
 
-                let state = AliceState::XmrLocked { state3 };
+                // If we ever have to refund the Monero, we scan for it from the block of the
+                // lock transaction.
+                let monero_wallet_restore_blockheight = monero_wallet
+                    .wait_for_transfer_height(&transfer_proof.tx_hash())
+                    .await?;
+
+                let state = AliceState::XmrLocked {
+                    monero_wallet_restore_blockheight: Some(monero_wallet_restore_blockheight),
+                    state3,
+                };
 
                 let db_state = (&state).into();
                 db.insert_latest_state(swap_id, database::Swap::Alice(db_state))
@@ -151,7 +157,10 @@ async fn run_until_internal(
                 )
                 .await
             }
-            AliceState::XmrLocked { state3 } => {
+            AliceState::XmrLocked {
+                monero_wallet_restore_blockheight,
+                state3,
+            } => {
                 let state = match state3.expired_timelocks(bitcoin_wallet.as_ref()).await? {
                     ExpiredTimelocks::None => {
                         let wait_for_enc_sig =
@@ -164,14 +173,21 @@ async fn run_until_internal(
                         pin_mut!(cancel_timelock_expires);
 
                         match select(cancel_timelock_expires, wait_for_enc_sig).await {
-                            Either::Left(_) => AliceState::CancelTimelockExpired { state3 },
+                            Either::Left(_) => AliceState::CancelTimelockExpired {
+                                monero_wallet_restore_blockheight,
+                                state3,
+                            },
                             Either::Right((enc_sig, _)) => AliceState::EncSigLearned {
+                                monero_wallet_restore_blockheight,
                                 state3,
                                 encrypted_signature: Box::new(enc_sig?),
                             },
                         }
                     }
-                    _ => AliceState::CancelTimelockExpired { state3 },
+                    _ => AliceState::CancelTimelockExpired {
+                        monero_wallet_restore_blockheight,
+                        state3,
+                    },
                 };
 
                 let db_state = (&state).into();
@@ -190,6 +206,7 @@ async fn run_until_internal(
                 .await
             }
             AliceState::EncSigLearned {
+                monero_wallet_restore_blockheight,
                 state3,
                 encrypted_signature,
             } => {
@@ -202,14 +219,26 @@ async fn run_until_internal(
                             state3.s_a,
                             state3.B,
                             &state3.redeem_address,
+                            state3.tx_fee_rate,
                         ) {
                             Ok(tx) => {
-                                match publish_bitcoin_redeem_transaction(tx, bitcoin_wallet.clone())
-                                    .await
+                                match publish_bitcoin_redeem_transaction(
+                                    tx.clone(),
+                                    bitcoin_wallet.clone(),
+                                )
+                                .await
                                 {
-                                    Ok(txid) => {
-                                        let publishded_redeem_tx = bitcoin_wallet
-                                            .wait_for_transaction_finality(txid, execution_params)
+                                    Ok(_) => {
+                                        let publishded_redeem_tx =
+                                            wait_for_bitcoin_redeem_transaction_finality(
+                                                tx,
+                                                &state3.tx_lock,
+                                                state3.cancel_timelock,
+                                                &state3.redeem_address,
+                                                state3.tx_fee_rate,
+                                                bitcoin_wallet.clone(),
+                                                execution_params,
+                                            )
                                             .await;
 
                                         match publishded_redeem_tx {
@@ -227,7 +256,10 @@ async fn run_until_internal(
                                             )
                                             .await?;
 
-                                        AliceState::CancelTimelockExpired { state3 }
+                                        AliceState::CancelTimelockExpired {
+                                            monero_wallet_restore_blockheight,
+                                            state3,
+                                        }
                                     }
                                 }
                             }
@@ -237,11 +269,17 @@ async fn run_until_internal(
                                     .wait_for_cancel_timelock_to_expire(bitcoin_wallet.as_ref())
                                     .await?;
 
-                                AliceState::CancelTimelockExpired { state3 }
+                                AliceState::CancelTimelockExpired {
+                                    monero_wallet_restore_blockheight,
+                                    state3,
+                                }
                             }
                         }
                     }
-                    _ => AliceState::CancelTimelockExpired { state3 },
+                    _ => AliceState::CancelTimelockExpired {
+                        monero_wallet_restore_blockheight,
+                        state3,
+                    },
                 };
 
                 let db_state = (&state).into();
@@ -259,18 +297,23 @@ async fn run_until_internal(
                 )
                 .await
             }
-            AliceState::CancelTimelockExpired { state3 } => {
+            AliceState::CancelTimelockExpired {
+                monero_wallet_restore_blockheight,
+                state3,
+            } => {
                 let tx_cancel = publish_cancel_transaction(
                     state3.tx_lock.clone(),
                     state3.a.clone(),
                     state3.B,
                     state3.cancel_timelock,
                     state3.tx_cancel_sig_bob.clone(),
+                    state3.tx_fee_rate,
                     bitcoin_wallet.clone(),
                 )
                 .await?;
 
                 let state = AliceState::BtcCancelled {
+                    monero_wallet_restore_blockheight,
                     state3,
                     tx_cancel: Box::new(tx_cancel),
                 };
@@ -289,16 +332,16 @@ async fn run_until_internal(
                 )
                 .await
             }
-            AliceState::BtcCancelled { state3, tx_cancel } => {
-                let tx_cancel_height = bitcoin_wallet
-                    .transaction_block_height(tx_cancel.txid())
-                    .await?;
-
+            AliceState::BtcCancelled {
+                monero_wallet_restore_blockheight,
+                state3,
+                tx_cancel,
+            } => {
                 let (tx_refund, published_refund_tx) = wait_for_bitcoin_refund(
                     &tx_cancel,
-                    tx_cancel_height,
                     state3.punish_timelock,
                     &state3.refund_address,
+                    state3.tx_fee_rate,
                     bitcoin_wallet.clone(),
                 )
                 .await?;
@@ -306,7 +349,11 @@ async fn run_until_internal(
                 // TODO(Franck): Review error handling
                 match published_refund_tx {
                     None => {
-                        let state = AliceState::BtcPunishable { tx_refund, state3 };
+                        let state = AliceState::BtcPunishable {
+                            monero_wallet_restore_blockheight,
+                            tx_refund,
+                            state3,
+                        };
                         let db_state = (&state).into();
                         db.insert_latest_state(swap_id, database::Swap::Alice(db_state))
                             .await?;
@@ -332,7 +379,11 @@ async fn run_until_internal(
                             state3.S_b_bitcoin,
                         )?;
 
-                        let state = AliceState::BtcRefunded { spend_key, state3 };
+                        let state = AliceState::BtcRefunded {
+                            monero_wallet_restore_blockheight,
+                            spend_key,
+                            state3,
+                        };
                         let db_state = (&state).into();
                         db.insert_latest_state(swap_id, database::Swap::Alice(db_state))
                             .await?;
@@ -350,12 +401,28 @@ async fn run_until_internal(
                     }
                 }
             }
-            AliceState::BtcRefunded { spend_key, state3 } => {
+            AliceState::BtcRefunded {
+                monero_wallet_restore_blockheight,
+                spend_key,
+                state3,
+            } => {
                 let view_key = state3.v;
 
-                monero_wallet
-                    .create_and_load_wallet_for_output(spend_key, view_key, None)
+                let tx_hashes = monero_wallet
+                    .sweep_to_main_wallet(
+                        swap_id,
+                        spend_key,
+                        view_key,
+                        monero_wallet_restore_blockheight,
+                        execution_params.monero_finality_confirmations,
+                    )
                     .await?;
+                let tx_hash_list = tx_hashes
+                    .iter()
+                    .map(|tx_hash| tx_hash.0.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                info!("Refunded Monero to main wallet in {}", tx_hash_list);
 
                 let state = AliceState::XmrRefunded;
                 let db_state = (&state).into();
@@ -363,7 +430,11 @@ async fn run_until_internal(
                     .await?;
                 Ok(state)
             }
-            AliceState::BtcPunishable { tx_refund, state3 } => {
+            AliceState::BtcPunishable {
+                monero_wallet_restore_blockheight,
+                tx_refund,
+                state3,
+            } => {
                 let signed_tx_punish = build_bitcoin_punish_transaction(
                     &state3.tx_lock,
                     state3.cancel_timelock,
@@ -372,15 +443,21 @@ async fn run_until_internal(
                     state3.tx_punish_sig_bob.clone(),
                     state3.a.clone(),
                     state3.B,
+                    state3.tx_fee_rate,
                 )?;
 
                 let punish_tx_finalised = publish_bitcoin_punish_transaction(
                     signed_tx_punish,
+                    &state3.punish_address,
+                    state3.tx_fee_rate,
                     bitcoin_wallet.clone(),
                     execution_params,
                 );
 
-                let refund_tx_seen = bitcoin_wallet.watch_for_raw_transaction(tx_refund.txid());
+                let refund_tx_seen = bitcoin_wallet.watch_for_raw_transaction(
+                    tx_refund.txid(),
+                    state3.refund_address.script_pubkey(),
+                );
 
                 pin_mut!(punish_tx_finalised);
                 pin_mut!(refund_tx_seen);
@@ -394,7 +471,11 @@ async fn run_until_internal(
                             state3.a.clone(),
                             state3.S_b_bitcoin,
                         )?;
-                        let state = AliceState::BtcRefunded { spend_key, state3 };
+                        let state = AliceState::BtcRefunded {
+                            monero_wallet_restore_blockheight,
+                            spend_key,
+                            state3,
+                        };
                         let db_state = (&state).into();
                         db.insert_latest_state(swap_id, database::Swap::Alice(db_state))
                             .await?;
@@ -50,6 +50,49 @@ pub struct Swap {
     pub swap_id: Uuid,
 }
 
+/// A swap loaded from the database for cancelling and refunding it.
+///
+/// Unlike [`Swap`] this only needs the Bitcoin wallet and does not connect to
+/// Alice, so Bob can get his bitcoin back even if Alice or the Monero
+/// infrastructure is unavailable.
+pub struct Recovery {
+    pub state: BobState,
+    pub db: Database,
+    pub bitcoin_wallet: Arc<bitcoin::Wallet>,
+    pub execution_params: ExecutionParams,
+    pub swap_id: Uuid,
+}
+
+impl Recovery {
+    pub fn load(
+        db: Database,
+        swap_id: Uuid,
+        bitcoin_wallet: Arc<bitcoin::Wallet>,
+        execution_params: ExecutionParams,
+    ) -> Result<Self> {
+        let state = load_state(&db, swap_id)?;
+
+        Ok(Self {
+            state,
+            db,
+            bitcoin_wallet,
+            execution_params,
+            swap_id,
+        })
+    }
+}
+
+fn load_state(db: &Database, swap_id: Uuid) -> Result<BobState> {
+    if let database::Swap::Bob(state) = db.get_state(swap_id)? {
+        Ok(state.into())
+    } else {
+        bail!(
+            "Trying to load swap with id {} for the wrong direction.",
+            swap_id
+        )
+    }
+}
+
 pub struct Builder {
     swap_id: Uuid,
     identity: Keypair,
@@ -63,6 +106,7 @@ pub struct Builder {
     monero_wallet: Arc<monero::Wallet>,
 
     init_params: InitParams,
+    coin_control: bitcoin::CoinControl,
     execution_params: ExecutionParams,
 }
 
@@ -96,6 +140,7 @@ impl Builder {
             bitcoin_wallet,
             monero_wallet,
             init_params: InitParams::None,
+            coin_control: bitcoin::CoinControl::default(),
             execution_params,
         }
     }
@@ -107,10 +152,22 @@ impl Builder {
         }
     }
 
+    /// Restricts the coins that fund the Bitcoin lock transaction of a new
+    /// swap.
+    pub fn with_coin_control(self, coin_control: bitcoin::CoinControl) -> Self {
+        Self {
+            coin_control,
+            ..self
+        }
+    }
+
     pub async fn build(self) -> Result<(bob::Swap, bob::EventLoop)> {
         match self.init_params {
             InitParams::New { btc_amount } => {
-                let initial_state = BobState::Started { btc_amount };
+                let initial_state = BobState::Started {
+                    btc_amount,
+                    coin_control: self.coin_control.clone(),
+                };
 
                 let (event_loop, event_loop_handle) = self.init_event_loop()?;
 
@@ -129,15 +186,7 @@ impl Builder {
             }
 
             InitParams::None => {
-                let resume_state =
-                    if let database::Swap::Bob(state) = self.db.get_state(self.swap_id)? {
-                        state.into()
-                    } else {
-                        bail!(
-                            "Trying to load swap with id {} for the wrong direction.",
-                            self.swap_id
-                        )
-                    };
+                let resume_state = load_state(&self.db, self.swap_id)?;
 
                 let (event_loop, event_loop_handle) = self.init_event_loop()?;
 
@@ -169,6 +218,7 @@ impl Builder {
             self.peer_id,
             self.alice_peer_id,
             self.alice_address.clone(),
+            self.swap_id,
             self.bitcoin_wallet.clone(),
         )
     }
@@ -262,11 +312,12 @@ impl Behaviour {
     pub fn start_execution_setup(
         &mut self,
         alice_peer_id: PeerId,
+        swap_id: Uuid,
         state0: State0,
         bitcoin_wallet: Arc<bitcoin::Wallet>,
     ) {
         self.execution_setup
-            .run(alice_peer_id, state0, bitcoin_wallet);
+            .run(alice_peer_id, swap_id, state0, bitcoin_wallet);
         info!("Start execution setup with {}", alice_peer_id);
     }
 
@@ -274,9 +325,13 @@ impl Behaviour {
     pub fn send_encrypted_signature(
         &mut self,
         alice: PeerId,
+        swap_id: Uuid,
         tx_redeem_encsig: bitcoin::EncryptedSignature,
     ) {
-        let msg = EncryptedSignature { tx_redeem_encsig };
+        let msg = EncryptedSignature {
+            swap_id,
+            tx_redeem_encsig,
+        };
         self.encrypted_signature.send(alice, msg);
         debug!("Encrypted signature sent");
     }
@@ -1,6 +1,7 @@
 use crate::{
     bitcoin::{ExpiredTimelocks, Txid, Wallet},
     database::{Database, Swap},
+    monero,
     protocol::bob::BobState,
 };
 use anyhow::{bail, Result};
@@ -23,8 +24,17 @@ pub async fn cancel(
     force: bool,
 ) -> Result<Result<(Txid, BobState), CancelError>> {
     let state4 = match state {
-        BobState::BtcLocked(state3) => state3.state4(),
-        BobState::XmrLockProofReceived { state, .. } => state.state4(),
+        // Scanning from the genesis block finds the Monero in any case
+        BobState::BtcLocked(state3) => state3.state4(
+            state3
+                .monero_wallet_restore_blockheight()
+                .unwrap_or(monero::BlockHeight { height: 0 }),
+        ),
+        BobState::XmrLockProofReceived {
+            state,
+            monero_wallet_restore_blockheight,
+            ..
+        } => state.state4(monero_wallet_restore_blockheight),
         BobState::XmrLocked(state4) => state4,
         BobState::EncSigSent(state4) => state4,
         BobState::CancelTimelockExpired(state4) => state4,
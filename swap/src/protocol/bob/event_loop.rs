@@ -12,7 +12,8 @@ use futures::FutureExt;
 use libp2p::{core::Multiaddr, PeerId};
 use std::sync::Arc;
 use tokio::sync::mpsc::{Receiver, Sender};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
 
 #[derive(Debug)]
 pub struct Channels<T> {
@@ -103,6 +104,7 @@ pub struct EventLoop {
     swarm: libp2p::Swarm<Behaviour>,
     bitcoin_wallet: Arc<bitcoin::Wallet>,
     alice_peer_id: PeerId,
+    swap_id: Uuid,
     recv_quote_response: Sender<QuoteResponse>,
     start_execution_setup: Receiver<State0>,
     done_execution_setup: Sender<Result<State2>>,
@@ -120,6 +122,7 @@ impl EventLoop {
         peer_id: PeerId,
         alice_peer_id: PeerId,
         alice_addr: Multiaddr,
+        swap_id: Uuid,
         bitcoin_wallet: Arc<bitcoin::Wallet>,
     ) -> Result<(Self, EventLoopHandle)> {
         let mut swarm = libp2p::swarm::SwarmBuilder::new(transport, behaviour, peer_id)
@@ -142,6 +145,7 @@ impl EventLoop {
         let event_loop = EventLoop {
             swarm,
             alice_peer_id,
+            swap_id,
             bitcoin_wallet,
             recv_quote_response: quote_response.sender,
             start_execution_setup: start_execution_setup.receiver,
@@ -182,10 +186,14 @@ impl EventLoop {
                             let _ = self.done_execution_setup.send(res.map(|state|*state)).await;
                         }
                         OutEvent::TransferProof{ msg, channel }=> {
-                            let _ = self.recv_transfer_proof.send(*msg).await;
-                            // Send back empty response so that the request/response protocol completes.
-                            if let Err(error) = self.swarm.transfer_proof.send_ack(channel) {
-                                error!("Failed to send Transfer Proof ack: {:?}", error);
+                            if msg.swap_id == self.swap_id {
+                                let _ = self.recv_transfer_proof.send(*msg).await;
+                                // Send back empty response so that the request/response protocol completes.
+                                if let Err(error) = self.swarm.transfer_proof.send_ack(channel) {
+                                    error!("Failed to send Transfer Proof ack: {:?}", error);
+                                }
+                            } else {
+                                warn!("Received transfer proof for unknown swap {}", msg.swap_id);
                             }
                         }
                         OutEvent::EncryptedSignatureAcknowledged => {
@@ -222,12 +230,12 @@ impl EventLoop {
                     if let Some(state0) = option {
                         let _ = self
                             .swarm
-                            .start_execution_setup(self.alice_peer_id, state0, self.bitcoin_wallet.clone());
+                            .start_execution_setup(self.alice_peer_id, self.swap_id, state0, self.bitcoin_wallet.clone());
                     }
                 },
                 encrypted_signature = self.send_encrypted_signature.recv().fuse() => {
                     if let Some(tx_redeem_encsig) = encrypted_signature {
-                        self.swarm.send_encrypted_signature(self.alice_peer_id, tx_redeem_encsig);
+                        self.swarm.send_encrypted_signature(self.alice_peer_id, self.swap_id, tx_redeem_encsig);
                     }
                 }
             }
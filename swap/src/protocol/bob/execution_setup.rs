@@ -11,15 +11,18 @@ use libp2p::PeerId;
 use libp2p_async_await::BehaviourOutEvent;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use uuid::Uuid;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Message0 {
+    pub(crate) swap_id: Uuid,
     pub(crate) B: crate::bitcoin::PublicKey,
     pub(crate) S_b_monero: monero::PublicKey,
     pub(crate) S_b_bitcoin: crate::bitcoin::PublicKey,
     pub(crate) dleq_proof_s_b: cross_curve_dleq::Proof,
     pub(crate) v_b: crate::monero::PrivateViewKey,
     pub(crate) refund_address: bitcoin::Address,
+    pub(crate) tx_fee_rate: crate::bitcoin::FeeRate,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -57,7 +60,7 @@ pub struct Behaviour {
 impl Default for Behaviour {
     fn default() -> Self {
         Self {
-            inner: libp2p_async_await::Behaviour::new(b"/comit/xmr/btc/execution_setup/1.0.0"),
+            inner: libp2p_async_await::Behaviour::new(b"/comit/xmr/btc/execution_setup/3.0.0"),
         }
     }
 }
@@ -66,6 +69,7 @@ impl Behaviour {
     pub fn run(
         &mut self,
         alice: PeerId,
+        swap_id: Uuid,
         state0: State0,
         bitcoin_wallet: Arc<crate::bitcoin::Wallet>,
     ) {
@@ -73,7 +77,7 @@ impl Behaviour {
             .do_protocol_dialer(alice, move |mut substream| async move {
                 substream
                     .write_message(
-                        &serde_cbor::to_vec(&state0.next_message())
+                        &serde_cbor::to_vec(&state0.next_message(swap_id))
                             .context("failed to serialize message0")?,
                     )
                     .await?;
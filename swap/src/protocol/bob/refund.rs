@@ -2,6 +2,7 @@ use crate::{
     bitcoin::Wallet,
     database::{Database, Swap},
     execution_params::ExecutionParams,
+    monero,
     protocol::bob::BobState,
 };
 use anyhow::{bail, Result};
@@ -22,8 +23,17 @@ pub async fn refund(
 ) -> Result<Result<BobState, SwapNotCancelledYet>> {
     let state4 = if force {
         match state {
-            BobState::BtcLocked(state3) => state3.state4(),
-            BobState::XmrLockProofReceived { state, .. } => state.state4(),
+            // Scanning from the genesis block finds the Monero in any case
+            BobState::BtcLocked(state3) => state3.state4(
+                state3
+                    .monero_wallet_restore_blockheight()
+                    .unwrap_or(monero::BlockHeight { height: 0 }),
+            ),
+            BobState::XmrLockProofReceived {
+                state,
+                monero_wallet_restore_blockheight,
+                ..
+            } => state.state4(monero_wallet_restore_blockheight),
             BobState::XmrLocked(state4) => state4,
             BobState::EncSigSent(state4) => state4,
             BobState::CancelTimelockExpired(state4) => state4,
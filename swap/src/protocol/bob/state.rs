@@ -3,35 +3,36 @@ use crate::{
         self, current_epoch, wait_for_cancel_timelock_to_expire, BroadcastSignedTransaction,
         BuildTxLockPsbt, CancelTimelock, ExpiredTimelocks, GetBlockHeight, GetNetwork,
         GetRawTransaction, PunishTimelock, Transaction, TransactionBlockHeight, TxCancel, Txid,
-        WatchForRawTransaction,
+        WaitForTransactionFinality, WatchForRawTransaction,
     },
     execution_params::ExecutionParams,
     monero,
-    monero::{monero_private_key, InsufficientFunds, TransferProof},
+    monero::{monero_private_key, InvalidLockTransaction, TransferProof},
     protocol::{
         alice::{Message1, Message3},
         bob::{EncryptedSignature, Message0, Message2, Message4},
     },
 };
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use ecdsa_fun::{adaptor::Adaptor, nonce::Deterministic, Signature};
-use monero_harness::rpc::wallet::BlockHeight;
 use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use std::fmt;
+use uuid::Uuid;
 
 #[derive(Debug, Clone)]
 pub enum BobState {
     Started {
         btc_amount: bitcoin::Amount,
+        coin_control: bitcoin::CoinControl,
     },
     ExecutionSetupDone(State2),
     BtcLocked(State3),
     XmrLockProofReceived {
         state: State3,
         lock_transfer_proof: TransferProof,
-        monero_wallet_restore_blockheight: BlockHeight,
+        monero_wallet_restore_blockheight: monero::BlockHeight,
     },
     XmrLocked(State4),
     EncSigSent(State4),
@@ -41,6 +42,8 @@ pub enum BobState {
     BtcRefunded(State4),
     XmrRedeemed {
         tx_lock_id: bitcoin::Txid,
+        /// Empty unless the Monero was sent on to a receive address.
+        sweep_tx_hashes: Vec<monero::TxHash>,
     },
     BtcPunished {
         tx_lock_id: bitcoin::Txid,
@@ -83,6 +86,9 @@ pub struct State0 {
     punish_timelock: PunishTimelock,
     refund_address: bitcoin::Address,
     min_monero_confirmations: u32,
+    tx_fee_rate: bitcoin::FeeRate,
+    tx_fee_rate_bounds: bitcoin::FeeRateBounds,
+    coin_control: bitcoin::CoinControl,
 }
 
 impl State0 {
@@ -94,6 +100,9 @@ impl State0 {
         punish_timelock: PunishTimelock,
         refund_address: bitcoin::Address,
         min_monero_confirmations: u32,
+        tx_fee_rate: bitcoin::FeeRate,
+        tx_fee_rate_bounds: bitcoin::FeeRateBounds,
+        coin_control: bitcoin::CoinControl,
     ) -> Self {
         let b = bitcoin::SecretKey::new_random(rng);
 
@@ -112,11 +121,15 @@ impl State0 {
             punish_timelock,
             refund_address,
             min_monero_confirmations,
+            tx_fee_rate,
+            tx_fee_rate_bounds,
+            coin_control,
         }
     }
 
-    pub fn next_message(&self) -> Message0 {
+    pub fn next_message(&self, swap_id: Uuid) -> Message0 {
         Message0 {
+            swap_id,
             B: self.b.public(),
             S_b_monero: monero::PublicKey::from_private_key(&monero::PrivateKey {
                 scalar: self.s_b.into_ed25519(),
@@ -125,6 +138,7 @@ impl State0 {
             dleq_proof_s_b: self.dleq_proof_s_b.clone(),
             v_b: self.v_b,
             refund_address: self.refund_address.clone(),
+            tx_fee_rate: self.tx_fee_rate,
         }
     }
 
@@ -140,7 +154,20 @@ impl State0 {
                 .ok_or_else(|| anyhow!("S_a is not a monero curve point"))?,
         )?;
 
-        let tx_lock = bitcoin::TxLock::new(wallet, self.btc, msg.A, self.b.public()).await?;
+        if !self.tx_fee_rate_bounds.contains(msg.tx_fee_rate) {
+            bail!(
+                "Alice proposed a fee rate of {} for the presigned transactions, \
+                expected between {} and {}",
+                msg.tx_fee_rate,
+                self.tx_fee_rate_bounds.min,
+                self.tx_fee_rate_bounds.max
+            )
+        }
+        bitcoin::ensure_lock_amount_covers_fees(self.btc, &self.refund_address, msg.tx_fee_rate)?;
+
+        let tx_lock =
+            bitcoin::TxLock::new(wallet, self.btc, msg.A, self.b.public(), &self.coin_control)
+                .await?;
         let v = msg.v_a + self.v_b;
 
         Ok(State1 {
@@ -157,6 +184,7 @@ impl State0 {
             redeem_address: msg.redeem_address,
             punish_address: msg.punish_address,
             tx_lock,
+            tx_fee_rate: msg.tx_fee_rate,
             min_monero_confirmations: self.min_monero_confirmations,
         })
     }
@@ -177,6 +205,7 @@ pub struct State1 {
     redeem_address: bitcoin::Address,
     punish_address: bitcoin::Address,
     tx_lock: bitcoin::TxLock,
+    tx_fee_rate: bitcoin::FeeRate,
     min_monero_confirmations: u32,
 }
 
@@ -188,8 +217,14 @@ impl State1 {
     }
 
     pub fn receive(self, msg: Message3) -> Result<State2> {
-        let tx_cancel = TxCancel::new(&self.tx_lock, self.cancel_timelock, self.A, self.b.public());
-        let tx_refund = bitcoin::TxRefund::new(&tx_cancel, &self.refund_address);
+        let tx_cancel = TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.A,
+            self.b.public(),
+            self.tx_fee_rate,
+        );
+        let tx_refund = bitcoin::TxRefund::new(&tx_cancel, &self.refund_address, self.tx_fee_rate);
 
         bitcoin::verify_sig(&self.A, &tx_cancel.digest(), &msg.tx_cancel_sig)?;
         bitcoin::verify_encsig(
@@ -213,6 +248,7 @@ impl State1 {
             redeem_address: self.redeem_address,
             punish_address: self.punish_address,
             tx_lock: self.tx_lock,
+            tx_fee_rate: self.tx_fee_rate,
             tx_cancel_sig_a: msg.tx_cancel_sig,
             tx_refund_encsig: msg.tx_refund_encsig,
             min_monero_confirmations: self.min_monero_confirmations,
@@ -235,6 +271,8 @@ pub struct State2 {
     redeem_address: bitcoin::Address,
     punish_address: bitcoin::Address,
     tx_lock: bitcoin::TxLock,
+    #[serde(default = "bitcoin::FeeRate::legacy")]
+    tx_fee_rate: bitcoin::FeeRate,
     tx_cancel_sig_a: Signature,
     tx_refund_encsig: bitcoin::EncryptedSignature,
     min_monero_confirmations: u32,
@@ -242,10 +280,20 @@ pub struct State2 {
 
 impl State2 {
     pub fn next_message(&self) -> Message4 {
-        let tx_cancel = TxCancel::new(&self.tx_lock, self.cancel_timelock, self.A, self.b.public());
+        let tx_cancel = TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.A,
+            self.b.public(),
+            self.tx_fee_rate,
+        );
         let tx_cancel_sig = self.b.sign(tx_cancel.digest());
-        let tx_punish =
-            bitcoin::TxPunish::new(&tx_cancel, &self.punish_address, self.punish_timelock);
+        let tx_punish = bitcoin::TxPunish::new(
+            &tx_cancel,
+            &self.punish_address,
+            self.punish_timelock,
+            self.tx_fee_rate,
+        );
         let tx_punish_sig = self.b.sign(tx_punish.digest());
 
         Message4 {
@@ -254,13 +302,29 @@ impl State2 {
         }
     }
 
-    pub async fn lock_btc<W>(self, bitcoin_wallet: &W) -> Result<State3>
+    /// Locks the Bitcoin and remembers `monero_wallet_restore_blockheight`,
+    /// the Monero height Alice cannot lock at before seeing our lock.
+    pub async fn lock_btc<W>(
+        self,
+        bitcoin_wallet: &W,
+        monero_wallet_restore_blockheight: monero::BlockHeight,
+    ) -> Result<State3>
     where
         W: bitcoin::SignTxLock + bitcoin::BroadcastSignedTransaction,
     {
         let signed_tx_lock = bitcoin_wallet.sign_tx_lock(self.tx_lock.clone()).await?;
+        self.tx_lock.ensure_signed(&signed_tx_lock)?;
 
-        tracing::info!("{}", self.tx_lock.txid());
+        tracing::info!(
+            "Publishing lock transaction {} spending {}",
+            self.tx_lock.txid(),
+            self.tx_lock
+                .inputs()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
         let _ = bitcoin_wallet
             .broadcast_signed_transaction(signed_tx_lock)
             .await?;
@@ -278,9 +342,11 @@ impl State2 {
             refund_address: self.refund_address,
             redeem_address: self.redeem_address,
             tx_lock: self.tx_lock,
+            tx_fee_rate: self.tx_fee_rate,
             tx_cancel_sig_a: self.tx_cancel_sig_a,
             tx_refund_encsig: self.tx_refund_encsig,
             min_monero_confirmations: self.min_monero_confirmations,
+            monero_wallet_restore_blockheight: Some(monero_wallet_restore_blockheight),
         })
     }
 }
@@ -299,27 +365,38 @@ pub struct State3 {
     refund_address: bitcoin::Address,
     redeem_address: bitcoin::Address,
     tx_lock: bitcoin::TxLock,
+    #[serde(default = "bitcoin::FeeRate::legacy")]
+    tx_fee_rate: bitcoin::FeeRate,
     tx_cancel_sig_a: Signature,
     tx_refund_encsig: bitcoin::EncryptedSignature,
     min_monero_confirmations: u32,
+    /// Missing for swaps that locked Bitcoin before we recorded it.
+    #[serde(default)]
+    monero_wallet_restore_blockheight: Option<monero::BlockHeight>,
 }
 
 impl State3 {
+    /// The Monero height from which to scan for the lock transaction, if it
+    /// was recorded when we locked the Bitcoin.
+    pub fn monero_wallet_restore_blockheight(&self) -> Option<monero::BlockHeight> {
+        self.monero_wallet_restore_blockheight
+    }
+
     pub async fn watch_for_lock_xmr<W>(
         self,
         xmr_wallet: &W,
         transfer_proof: TransferProof,
-        monero_wallet_restore_blockheight: u32,
-    ) -> Result<Result<State4, InsufficientFunds>>
+    ) -> Result<Result<State4, InvalidLockTransaction>>
     where
-        W: monero::WatchForTransfer,
+        W: monero::WatchForTransfer + monero::CheckLockTransaction,
     {
         let S_b_monero = monero::PublicKey::from_private_key(&monero::PrivateKey::from_scalar(
             self.s_b.into_ed25519(),
         ));
         let S = self.S_a_monero + S_b_monero;
 
-        if let Err(e) = xmr_wallet
+        let tx_hash = transfer_proof.tx_hash();
+        let lock_height = match xmr_wallet
             .watch_for_transfer(
                 S,
                 self.v.public(),
@@ -328,25 +405,66 @@ impl State3 {
                 self.min_monero_confirmations,
             )
             .await
+        {
+            Ok(lock_height) => lock_height,
+            Err(e) => return Ok(Err(e.into())),
+        };
+
+        if let Err(e) = xmr_wallet
+            .check_lock_transaction(tx_hash, S, self.v, self.xmr)
+            .await?
         {
             return Ok(Err(e));
         }
 
-        Ok(Ok(State4 {
-            A: self.A,
-            b: self.b,
-            s_b: self.s_b,
-            S_a_bitcoin: self.S_a_bitcoin,
-            v: self.v,
-            cancel_timelock: self.cancel_timelock,
-            punish_timelock: self.punish_timelock,
-            refund_address: self.refund_address,
-            redeem_address: self.redeem_address,
-            tx_lock: self.tx_lock,
-            tx_cancel_sig_a: self.tx_cancel_sig_a,
-            tx_refund_encsig: self.tx_refund_encsig,
-            monero_wallet_restore_blockheight,
-        }))
+        Ok(Ok(self.state4(lock_height)))
+    }
+
+    /// Detects the Monero lock transaction with the shared view key instead
+    /// of the transfer proof sent by Alice, in case that message gets lost.
+    /// The chain is scanned from `monero_wallet_restore_blockheight`.
+    pub async fn watch_for_lock_xmr_with_view_key<W>(
+        self,
+        swap_id: Uuid,
+        xmr_wallet: &W,
+        monero_wallet_restore_blockheight: monero::BlockHeight,
+    ) -> Result<Result<State4, InvalidLockTransaction>>
+    where
+        W: monero::WatchForTransfer + monero::CheckLockTransaction,
+    {
+        let S_b_monero = monero::PublicKey::from_private_key(&monero::PrivateKey::from_scalar(
+            self.s_b.into_ed25519(),
+        ));
+        let S = self.S_a_monero + S_b_monero;
+
+        let (tx_hash, lock_height) = match xmr_wallet
+            .watch_for_transfer_with_view_key(
+                swap_id,
+                S,
+                self.v,
+                monero_wallet_restore_blockheight,
+                self.xmr,
+                self.min_monero_confirmations,
+            )
+            .await?
+        {
+            Ok(lock) => lock,
+            Err(e) => return Ok(Err(e.into())),
+        };
+        tracing::info!(
+            "Found Monero lock transaction {} in block {}",
+            tx_hash.0,
+            lock_height.height
+        );
+
+        if let Err(e) = xmr_wallet
+            .check_lock_transaction(tx_hash, S, self.v, self.xmr)
+            .await?
+        {
+            return Ok(Err(e));
+        }
+
+        Ok(Ok(self.state4(lock_height)))
     }
 
     pub async fn wait_for_cancel_timelock_to_expire<W>(&self, bitcoin_wallet: &W) -> Result<()>
@@ -361,7 +479,22 @@ impl State3 {
         .await
     }
 
-    pub fn state4(&self) -> State4 {
+    /// Waits until the lock transaction is final, publishing it again if a
+    /// reorganisation drops it from the chain.
+    pub async fn wait_for_lock_finality<W>(
+        &self,
+        bitcoin_wallet: &W,
+        execution_params: ExecutionParams,
+    ) -> Result<()>
+    where
+        W: WaitForTransactionFinality,
+    {
+        bitcoin_wallet
+            .wait_for_transaction_finality(self.tx_lock.txid(), execution_params)
+            .await
+    }
+
+    pub fn state4(&self, monero_wallet_restore_blockheight: monero::BlockHeight) -> State4 {
         State4 {
             A: self.A,
             b: self.b.clone(),
@@ -373,9 +506,10 @@ impl State3 {
             refund_address: self.refund_address.clone(),
             redeem_address: self.redeem_address.clone(),
             tx_lock: self.tx_lock.clone(),
+            tx_fee_rate: self.tx_fee_rate,
             tx_cancel_sig_a: self.tx_cancel_sig_a.clone(),
             tx_refund_encsig: self.tx_refund_encsig.clone(),
-            monero_wallet_restore_blockheight: 0u32,
+            monero_wallet_restore_blockheight: monero_wallet_restore_blockheight.height,
         }
     }
 
@@ -409,21 +543,28 @@ pub struct State4 {
     refund_address: bitcoin::Address,
     redeem_address: bitcoin::Address,
     tx_lock: bitcoin::TxLock,
+    #[serde(default = "bitcoin::FeeRate::legacy")]
+    tx_fee_rate: bitcoin::FeeRate,
     tx_cancel_sig_a: Signature,
     tx_refund_encsig: bitcoin::EncryptedSignature,
     monero_wallet_restore_blockheight: u32,
 }
 
 impl State4 {
-    pub fn next_message(&self) -> EncryptedSignature {
-        let tx_redeem = bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address);
+    pub fn next_message(&self, swap_id: Uuid) -> EncryptedSignature {
+        let tx_redeem =
+            bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address, self.tx_fee_rate);
         let tx_redeem_encsig = self.b.encsign(self.S_a_bitcoin, tx_redeem.digest());
 
-        EncryptedSignature { tx_redeem_encsig }
+        EncryptedSignature {
+            swap_id,
+            tx_redeem_encsig,
+        }
     }
 
     pub fn tx_redeem_encsig(&self) -> bitcoin::EncryptedSignature {
-        let tx_redeem = bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address);
+        let tx_redeem =
+            bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address, self.tx_fee_rate);
         self.b.encsign(self.S_a_bitcoin, tx_redeem.digest())
     }
 
@@ -431,8 +572,13 @@ impl State4 {
     where
         W: GetRawTransaction,
     {
-        let tx_cancel =
-            bitcoin::TxCancel::new(&self.tx_lock, self.cancel_timelock, self.A, self.b.public());
+        let tx_cancel = bitcoin::TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.A,
+            self.b.public(),
+            self.tx_fee_rate,
+        );
 
         let sig_a = self.tx_cancel_sig_a.clone();
         let sig_b = self.b.sign(tx_cancel.digest());
@@ -450,39 +596,48 @@ impl State4 {
         Ok(tx)
     }
 
+    /// The cancel transaction pays to the shared output, so its fee can only
+    /// be bumped together with the refund transaction in `refund_btc`.
     pub async fn submit_tx_cancel<W>(&self, bitcoin_wallet: &W) -> Result<Txid>
     where
         W: BroadcastSignedTransaction,
     {
-        let tx_cancel =
-            bitcoin::TxCancel::new(&self.tx_lock, self.cancel_timelock, self.A, self.b.public());
+        let tx_id = bitcoin_wallet
+            .broadcast_signed_transaction(self.signed_tx_cancel())
+            .await?;
+        Ok(tx_id)
+    }
+
+    fn signed_tx_cancel(&self) -> Transaction {
+        let tx_cancel = bitcoin::TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.A,
+            self.b.public(),
+            self.tx_fee_rate,
+        );
 
         let sig_a = self.tx_cancel_sig_a.clone();
         let sig_b = self.b.sign(tx_cancel.digest());
 
-        let tx_cancel = tx_cancel
-            .clone()
+        tx_cancel
             .add_signatures(&self.tx_lock, (self.A, sig_a), (self.b.public(), sig_b))
             .expect(
                 "sig_{a,b} to be valid signatures for
                 tx_cancel",
-            );
-
-        let tx_id = bitcoin_wallet
-            .broadcast_signed_transaction(tx_cancel)
-            .await?;
-        Ok(tx_id)
+            )
     }
 
     pub async fn watch_for_redeem_btc<W>(&self, bitcoin_wallet: &W) -> Result<State5>
     where
         W: WatchForRawTransaction,
     {
-        let tx_redeem = bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address);
+        let tx_redeem =
+            bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address, self.tx_fee_rate);
         let tx_redeem_encsig = self.b.encsign(self.S_a_bitcoin, tx_redeem.digest());
 
         let tx_redeem_candidate = bitcoin_wallet
-            .watch_for_raw_transaction(tx_redeem.txid())
+            .watch_for_raw_transaction(tx_redeem.txid(), self.redeem_address.script_pubkey())
             .await?;
 
         let tx_redeem_sig =
@@ -530,11 +685,19 @@ impl State4 {
         execution_params: ExecutionParams,
     ) -> Result<()>
     where
-        W: bitcoin::BroadcastSignedTransaction + bitcoin::WaitForTransactionFinality,
+        W: bitcoin::BroadcastSignedTransaction
+            + bitcoin::GetRawTransaction
+            + bitcoin::BumpFeeUntilConfirmed
+            + bitcoin::WaitForTransactionFinality,
     {
-        let tx_cancel =
-            bitcoin::TxCancel::new(&self.tx_lock, self.cancel_timelock, self.A, self.b.public());
-        let tx_refund = bitcoin::TxRefund::new(&tx_cancel, &self.refund_address);
+        let tx_cancel = bitcoin::TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.A,
+            self.b.public(),
+            self.tx_fee_rate,
+        );
+        let tx_refund = bitcoin::TxRefund::new(&tx_cancel, &self.refund_address, self.tx_fee_rate);
 
         let adaptor = Adaptor::<Sha256, Deterministic<Sha256>>::default();
 
@@ -548,14 +711,47 @@ impl State4 {
             (self.b.public(), sig_b),
         )?;
 
+        // The cancel transaction may have been reorganised out since it was
+        // published, the refund cannot be accepted without it.
+        if bitcoin_wallet
+            .get_raw_transaction(tx_cancel.txid())
+            .await
+            .is_err()
+        {
+            bitcoin_wallet
+                .broadcast_signed_transaction(self.signed_tx_cancel())
+                .await?;
+        }
+
         let txid = bitcoin_wallet
-            .broadcast_signed_transaction(signed_tx_refund)
+            .broadcast_signed_transaction(signed_tx_refund.clone())
             .await?;
 
+        // Alice can punish once the punish timelock expires, which is counted
+        // from the block that confirms the cancel transaction.
+        let deadline = bitcoin::Deadline::AfterConfirmation {
+            txid: tx_cancel.txid(),
+            blocks: self.punish_timelock.into(),
+        };
+        let package = bitcoin::CpfpPackage::new(
+            signed_tx_refund,
+            bitcoin::TxRefund::fee(self.tx_fee_rate, &self.refund_address),
+        )
+        .with_parent(
+            self.signed_tx_cancel(),
+            bitcoin::TxCancel::fee(self.tx_fee_rate),
+        );
         bitcoin_wallet
-            .wait_for_transaction_finality(txid, execution_params)
+            .bump_fee_until_confirmed(package, deadline)
             .await?;
 
+        // Both are published again if a reorganisation drops them, the refund
+        // is only accepted once the cancel transaction is back.
+        tokio::try_join!(
+            bitcoin_wallet.wait_for_transaction_finality(tx_cancel.txid(), execution_params),
+            bitcoin_wallet.wait_for_transaction_finality(txid, execution_params)
+        )?;
+
         Ok(())
     }
 
@@ -575,7 +771,7 @@ pub struct State5 {
 }
 
 impl State5 {
-    pub async fn claim_xmr<W>(&self, monero_wallet: &W) -> Result<()>
+    pub async fn claim_xmr<W>(&self, swap_id: Uuid, monero_wallet: &W) -> Result<()>
     where
         W: monero::CreateWalletForOutput,
     {
@@ -585,10 +781,9 @@ impl State5 {
 
         let s = self.s_a + s_b;
 
-        // NOTE: This actually generates and opens a new wallet, closing the currently
-        // open one.
         monero_wallet
-            .create_and_load_wallet_for_output(
+            .create_wallet_for_output(
+                swap_id,
                 s,
                 self.v,
                 Some(self.monero_wallet_restore_blockheight),
@@ -4,17 +4,24 @@ use crate::{
     database::{Database, Swap},
     execution_params::ExecutionParams,
     monero,
-    monero::InsufficientFunds,
-    protocol::bob::{self, event_loop::EventLoopHandle, state::*, QuoteRequest},
+    monero::SweepAll,
+    protocol::{
+        alice::{QuoteResponse, RejectReason},
+        bob::{self, event_loop::EventLoopHandle, state::*, QuoteRequest},
+    },
 };
 use anyhow::{bail, Result};
 use async_recursion::async_recursion;
 use rand::rngs::OsRng;
 use std::sync::Arc;
 use tokio::select;
-use tracing::info;
+use tracing::{info, warn};
 use uuid::Uuid;
 
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("Alice rejected the quote request: {0}")]
+pub struct QuoteRejected(pub RejectReason);
+
 pub fn is_complete(state: &BobState) -> bool {
     matches!(
         state,
@@ -66,7 +73,10 @@ async fn run_until_internal(
         Ok(state)
     } else {
         match state {
-            BobState::Started { btc_amount } => {
+            BobState::Started {
+                btc_amount,
+                coin_control,
+            } => {
                 let bitcoin_refund_address = bitcoin_wallet.new_address().await?;
 
                 event_loop_handle.dial().await?;
@@ -75,7 +85,9 @@ async fn run_until_internal(
                     btc_amount,
                     &mut event_loop_handle,
                     execution_params,
+                    bitcoin_wallet.as_ref(),
                     bitcoin_refund_address,
+                    coin_control,
                 )
                 .await?;
 
@@ -98,7 +110,12 @@ async fn run_until_internal(
                 // Do not lock Bitcoin if not connected to Alice.
                 event_loop_handle.dial().await?;
                 // Alice and Bob have exchanged info
-                let state3 = state2.lock_btc(bitcoin_wallet.as_ref()).await?;
+                // The Monero lock transaction cannot be in an earlier block, so this is
+                // where we start scanning for it with the view key.
+                let monero_wallet_restore_blockheight = monero_wallet.block_height().await?;
+                let state3 = state2
+                    .lock_btc(bitcoin_wallet.as_ref(), monero_wallet_restore_blockheight)
+                    .await?;
 
                 let state = BobState::BtcLocked(state3);
                 let db_state = state.clone().into();
@@ -118,6 +135,23 @@ async fn run_until_internal(
             // Bob has locked Btc
             // Watch for Alice to Lock Xmr or for cancel timelock to elapse
             BobState::BtcLocked(state3) => {
+                // Published again if a reorganisation drops it, Alice only
+                // locks the Monero once it is final.
+                state3
+                    .wait_for_lock_finality(bitcoin_wallet.as_ref(), execution_params)
+                    .await?;
+
+                let monero_wallet_restore_blockheight = match state3
+                    .monero_wallet_restore_blockheight()
+                {
+                    Some(height) => height,
+                    None => {
+                        let height = monero_wallet.block_height().await?;
+                        warn!("No Monero restore height recorded for this swap, scanning for the lock transaction from the current block {}", height);
+                        height
+                    }
+                };
+
                 let state = if let ExpiredTimelocks::None =
                     state3.current_epoch(bitcoin_wallet.as_ref()).await?
                 {
@@ -127,13 +161,13 @@ async fn run_until_internal(
                     let cancel_timelock_expires =
                         state3.wait_for_cancel_timelock_to_expire(bitcoin_wallet.as_ref());
 
-                    // Record the current monero wallet block height so we don't have to scan from
-                    // block 0 once we create the redeem wallet.
-                    // TODO: This can be optimized further by extracting the block height when
-                    //  tx-lock was included. However, scanning a few more blocks won't do any harm
-                    //  and is simpler.
-                    let monero_wallet_restore_blockheight =
-                        monero_wallet.inner.block_height().await?;
+                    // The transfer proof is only a shortcut, we also find the lock with the
+                    // shared view key in case Alice's message never arrives.
+                    let xmr_lock_watcher = state3.clone().watch_for_lock_xmr_with_view_key(
+                        swap_id,
+                        monero_wallet.as_ref(),
+                        monero_wallet_restore_blockheight,
+                    );
 
                     select! {
                         transfer_proof = transfer_proof_watcher => {
@@ -144,13 +178,24 @@ async fn run_until_internal(
                                 monero_wallet_restore_blockheight
                             }
                         },
+                        state4 = xmr_lock_watcher => {
+                            match state4? {
+                                Ok(state4) => BobState::XmrLocked(state4),
+                                Err(e) => {
+                                     warn!("The Monero lock transaction of the other party is invalid: {}! Waiting for refund...", e);
+                                     state3.wait_for_cancel_timelock_to_expire(bitcoin_wallet.as_ref()).await?;
+                                     let state4 = state3.state4(monero_wallet_restore_blockheight);
+                                     BobState::CancelTimelockExpired(state4)
+                                },
+                            }
+                        },
                         _ = cancel_timelock_expires => {
-                            let state4 = state3.state4();
+                            let state4 = state3.state4(monero_wallet_restore_blockheight);
                             BobState::CancelTimelockExpired(state4)
                         }
                     }
                 } else {
-                    let state4 = state3.state4();
+                    let state4 = state3.state4(monero_wallet_restore_blockheight);
                     BobState::CancelTimelockExpired(state4)
                 };
                 let db_state = state.clone().into();
@@ -177,11 +222,9 @@ async fn run_until_internal(
                 {
                     event_loop_handle.dial().await?;
 
-                    let xmr_lock_watcher = state.clone().watch_for_lock_xmr(
-                        monero_wallet.as_ref(),
-                        lock_transfer_proof,
-                        monero_wallet_restore_blockheight.height,
-                    );
+                    let xmr_lock_watcher = state
+                        .clone()
+                        .watch_for_lock_xmr(monero_wallet.as_ref(), lock_transfer_proof);
                     let cancel_timelock_expires =
                         state.wait_for_cancel_timelock_to_expire(bitcoin_wallet.as_ref());
 
@@ -189,21 +232,21 @@ async fn run_until_internal(
                         state4 = xmr_lock_watcher => {
                             match state4? {
                                 Ok(state4) => BobState::XmrLocked(state4),
-                                Err(InsufficientFunds {..}) => {
-                                     info!("The other party has locked insufficient Monero funds! Waiting for refund...");
+                                Err(e) => {
+                                     warn!("The Monero lock transaction of the other party is invalid: {}! Waiting for refund...", e);
                                      state.wait_for_cancel_timelock_to_expire(bitcoin_wallet.as_ref()).await?;
-                                     let state4 = state.state4();
+                                     let state4 = state.state4(monero_wallet_restore_blockheight);
                                      BobState::CancelTimelockExpired(state4)
                                 },
                             }
                         },
                         _ = cancel_timelock_expires => {
-                            let state4 = state.state4();
+                            let state4 = state.state4(monero_wallet_restore_blockheight);
                             BobState::CancelTimelockExpired(state4)
                         }
                     }
                 } else {
-                    let state4 = state.state4();
+                    let state4 = state.state4(monero_wallet_restore_blockheight);
                     BobState::CancelTimelockExpired(state4)
                 };
 
@@ -300,10 +343,34 @@ async fn run_until_internal(
             }
             BobState::BtcRedeemed(state) => {
                 // Bob redeems XMR using revealed s_a
-                state.claim_xmr(monero_wallet.as_ref()).await?;
+                state.claim_xmr(swap_id, monero_wallet.as_ref()).await?;
+
+                let sweep_tx_hashes = match db.get_monero_address(swap_id)? {
+                    Some(receive_address) => {
+                        let tx_hashes = monero_wallet.sweep_all(swap_id, receive_address).await?;
+                        let tx_hash_list = tx_hashes
+                            .iter()
+                            .map(|tx_hash| tx_hash.0.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        info!(
+                            "Sent redeemed Monero to {} in {}",
+                            receive_address, tx_hash_list
+                        );
+                        tx_hashes
+                    }
+                    None => {
+                        info!(
+                            "Redeemed Monero is in wallet {} of monero-wallet-rpc",
+                            monero::swap_wallet_name(swap_id)
+                        );
+                        vec![]
+                    }
+                };
 
                 let state = BobState::XmrRedeemed {
                     tx_lock_id: state.tx_lock_id(),
+                    sweep_tx_hashes,
                 };
                 let db_state = state.clone().into();
                 db.insert_latest_state(swap_id, Swap::Bob(db_state)).await?;
@@ -378,7 +445,13 @@ async fn run_until_internal(
             BobState::BtcRefunded(state4) => Ok(BobState::BtcRefunded(state4)),
             BobState::BtcPunished { tx_lock_id } => Ok(BobState::BtcPunished { tx_lock_id }),
             BobState::SafelyAborted => Ok(BobState::SafelyAborted),
-            BobState::XmrRedeemed { tx_lock_id } => Ok(BobState::XmrRedeemed { tx_lock_id }),
+            BobState::XmrRedeemed {
+                tx_lock_id,
+                sweep_tx_hashes,
+            } => Ok(BobState::XmrRedeemed {
+                tx_lock_id,
+                sweep_tx_hashes,
+            }),
         }
     }
 }
@@ -387,22 +460,30 @@ pub async fn request_quote_and_setup(
     btc_amount: bitcoin::Amount,
     event_loop_handle: &mut EventLoopHandle,
     execution_params: ExecutionParams,
+    bitcoin_wallet: &bitcoin::Wallet,
     bitcoin_refund_address: bitcoin::Address,
+    coin_control: bitcoin::CoinControl,
 ) -> Result<bob::state::State2> {
     event_loop_handle
         .send_quote_request(QuoteRequest { btc_amount })
         .await?;
 
-    let quote_response = event_loop_handle.recv_quote_response().await?;
+    let xmr_amount = match event_loop_handle.recv_quote_response().await? {
+        QuoteResponse::Quote { xmr_amount } => xmr_amount,
+        QuoteResponse::Rejected { reason } => return Err(QuoteRejected(reason).into()),
+    };
 
     let state0 = State0::new(
         &mut OsRng,
         btc_amount,
-        quote_response.xmr_amount,
+        xmr_amount,
         execution_params.bitcoin_cancel_timelock,
         execution_params.bitcoin_punish_timelock,
         bitcoin_refund_address,
         execution_params.monero_finality_confirmations,
+        bitcoin_wallet.estimate_presigned_fee_rate().await,
+        bitcoin_wallet.fee_estimation.presigned_fee_rate_bounds(),
+        coin_control,
     );
 
     let state2 = event_loop_handle.execution_setup(state0).await?;
@@ -0,0 +1,126 @@
+pub mod testutils;
+
+use std::time::Duration;
+use swap::{
+    bitcoin::ExpiredTimelocks,
+    protocol::{
+        alice,
+        alice::{cancel::CancelError, punish::PunishError, AliceState},
+        bob,
+        bob::BobState,
+    },
+};
+use testutils::{bob_run_until::is_xmr_locked, SlowCancelConfig};
+use tokio::time::sleep;
+
+#[tokio::test]
+async fn given_bob_dead_after_xmr_locked_alice_manually_cancels_and_punishes() {
+    testutils::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let (bob_swap, bob_join_handle) = ctx.new_swap_as_bob().await;
+
+        let bob_state = bob::run_until(bob_swap, is_xmr_locked).await.unwrap();
+
+        assert!(matches!(bob_state, BobState::XmrLocked { .. }));
+
+        // Bob never sends the encrypted signature
+        bob_join_handle.abort();
+
+        let alice_swap = ctx.stop_and_resume_alice_swap_from_db().await;
+        let state3 = if let AliceState::XmrLocked { state3, .. } = alice_swap.state {
+            state3
+        } else {
+            panic!("Alice in unexpected state {}", alice_swap.state);
+        };
+
+        // Alice cannot punish before cancelling
+        let error = alice::punish(
+            alice_swap.swap_id,
+            ctx.alice_state_from_db(),
+            alice_swap.execution_params,
+            alice_swap.bitcoin_wallet.clone(),
+            alice_swap.db.clone(),
+            false,
+        )
+        .await
+        .unwrap()
+        .unwrap_err();
+        assert!(matches!(error, PunishError::SwapNotCancelledYet));
+
+        // Alice cannot cancel before the cancel timelock expired
+        let error = alice::cancel(
+            alice_swap.swap_id,
+            ctx.alice_state_from_db(),
+            alice_swap.bitcoin_wallet.clone(),
+            alice_swap.monero_wallet.clone(),
+            alice_swap.db.clone(),
+            false,
+        )
+        .await
+        .unwrap()
+        .unwrap_err();
+        assert!(matches!(error, CancelError::CancelTimelockNotExpiredYet));
+
+        state3
+            .wait_for_cancel_timelock_to_expire(alice_swap.bitcoin_wallet.as_ref())
+            .await
+            .unwrap();
+
+        // Alice manually cancels
+        let (_, state) = alice::cancel(
+            alice_swap.swap_id,
+            ctx.alice_state_from_db(),
+            alice_swap.bitcoin_wallet.clone(),
+            alice_swap.monero_wallet.clone(),
+            alice_swap.db.clone(),
+            false,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(matches!(state, AliceState::BtcCancelled { .. }));
+        assert!(matches!(
+            ctx.alice_state_from_db(),
+            AliceState::BtcCancelled { .. }
+        ));
+
+        // Alice cannot punish before the punish timelock expired
+        let error = alice::punish(
+            alice_swap.swap_id,
+            ctx.alice_state_from_db(),
+            alice_swap.execution_params,
+            alice_swap.bitcoin_wallet.clone(),
+            alice_swap.db.clone(),
+            false,
+        )
+        .await
+        .unwrap()
+        .unwrap_err();
+        assert!(matches!(error, PunishError::PunishTimelockNotExpiredYet));
+
+        while !matches!(
+            state3
+                .expired_timelocks(alice_swap.bitcoin_wallet.as_ref())
+                .await
+                .unwrap(),
+            ExpiredTimelocks::Punish
+        ) {
+            sleep(Duration::from_secs(1)).await;
+        }
+
+        // Alice manually punishes
+        let (_, state) = alice::punish(
+            alice_swap.swap_id,
+            ctx.alice_state_from_db(),
+            alice_swap.execution_params,
+            alice_swap.bitcoin_wallet.clone(),
+            alice_swap.db.clone(),
+            false,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        ctx.assert_alice_punished(state).await;
+    })
+    .await;
+}
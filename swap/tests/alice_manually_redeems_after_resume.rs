@@ -0,0 +1,53 @@
+pub mod testutils;
+
+use swap::protocol::{alice, alice::AliceState, bob, bob::BobState};
+use testutils::{
+    alice_run_until::is_encsig_learned, bob_run_until::is_xmr_locked, SlowCancelConfig,
+};
+
+#[tokio::test]
+async fn given_alice_resumes_swap_until_encsig_learned_alice_manually_redeems() {
+    testutils::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let (bob_swap, bob_join_handle) = ctx.new_swap_as_bob().await;
+
+        let bob_state = bob::run_until(bob_swap, is_xmr_locked).await.unwrap();
+
+        assert!(matches!(bob_state, BobState::XmrLocked { .. }));
+
+        let alice_swap = ctx.stop_and_resume_alice_swap_from_db().await;
+        assert!(matches!(alice_swap.state, AliceState::XmrLocked { .. }));
+
+        let swap_id = alice_swap.swap_id;
+        let execution_params = alice_swap.execution_params;
+        let bitcoin_wallet = alice_swap.bitcoin_wallet.clone();
+        let db = alice_swap.db.clone();
+
+        let (bob_swap, _) = ctx.stop_and_resume_bob_from_db(bob_join_handle).await;
+        let bob_swap = tokio::spawn(bob::run(bob_swap));
+
+        let alice_state = alice::run_until(alice_swap, is_encsig_learned)
+            .await
+            .unwrap();
+        assert!(matches!(alice_state, AliceState::EncSigLearned { .. }));
+
+        // Alice manually redeems
+        let (_, alice_state) = alice::redeem(
+            swap_id,
+            alice_state,
+            execution_params,
+            bitcoin_wallet,
+            db,
+            false,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        ctx.assert_alice_manually_redeemed(alice_state).await;
+        assert!(matches!(ctx.alice_state_from_db(), AliceState::BtcRedeemed));
+
+        let bob_state = bob_swap.await.unwrap().unwrap();
+        ctx.assert_bob_redeemed(bob_state).await;
+    })
+    .await;
+}
@@ -0,0 +1,37 @@
+pub mod testutils;
+
+use swap::bitcoin::{
+    Amount, BumpFeeUntilConfirmed, CpfpPackage, Deadline, FeeRate, GetBlockHeight,
+};
+use testutils::SlowCancelConfig;
+
+#[tokio::test]
+async fn given_transaction_pays_too_little_wallet_pays_for_it_with_a_child() {
+    testutils::setup_test(SlowCancelConfig, |ctx| async move {
+        let wallet = ctx.alice_bitcoin_wallet();
+        let starting_balance = wallet.balance().await.unwrap();
+
+        let amount = Amount::from_sat(1_000_000);
+        let address = wallet.new_address().await.unwrap();
+        let (transaction, fee) = ctx
+            .send_stuck_transaction(&address, amount, FeeRate::from_sat_per_vb(2))
+            .await;
+        let package = CpfpPackage::new(transaction, fee);
+
+        // The wallet estimates the same fee rate as the presigned transactions pay
+        let child_fee = package.child_fee(FeeRate::from_sat_per_vb(10), |_| true);
+
+        let deadline = wallet.get_block_height().await.unwrap() + 10;
+        wallet
+            .bump_fee_until_confirmed(package, Deadline::Height(deadline))
+            .await
+            .unwrap();
+
+        wallet.sync_wallet().await.unwrap();
+        assert_eq!(
+            wallet.balance().await.unwrap(),
+            starting_balance + amount - child_fee
+        );
+    })
+    .await;
+}
@@ -0,0 +1,41 @@
+pub mod testutils;
+
+use swap::protocol::{bob, bob::BobState};
+use testutils::{alice_run_until::is_xmr_locked, bob_run_until::is_btc_locked, SlowCancelConfig};
+
+fn is_xmr_lock_detected(state: &BobState) -> bool {
+    matches!(
+        state,
+        BobState::XmrLockProofReceived { .. } | BobState::XmrLocked(..)
+    )
+}
+
+#[tokio::test]
+async fn given_transfer_proof_is_lost_bob_finds_xmr_lock_with_view_key() {
+    testutils::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let (bob_swap, bob_join_handle) = ctx.new_swap_as_bob().await;
+
+        let bob_state = bob::run_until(bob_swap, is_btc_locked).await.unwrap();
+
+        assert!(matches!(bob_state, BobState::BtcLocked { .. }));
+
+        // Bob is offline when Alice sends the transfer proof, it is never sent again
+        bob_join_handle.abort();
+        ctx.wait_for_alice_state(is_xmr_locked).await;
+
+        let (bob_swap, bob_join_handle) = ctx.stop_and_resume_bob_from_db(bob_join_handle).await;
+        let bob_state = bob::run_until(bob_swap, is_xmr_lock_detected)
+            .await
+            .unwrap();
+
+        // Found through the shared view key, without a proof from Alice
+        assert!(matches!(bob_state, BobState::XmrLocked(..)));
+
+        let (bob_swap, _) = ctx.stop_and_resume_bob_from_db(bob_join_handle).await;
+        let bob_state = bob::run(bob_swap).await.unwrap();
+
+        ctx.assert_bob_redeemed(bob_state).await;
+        ctx.assert_alice_redeemed().await;
+    })
+    .await;
+}
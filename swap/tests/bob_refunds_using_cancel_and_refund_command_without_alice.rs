@@ -0,0 +1,62 @@
+pub mod testutils;
+
+use swap::protocol::{bob, bob::BobState};
+use testutils::{bob_run_until::is_btc_locked, FastCancelConfig};
+
+#[tokio::test]
+async fn given_alice_is_gone_bob_manually_refunds_after_btc_locked() {
+    testutils::setup_test(FastCancelConfig, |mut ctx| async move {
+        let (bob_swap, bob_join_handle) = ctx.new_swap_as_bob().await;
+
+        let bob_state = bob::run_until(bob_swap, is_btc_locked).await.unwrap();
+
+        assert!(matches!(bob_state, BobState::BtcLocked { .. }));
+
+        ctx.stop_alice().await;
+        bob_join_handle.abort();
+
+        let recovery = ctx.recover_bob_from_db();
+
+        // Ensure Bob's timelock is expired
+        if let BobState::BtcLocked(state3) = recovery.state.clone() {
+            state3
+                .wait_for_cancel_timelock_to_expire(recovery.bitcoin_wallet.as_ref())
+                .await
+                .unwrap();
+        } else {
+            panic!("Bob in unexpected state {}", recovery.state);
+        }
+
+        // Bob manually cancels without a connection to Alice
+        let (_, state) = bob::cancel(
+            recovery.swap_id,
+            recovery.state,
+            recovery.bitcoin_wallet,
+            recovery.db,
+            false,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(matches!(state, BobState::BtcCancelled { .. }));
+
+        // Bob manually refunds without a connection to Alice
+        let recovery = ctx.recover_bob_from_db();
+        assert!(matches!(recovery.state, BobState::BtcCancelled { .. }));
+
+        let bob_state = bob::refund(
+            recovery.swap_id,
+            recovery.state,
+            recovery.execution_params,
+            recovery.bitcoin_wallet,
+            recovery.db,
+            false,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        ctx.assert_bob_refunded(bob_state).await;
+    })
+    .await;
+}
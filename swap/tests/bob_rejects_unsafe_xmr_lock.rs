@@ -0,0 +1,103 @@
+pub mod testutils;
+
+use monero_harness::{
+    rpc::wallet::{self, Destination},
+    Monero,
+};
+use rand::rngs::OsRng;
+use swap::monero::{self, CheckLockTransaction, InvalidLockTransaction, PrivateViewKey, TxHash};
+use testcontainers::clients::Cli;
+
+const LOCK_AMOUNT: u64 = 1_000_000_000_000;
+
+/// A block height far in the future.
+const UNLOCK_HEIGHT: u64 = 10_000_000;
+
+struct LockKeys {
+    public_spend_key: monero::PublicKey,
+    private_view_key: PrivateViewKey,
+}
+
+impl LockKeys {
+    fn new_random() -> Self {
+        let private_spend_key = monero::PrivateKey::from_scalar(monero::Scalar::random(&mut OsRng));
+
+        Self {
+            public_spend_key: monero::PublicKey::from_private_key(&private_spend_key),
+            private_view_key: PrivateViewKey::new_random(&mut OsRng),
+        }
+    }
+
+    fn address(&self) -> String {
+        monero::Address::standard(
+            monero::Network::Mainnet,
+            self.public_spend_key,
+            self.private_view_key.public().into(),
+        )
+        .to_string()
+    }
+}
+
+async fn lock(wallet: &wallet::Client, address: String, amount: u64, unlock_time: u64) -> TxHash {
+    let transfer = wallet
+        .transfer_with_unlock_time(0, vec![Destination { amount, address }], unlock_time)
+        .await
+        .unwrap();
+
+    TxHash(transfer.tx_hash)
+}
+
+#[tokio::test]
+async fn given_xmr_lock_is_unsafe_bob_rejects_it() {
+    let _guard = testutils::init_tracing();
+
+    let cli = Cli::default();
+    let (monero, _monerods) = Monero::new(&cli, None, vec![]).await.unwrap();
+    monero.init(vec![]).await.unwrap();
+
+    // Alice pays from the miner wallet, which has plenty of unlocked outputs
+    let miner_wallet = monero.wallet("miner").unwrap();
+    let alice = miner_wallet.client();
+    let bob = monero::Wallet::new(
+        alice.url.clone(),
+        monero::Network::Mainnet,
+        miner_wallet.name(),
+    )
+    .with_daemon(monero.monerod().client().url);
+
+    let keys = LockKeys::new_random();
+    let amount = monero::Amount::from_piconero(LOCK_AMOUNT);
+    let check = |tx_hash| {
+        bob.check_lock_transaction(
+            tx_hash,
+            keys.public_spend_key,
+            keys.private_view_key,
+            amount,
+        )
+    };
+
+    let tx_hash = lock(&alice, keys.address(), LOCK_AMOUNT, UNLOCK_HEIGHT).await;
+    let error = check(tx_hash).await.unwrap().unwrap_err();
+    assert!(matches!(error, InvalidLockTransaction::UnlockTime {
+        unlock_time: UNLOCK_HEIGHT,
+        ..
+    }));
+
+    let other_address = LockKeys::new_random().address();
+    let tx_hash = lock(&alice, other_address, LOCK_AMOUNT, 0).await;
+    let error = check(tx_hash).await.unwrap().unwrap_err();
+    assert!(matches!(
+        error,
+        InvalidLockTransaction::NotToLockAddress { .. }
+    ));
+
+    let tx_hash = lock(&alice, keys.address(), LOCK_AMOUNT / 2, 0).await;
+    let error = check(tx_hash).await.unwrap().unwrap_err();
+    assert!(matches!(
+        error,
+        InvalidLockTransaction::InsufficientFunds(_)
+    ));
+
+    let tx_hash = lock(&alice, keys.address(), LOCK_AMOUNT, 0).await;
+    check(tx_hash).await.unwrap().unwrap();
+}
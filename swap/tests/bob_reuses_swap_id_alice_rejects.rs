@@ -0,0 +1,25 @@
+pub mod testutils;
+
+use swap::protocol::{alice::AliceState, bob};
+use testutils::SlowCancelConfig;
+
+#[tokio::test]
+async fn given_taker_reuses_swap_id_alice_rejects_swap_before_bitcoin_is_locked() {
+    testutils::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let (bob_swap, _bob_join_handle) = ctx.new_swap_as_bob().await;
+        let swap_id = bob_swap.swap_id;
+
+        let bob_state = bob::run(bob_swap).await.unwrap();
+        ctx.assert_alice_redeemed().await;
+
+        // Another taker chooses the id of the finished swap
+        let (duplicate_swap, _duplicate_join_handle) =
+            ctx.new_concurrent_swap_as_bob(swap_id).await;
+        assert!(bob::run(duplicate_swap).await.is_err());
+
+        // Alice keeps the finished swap and nobody locked Bitcoin for the duplicate
+        assert!(matches!(ctx.alice_state_from_db(), AliceState::BtcRedeemed));
+        ctx.assert_bob_redeemed(bob_state).await;
+    })
+    .await;
+}
@@ -0,0 +1,30 @@
+pub mod testutils;
+
+use swap::protocol::bob;
+use testutils::SlowCancelConfig;
+
+/// Run the following tests with RUST_MIN_STACK=10000000
+
+#[tokio::test]
+async fn given_bitcoin_lock_reorged_out_swap_completes() {
+    testutils::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let (bob_swap, _bob_join_handle) = ctx.new_swap_as_bob().await;
+
+        // Both parties keep running while the lock transaction is reorganised
+        // out, they have to wait for it again instead of being restarted.
+        let bob_swap = tokio::spawn(bob::run(bob_swap));
+
+        // Bob's lock transaction is the first one of the swap
+        let tx_lock_id = ctx.wait_for_mempool_transaction().await;
+        let reorged_block = ctx.reorg_out_transaction(tx_lock_id).await;
+
+        let bob_state = bob_swap.await.unwrap().unwrap();
+
+        let final_block = ctx.wait_for_transaction_block(tx_lock_id).await;
+        assert_ne!(final_block, reorged_block);
+
+        ctx.assert_alice_redeemed().await;
+        ctx.assert_bob_redeemed(bob_state).await;
+    })
+    .await;
+}
@@ -0,0 +1,28 @@
+pub mod testutils;
+
+use swap::protocol::bob;
+use testutils::SlowCancelConfig;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn given_two_takers_swap_at_the_same_time_both_swaps_complete() {
+    testutils::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let (first_swap, _first_join_handle) = ctx.new_swap_as_bob().await;
+        let first_swap_id = first_swap.swap_id;
+
+        let second_swap_id = Uuid::new_v4();
+        let (second_swap, _second_join_handle) =
+            ctx.new_concurrent_swap_as_bob(second_swap_id).await;
+        assert_eq!(second_swap.swap_id, second_swap_id);
+
+        // Each swap only receives its own transfer proof and encrypted signature
+        let (first_state, second_state) = tokio::join!(bob::run(first_swap), bob::run(second_swap));
+
+        ctx.assert_concurrent_swaps_redeemed(vec![
+            (first_swap_id, first_state.unwrap()),
+            (second_swap_id, second_state.unwrap()),
+        ])
+        .await;
+    })
+    .await;
+}
@@ -0,0 +1,111 @@
+pub mod testutils;
+
+use monero_harness::Monero;
+use rand::rngs::OsRng;
+use swap::monero::{self, PrivateViewKey, SweepToMainWallet};
+use testcontainers::clients::Cli;
+use uuid::Uuid;
+
+const LOCK_AMOUNT: u64 = 1_000_000_000_000;
+
+struct SwapKeys {
+    swap_id: Uuid,
+    private_spend_key: monero::PrivateKey,
+    private_view_key: PrivateViewKey,
+}
+
+impl SwapKeys {
+    fn new_random() -> Self {
+        Self {
+            swap_id: Uuid::new_v4(),
+            private_spend_key: monero::PrivateKey::from_scalar(monero::Scalar::random(&mut OsRng)),
+            private_view_key: PrivateViewKey::new_random(&mut OsRng),
+        }
+    }
+
+    fn lock_address(&self) -> String {
+        monero::Address::standard(
+            monero::Network::Mainnet,
+            monero::PublicKey::from_private_key(&self.private_spend_key),
+            self.private_view_key.public().into(),
+        )
+        .to_string()
+    }
+}
+
+#[tokio::test]
+async fn given_two_swaps_sweep_at_the_same_time_both_reach_main_wallet() {
+    let _guard = testutils::init_tracing();
+
+    let cli = Cli::default();
+    let (monero, _monerods) = Monero::new(&cli, None, vec!["alice".to_string()])
+        .await
+        .unwrap();
+    monero.init(vec![("alice", LOCK_AMOUNT)]).await.unwrap();
+
+    let monero_wallet_rpc = monero.wallet("alice").unwrap();
+    let monero_wallet = monero::Wallet::new(
+        monero_wallet_rpc.client().url,
+        monero::Network::Mainnet,
+        monero_wallet_rpc.name(),
+    )
+    .with_daemon(monero.monerod().client().url);
+
+    let main_address = monero_wallet_rpc.address().await.unwrap().address;
+    let starting_balance = monero_wallet.get_balance().await.unwrap();
+    let restore_height = monero_wallet.block_height().await.unwrap();
+
+    let first_swap = SwapKeys::new_random();
+    let second_swap = SwapKeys::new_random();
+    let miner_wallet = monero.wallet("miner").unwrap();
+    for swap in &[&first_swap, &second_swap] {
+        miner_wallet
+            .transfer(&swap.lock_address(), LOCK_AMOUNT)
+            .await
+            .unwrap();
+    }
+
+    // Both swap wallets are opened on the same monero-wallet-rpc, the switches
+    // between them and the main wallet must not interleave
+    let sweep = |swap: &SwapKeys| {
+        monero_wallet.sweep_to_main_wallet(
+            swap.swap_id,
+            swap.private_spend_key,
+            swap.private_view_key,
+            Some(restore_height),
+            1,
+        )
+    };
+    let (first_sweep, second_sweep) = tokio::join!(sweep(&first_swap), sweep(&second_swap));
+    assert!(!first_sweep.unwrap().is_empty());
+    assert!(!second_sweep.unwrap().is_empty());
+
+    // Each swap has its own wallet, which is empty now
+    for swap in &[&first_swap, &second_swap] {
+        let swap_wallet_balance = monero_wallet
+            .get_swap_wallet_balance(swap.swap_id)
+            .await
+            .unwrap();
+        assert_eq!(swap_wallet_balance, monero::Amount::ZERO);
+    }
+
+    // The main wallet is open again and received both outputs
+    assert_eq!(
+        monero_wallet_rpc.address().await.unwrap().address,
+        main_address
+    );
+    monero_wallet.refresh().await.unwrap();
+    let balance = monero_wallet.get_balance().await.unwrap();
+    let fees_paid = monero_wallet
+        .get_fees_paid(first_swap.swap_id)
+        .await
+        .unwrap()
+        + monero_wallet
+            .get_fees_paid(second_swap.swap_id)
+            .await
+            .unwrap();
+    assert_eq!(
+        balance,
+        starting_balance + monero::Amount::from_piconero(LOCK_AMOUNT * 2) - fees_paid
+    );
+}
@@ -0,0 +1,74 @@
+pub mod testutils;
+
+use monero_harness::{rpc::wallet, Monero};
+use std::time::Duration;
+use swap::monero;
+use tempfile::tempdir;
+use testcontainers::clients::Cli;
+use tokio::time::{sleep, timeout};
+
+const MAIN_WALLET: &str = "main";
+
+/// Needs a local monero-wallet-rpc binary, run with
+/// MONERO_WALLET_RPC=/path/to/monero-wallet-rpc cargo test -- --ignored
+
+#[tokio::test]
+#[ignore]
+async fn given_monero_wallet_rpc_stops_it_is_restarted_with_main_wallet_open() {
+    let _guard = testutils::init_tracing();
+
+    let binary = std::env::var("MONERO_WALLET_RPC").expect("MONERO_WALLET_RPC is not set");
+
+    let cli = Cli::default();
+    let (monero, _monerods) = Monero::new(&cli, None, vec![]).await.unwrap();
+    let monerod_url = monero.monerod().client().url;
+    let daemon_address = format!(
+        "{}:{}",
+        monerod_url.host_str().unwrap(),
+        monerod_url.port().unwrap()
+    );
+
+    let wallet_dir = tempdir().unwrap();
+    let wallet_rpc = monero::WalletRpc::start(
+        binary.into(),
+        daemon_address,
+        wallet_dir.path(),
+        monero::Network::Mainnet,
+        MAIN_WALLET,
+    )
+    .await
+    .unwrap();
+
+    let monero_wallet =
+        monero::Wallet::new(wallet_rpc.url(), monero::Network::Mainnet, MAIN_WALLET)
+            .with_login(wallet_rpc.login());
+    monero_wallet.open_or_create_main_wallet().await.unwrap();
+
+    let client = wallet::Client::new(wallet_rpc.url()).with_login(wallet_rpc.login());
+    let main_address = client.get_address(0).await.unwrap().address;
+
+    // The process exits as if it crashed, it is not asked to by the supervisor
+    client.stop_wallet().await.unwrap();
+
+    // The restarted process listens on the same port, requires the same login
+    // and has the main wallet open again
+    let restarted_address = timeout(Duration::from_secs(60), async {
+        while client.block_height().await.is_ok() {
+            sleep(Duration::from_millis(100)).await;
+        }
+        loop {
+            match client.get_address(0).await {
+                Ok(address) => break address.address,
+                Err(_) => sleep(Duration::from_secs(1)).await,
+            }
+        }
+    })
+    .await
+    .expect("monero-wallet-rpc was not restarted");
+    assert_eq!(restarted_address, main_address);
+    monero_wallet.block_height().await.unwrap();
+
+    wallet_rpc.shutdown().await.unwrap();
+
+    assert!(client.block_height().await.is_err());
+}
@@ -2,12 +2,14 @@ mod bitcoind;
 mod electrs;
 
 use crate::testutils;
-use anyhow::{Context, Result};
+use ::bitcoin::hashes::hex::FromHex;
+use anyhow::{anyhow, bail, Context, Result};
 use bitcoin_harness::{BitcoindRpcApi, Client};
 use futures::{future::RemoteHandle, Future};
 use get_port::get_port;
 use libp2p::{core::Multiaddr, PeerId};
 use monero_harness::{image, Monero};
+use serde_json::{json, Value};
 use std::{
     path::{Path, PathBuf},
     sync::Arc,
@@ -16,16 +18,13 @@ use std::{
 use swap::{
     bitcoin,
     bitcoin::{CancelTimelock, PunishTimelock},
+    database,
     database::Database,
     execution_params,
     execution_params::{ExecutionParams, GetExecutionParams},
     monero,
-    protocol::{
-        alice,
-        alice::{event_loop::RATE, AliceState},
-        bob,
-        bob::BobState,
-    },
+    price::{FixedRate, Rate, Spread},
+    protocol::{alice, alice::AliceState, bob, bob::BobState},
     seed::Seed,
 };
 use tempfile::tempdir;
@@ -38,6 +37,10 @@ use uuid::Uuid;
 
 const TEST_WALLET_NAME: &str = "testwallet";
 
+/// Both parties use the same fixed fee rate so the fees of the presigned
+/// transactions are known in advance.
+const FEE_RATE: bitcoin::FeeRate = bitcoin::FeeRate::from_sat_per_vb(10);
+
 #[derive(Debug, Clone)]
 pub struct StartingBalances {
     pub xmr: monero::Amount,
@@ -69,6 +72,50 @@ impl BobParams {
             self.execution_params,
         )
     }
+
+    pub fn recovery(&self) -> bob::Recovery {
+        bob::Recovery::load(
+            Database::open(&self.db_path.clone().as_path()).unwrap(),
+            self.swap_id,
+            self.bitcoin_wallet.clone(),
+            self.execution_params,
+        )
+        .unwrap()
+    }
+}
+
+#[derive(Clone)]
+struct AliceParams {
+    seed: Seed,
+    db: Arc<Database>,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    monero_wallet: Arc<monero::Wallet>,
+    listen_address: Multiaddr,
+    rate: Rate,
+    execution_params: ExecutionParams,
+}
+
+impl AliceParams {
+    pub fn event_loop(
+        &self,
+    ) -> (
+        alice::EventLoop,
+        mpsc::Receiver<RemoteHandle<anyhow::Result<AliceState>>>,
+    ) {
+        alice::EventLoop::new(
+            self.listen_address.clone(),
+            self.seed,
+            self.execution_params,
+            self.bitcoin_wallet.clone(),
+            self.monero_wallet.clone(),
+            self.db.clone(),
+            Box::new(FixedRate::new(self.rate)),
+            Spread::zero(),
+            bitcoin::Amount::ZERO,
+            bitcoin::Amount::max_value(),
+        )
+        .unwrap()
+    }
 }
 
 pub struct BobEventLoopJoinHandle(JoinHandle<()>);
@@ -89,11 +136,15 @@ pub struct TestContext {
     alice_bitcoin_wallet: Arc<bitcoin::Wallet>,
     alice_monero_wallet: Arc<monero::Wallet>,
     alice_swap_handle: mpsc::Receiver<RemoteHandle<anyhow::Result<AliceState>>>,
+    alice_params: AliceParams,
+    alice_event_loop_join_handle: AliceEventLoopJoinHandle,
 
     bob_params: BobParams,
     bob_starting_balances: StartingBalances,
     bob_bitcoin_wallet: Arc<bitcoin::Wallet>,
     bob_monero_wallet: Arc<monero::Wallet>,
+
+    bitcoind_url: Url,
 }
 
 impl TestContext {
@@ -111,6 +162,32 @@ impl TestContext {
         (swap, BobEventLoopJoinHandle(join_handle))
     }
 
+    /// Starts another swap with the given id alongside the first one. It is
+    /// run by a second taker with its own seed and database, who shares Bob's
+    /// wallets.
+    pub async fn new_concurrent_swap_as_bob(
+        &mut self,
+        swap_id: Uuid,
+    ) -> (bob::Swap, BobEventLoopJoinHandle) {
+        let bob_params = BobParams {
+            seed: Seed::random().unwrap(),
+            db_path: tempdir().unwrap().path().to_path_buf(),
+            swap_id,
+            ..self.bob_params.clone()
+        };
+
+        let (swap, event_loop) = bob_params
+            .builder()
+            .with_init_params(self.btc_amount)
+            .build()
+            .await
+            .unwrap();
+
+        let join_handle = tokio::spawn(async move { event_loop.run().await });
+
+        (swap, BobEventLoopJoinHandle(join_handle))
+    }
+
     pub async fn stop_and_resume_bob_from_db(
         &mut self,
         join_handle: BobEventLoopJoinHandle,
@@ -124,10 +201,213 @@ impl TestContext {
         (swap, BobEventLoopJoinHandle(join_handle))
     }
 
+    /// Loads Bob's swap from the database for cancelling and refunding it,
+    /// without connecting to Alice.
+    pub fn recover_bob_from_db(&self) -> bob::Recovery {
+        self.bob_params.recovery()
+    }
+
+    pub async fn stop_alice(&mut self) {
+        // Dropping the remote handle stops the running swap
+        let swap_handle = self.alice_swap_handle.recv().await.unwrap();
+        drop(swap_handle);
+        self.alice_event_loop_join_handle.0.abort();
+        // Wait until the event loop is dropped so the listen address is free again
+        let _ = (&mut self.alice_event_loop_join_handle.0).await;
+    }
+
+    pub async fn stop_and_resume_alice_from_db(&mut self) {
+        self.stop_alice().await;
+
+        let (mut event_loop, swap_handle) = self.alice_params.event_loop();
+        event_loop.resume_unfinished_swaps().await.unwrap();
+
+        let join_handle = tokio::spawn(async move { event_loop.run().await });
+
+        self.alice_swap_handle = swap_handle;
+        self.alice_event_loop_join_handle = AliceEventLoopJoinHandle(join_handle);
+    }
+
+    /// Stops Alice's event loop and loads her swap from the database the way
+    /// `nectar resume` does. The swap is not run, it is driven by the caller
+    /// through a fresh event loop.
+    pub async fn stop_and_resume_alice_swap_from_db(&mut self) -> alice::Swap {
+        self.stop_alice().await;
+
+        let swap_id = self.bob_params.swap_id;
+        let (mut event_loop, swap_handle) = self.alice_params.event_loop();
+        let swap = alice::Builder::new(
+            event_loop.peer_id(),
+            self.alice_params.execution_params,
+            swap_id,
+            self.alice_params.bitcoin_wallet.clone(),
+            self.alice_params.monero_wallet.clone(),
+            self.alice_params.db.clone(),
+            self.alice_params.listen_address.clone(),
+            event_loop.new_handle(swap_id),
+        )
+        .build()
+        .await
+        .unwrap();
+
+        let join_handle = tokio::spawn(async move { event_loop.run().await });
+
+        self.alice_swap_handle = swap_handle;
+        self.alice_event_loop_join_handle = AliceEventLoopJoinHandle(join_handle);
+
+        swap
+    }
+
+    /// Loads the latest state of Alice's swap from her database.
+    pub fn alice_state_from_db(&self) -> AliceState {
+        match self
+            .alice_params
+            .db
+            .get_state(self.bob_params.swap_id)
+            .unwrap()
+        {
+            database::Swap::Alice(state) => state.into(),
+            database::Swap::Bob(_) => panic!("Alice's database contains a swap of Bob"),
+        }
+    }
+
+    /// Waits until Alice's swap reached a state that satisfies
+    /// `is_target_state` in her database.
+    pub async fn wait_for_alice_state(&self, is_target_state: fn(&AliceState) -> bool) {
+        loop {
+            if let Ok(database::Swap::Alice(state)) =
+                self.alice_params.db.get_state(self.bob_params.swap_id)
+            {
+                if is_target_state(&state.into()) {
+                    break;
+                }
+            }
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    pub fn alice_bitcoin_wallet(&self) -> Arc<bitcoin::Wallet> {
+        self.alice_bitcoin_wallet.clone()
+    }
+
+    /// Pays `amount` to `address` from the wallet of the node at `fee_rate`,
+    /// with the payment as the first output. The node does not mine the
+    /// transaction until a child pays for it. Returns the transaction and its
+    /// fee.
+    pub async fn send_stuck_transaction(
+        &self,
+        address: &bitcoin::Address,
+        amount: bitcoin::Amount,
+        fee_rate: bitcoin::FeeRate,
+    ) -> (bitcoin::Transaction, bitcoin::Amount) {
+        let wallet_url = self
+            .bitcoind_url
+            .join(&format!("wallet/{}", TEST_WALLET_NAME))
+            .unwrap();
+
+        let mut outputs = serde_json::Map::new();
+        outputs.insert(address.to_string(), json!(amount.as_btc()));
+        let unfunded = bitcoind_rpc(&wallet_url, "createrawtransaction", json!([[], outputs]))
+            .await
+            .unwrap();
+
+        // The node takes fee rates in BTC per 1000 vbytes
+        let options = json!({
+            "changePosition": 1,
+            "feeRate": fee_rate.as_sat_per_vb() as f64 / 100_000.0,
+        });
+        let funded = bitcoind_rpc(
+            &wallet_url,
+            "fundrawtransaction",
+            json!([unfunded, options]),
+        )
+        .await
+        .unwrap();
+        let fee = bitcoin::Amount::from_btc(funded["fee"].as_f64().unwrap()).unwrap();
+
+        let signed = bitcoind_rpc(
+            &wallet_url,
+            "signrawtransactionwithwallet",
+            json!([funded["hex"]]),
+        )
+        .await
+        .unwrap();
+        let txid = bitcoind_rpc(
+            &self.bitcoind_url,
+            "sendrawtransaction",
+            json!([signed["hex"]]),
+        )
+        .await
+        .unwrap();
+
+        // Miners see the transaction as paying no fee at all
+        bitcoind_rpc(
+            &self.bitcoind_url,
+            "prioritisetransaction",
+            json!([txid, 0, -(fee.as_sat() as i64)]),
+        )
+        .await
+        .unwrap();
+
+        let bytes = Vec::<u8>::from_hex(signed["hex"].as_str().unwrap()).unwrap();
+        let transaction = ::bitcoin::consensus::deserialize(&bytes).unwrap();
+
+        (transaction, fee)
+    }
+
+    /// Waits for the next transaction to enter the mempool of the node.
+    pub async fn wait_for_mempool_transaction(&self) -> bitcoin::Txid {
+        loop {
+            let mempool = bitcoind_rpc(&self.bitcoind_url, "getrawmempool", json!([]))
+                .await
+                .unwrap();
+            if let Some(txid) = mempool.as_array().and_then(|txids| txids.first()) {
+                break txid.as_str().unwrap().parse().unwrap();
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Waits for the transaction to be mined and returns the hash of its
+    /// block.
+    pub async fn wait_for_transaction_block(&self, txid: bitcoin::Txid) -> Value {
+        loop {
+            let transaction =
+                bitcoind_rpc(&self.bitcoind_url, "getrawtransaction", json!([txid, true]))
+                    .await
+                    .unwrap();
+            if let Some(block_hash) = transaction.get("blockhash") {
+                break block_hash.clone();
+            }
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// Waits for the transaction to be mined and invalidates its block. The
+    /// transaction goes back to the mempool and is mined again in a later
+    /// block of the new chain. Returns the hash of the invalidated block.
+    pub async fn reorg_out_transaction(&self, txid: bitcoin::Txid) -> Value {
+        let block_hash = self.wait_for_transaction_block(txid).await;
+
+        bitcoind_rpc(
+            &self.bitcoind_url,
+            "invalidateblock",
+            json!([block_hash.clone()]),
+        )
+        .await
+        .unwrap();
+
+        block_hash
+    }
+
     pub async fn assert_alice_redeemed(&mut self) {
         let swap_handle = self.alice_swap_handle.recv().await.unwrap();
         let state = swap_handle.await.unwrap();
 
+        self.assert_alice_manually_redeemed(state).await;
+    }
+
+    pub async fn assert_alice_manually_redeemed(&self, state: AliceState) {
         assert!(matches!(state, AliceState::BtcRedeemed));
 
         self.alice_bitcoin_wallet
@@ -135,11 +415,13 @@ impl TestContext {
             .await
             .expect("Could not sync wallet");
 
+        // All addresses of the wallet have the same script type as the redeem address
+        let redeem_address = self.alice_bitcoin_wallet.new_address().await.unwrap();
         let btc_balance_after_swap = self.alice_bitcoin_wallet.as_ref().balance().await.unwrap();
         assert_eq!(
             btc_balance_after_swap,
             self.alice_starting_balances.btc + self.btc_amount
-                - bitcoin::Amount::from_sat(bitcoin::TX_FEE)
+                - bitcoin::TxRedeem::fee(FEE_RATE, &redeem_address)
         );
 
         let xmr_balance_after_swap = self
@@ -165,20 +447,25 @@ impl TestContext {
         let btc_balance_after_swap = self.alice_bitcoin_wallet.as_ref().balance().await.unwrap();
         assert_eq!(btc_balance_after_swap, self.alice_starting_balances.btc);
 
-        // Ensure that Alice's balance is refreshed as we use a newly created wallet
-        self.alice_monero_wallet
-            .as_ref()
-            .inner
-            .refresh()
-            .await
-            .unwrap();
+        // The refund is swept back into Alice's main wallet, which is open again
+        self.alice_monero_wallet.refresh().await.unwrap();
         let xmr_balance_after_swap = self
             .alice_monero_wallet
             .as_ref()
             .get_balance()
             .await
             .unwrap();
-        assert_eq!(xmr_balance_after_swap, self.xmr_amount);
+        // Only the fees of the lock and the sweep are lost
+        let fees_paid = self
+            .alice_monero_wallet
+            .get_fees_paid(self.bob_params.swap_id)
+            .await
+            .unwrap();
+        assert!(fees_paid > monero::Amount::ZERO);
+        assert_eq!(
+            xmr_balance_after_swap,
+            self.alice_starting_balances.xmr - fees_paid
+        );
     }
 
     pub async fn assert_alice_punished(&self, state: AliceState) {
@@ -189,11 +476,14 @@ impl TestContext {
             .await
             .expect("Could not sync wallet");
 
+        // All addresses of the wallet have the same script type as the punish address
+        let punish_address = self.alice_bitcoin_wallet.new_address().await.unwrap();
         let btc_balance_after_swap = self.alice_bitcoin_wallet.as_ref().balance().await.unwrap();
         assert_eq!(
             btc_balance_after_swap,
             self.alice_starting_balances.btc + self.btc_amount
-                - bitcoin::Amount::from_sat(2 * bitcoin::TX_FEE)
+                - bitcoin::TxCancel::fee(FEE_RATE)
+                - bitcoin::TxPunish::fee(FEE_RATE, &punish_address)
         );
 
         let xmr_balance_after_swap = self
@@ -211,7 +501,7 @@ impl TestContext {
             .await
             .expect("Could not sync wallet");
 
-        let lock_tx_id = if let BobState::XmrRedeemed { tx_lock_id } = state {
+        let lock_tx_id = if let BobState::XmrRedeemed { tx_lock_id, .. } = state {
             tx_lock_id
         } else {
             panic!("Bob in not in xmr redeemed state: {:?}", state);
@@ -229,18 +519,86 @@ impl TestContext {
             self.bob_starting_balances.btc - self.btc_amount - lock_tx_bitcoin_fee
         );
 
-        // Ensure that Bob's balance is refreshed as we use a newly created wallet
-        self.bob_monero_wallet
-            .as_ref()
-            .inner
-            .refresh()
+        // The redeemed Monero is in the wallet of the swap, Bob's main wallet is open
+        // again and untouched
+        let xmr_balance_after_swap = self
+            .bob_monero_wallet
+            .get_swap_wallet_balance(self.bob_params.swap_id)
             .await
             .unwrap();
-        let xmr_balance_after_swap = self.bob_monero_wallet.as_ref().get_balance().await.unwrap();
+        assert_eq!(xmr_balance_after_swap, self.xmr_amount);
+
+        self.bob_monero_wallet.refresh().await.unwrap();
+        let main_xmr_balance = self.bob_monero_wallet.get_balance().await.unwrap();
+        assert_eq!(main_xmr_balance, self.bob_starting_balances.xmr);
+    }
+
+    /// Asserts that all swaps Bob ran at the same time were redeemed, given
+    /// the id and final state of each of them.
+    pub async fn assert_concurrent_swaps_redeemed(&mut self, bob_swaps: Vec<(Uuid, BobState)>) {
+        let swaps = bob_swaps.len() as u64;
+
+        for _ in 0..swaps {
+            let swap_handle = self.alice_swap_handle.recv().await.unwrap();
+            let state = swap_handle.await.unwrap();
+            assert!(matches!(state, AliceState::BtcRedeemed));
+        }
+
+        self.alice_bitcoin_wallet
+            .sync_wallet()
+            .await
+            .expect("Could not sync wallet");
+
+        let redeem_address = self.alice_bitcoin_wallet.new_address().await.unwrap();
+        let btc_balance_after_swaps = self.alice_bitcoin_wallet.as_ref().balance().await.unwrap();
         assert_eq!(
-            xmr_balance_after_swap,
-            self.bob_starting_balances.xmr + self.xmr_amount
+            btc_balance_after_swaps,
+            self.alice_starting_balances.btc
+                + (self.btc_amount - bitcoin::TxRedeem::fee(FEE_RATE, &redeem_address)) * swaps
+        );
+
+        let xmr_balance_after_swaps = self.alice_monero_wallet.get_balance().await.unwrap();
+        assert!(
+            xmr_balance_after_swaps <= self.alice_starting_balances.xmr - self.xmr_amount * swaps
+        );
+
+        self.bob_bitcoin_wallet
+            .sync_wallet()
+            .await
+            .expect("Could not sync wallet");
+
+        let mut lock_tx_bitcoin_fees = bitcoin::Amount::ZERO;
+        for (swap_id, state) in bob_swaps {
+            let lock_tx_id = if let BobState::XmrRedeemed { tx_lock_id, .. } = state {
+                tx_lock_id
+            } else {
+                panic!("Bob in not in xmr redeemed state: {:?}", state);
+            };
+
+            lock_tx_bitcoin_fees += self
+                .bob_bitcoin_wallet
+                .transaction_fee(lock_tx_id)
+                .await
+                .unwrap();
+
+            // Each swap redeemed into its own wallet
+            let xmr_balance_after_swap = self
+                .bob_monero_wallet
+                .get_swap_wallet_balance(swap_id)
+                .await
+                .unwrap();
+            assert_eq!(xmr_balance_after_swap, self.xmr_amount);
+        }
+
+        let btc_balance_after_swaps = self.bob_bitcoin_wallet.as_ref().balance().await.unwrap();
+        assert_eq!(
+            btc_balance_after_swaps,
+            self.bob_starting_balances.btc - self.btc_amount * swaps - lock_tx_bitcoin_fees
         );
+
+        self.bob_monero_wallet.refresh().await.unwrap();
+        let main_xmr_balance = self.bob_monero_wallet.get_balance().await.unwrap();
+        assert_eq!(main_xmr_balance, self.bob_starting_balances.xmr);
     }
 
     pub async fn assert_bob_refunded(&self, state: BobState) {
@@ -260,17 +618,20 @@ impl TestContext {
             .await
             .unwrap();
 
+        // All addresses of the wallet have the same script type as the refund address
+        let refund_address = self.bob_bitcoin_wallet.new_address().await.unwrap();
         let btc_balance_after_swap = self.bob_bitcoin_wallet.as_ref().balance().await.unwrap();
 
         let alice_submitted_cancel = btc_balance_after_swap
             == self.bob_starting_balances.btc
                 - lock_tx_bitcoin_fee
-                - bitcoin::Amount::from_sat(bitcoin::TX_FEE);
+                - bitcoin::TxRefund::fee(FEE_RATE, &refund_address);
 
         let bob_submitted_cancel = btc_balance_after_swap
             == self.bob_starting_balances.btc
                 - lock_tx_bitcoin_fee
-                - bitcoin::Amount::from_sat(2 * bitcoin::TX_FEE);
+                - bitcoin::TxCancel::fee(FEE_RATE)
+                - bitcoin::TxRefund::fee(FEE_RATE, &refund_address);
 
         // The cancel tx can be submitted by both Alice and Bob.
         // Since we cannot be sure who submitted it we have to assert accordingly
@@ -309,7 +670,23 @@ impl TestContext {
     }
 }
 
-pub async fn setup_test<T, F, C>(_config: C, testfn: T)
+/// The backend of the Bitcoin wallets of Alice and Bob.
+#[derive(Debug, Clone, Copy)]
+pub enum BitcoinBackend {
+    Electrum,
+    Bitcoind,
+}
+
+pub async fn setup_test<T, F, C>(config: C, testfn: T)
+where
+    T: Fn(TestContext) -> F,
+    F: Future<Output = ()>,
+    C: GetExecutionParams,
+{
+    setup_test_with_backend(config, BitcoinBackend::Electrum, testfn).await
+}
+
+pub async fn setup_test_with_backend<T, F, C>(_config: C, backend: BitcoinBackend, testfn: T)
 where
     T: Fn(TestContext) -> F,
     F: Future<Output = ()>,
@@ -323,8 +700,11 @@ where
 
     let (monero, containers) = testutils::init_containers(&cli).await;
 
+    // 100 XMR per BTC
+    let rate = Rate::new(bitcoin::Amount::from_sat(1_000_000));
+
     let btc_amount = bitcoin::Amount::from_sat(1_000_000);
-    let xmr_amount = monero::Amount::from_monero(btc_amount.as_btc() * RATE as f64).unwrap();
+    let xmr_amount = rate.sell_quote(btc_amount).unwrap();
 
     let alice_starting_balances = StartingBalances {
         xmr: xmr_amount * 10,
@@ -341,10 +721,6 @@ where
         .electrs
         .get_host_port(testutils::electrs::RPC_PORT)
         .expect("Could not map electrs rpc port");
-    let electrs_http_port = containers
-        .electrs
-        .get_host_port(testutils::electrs::HTTP_PORT)
-        .expect("Could not map electrs http port");
 
     let alice_seed = Seed::random().unwrap();
     let bob_seed = Seed::random().unwrap();
@@ -356,8 +732,8 @@ where
         alice_starting_balances.clone(),
         tempdir().unwrap().path(),
         electrs_rpc_port,
-        electrs_http_port,
         alice_seed,
+        backend,
     )
     .await;
 
@@ -373,31 +749,33 @@ where
 
     let (bob_bitcoin_wallet, bob_monero_wallet) = init_test_wallets(
         "bob",
-        containers.bitcoind_url,
+        containers.bitcoind_url.clone(),
         &monero,
         bob_starting_balances.clone(),
         tempdir().unwrap().path(),
         electrs_rpc_port,
-        electrs_http_port,
         bob_seed,
+        backend,
     )
     .await;
 
-    let (mut alice_event_loop, alice_swap_handle) = alice::EventLoop::new(
-        alice_listen_address.clone(),
-        alice_seed,
+    let alice_params = AliceParams {
+        seed: alice_seed,
+        db: alice_db,
+        bitcoin_wallet: alice_bitcoin_wallet.clone(),
+        monero_wallet: alice_monero_wallet.clone(),
+        listen_address: alice_listen_address.clone(),
+        rate,
         execution_params,
-        alice_bitcoin_wallet.clone(),
-        alice_monero_wallet.clone(),
-        alice_db,
-    )
-    .unwrap();
+    };
+
+    let (mut alice_event_loop, alice_swap_handle) = alice_params.event_loop();
 
     let alice_peer_id = alice_event_loop.peer_id();
 
-    tokio::spawn(async move {
+    let alice_event_loop_join_handle = AliceEventLoopJoinHandle(tokio::spawn(async move {
         alice_event_loop.run().await;
-    });
+    }));
 
     let bob_params = BobParams {
         seed: Seed::random().unwrap(),
@@ -417,10 +795,13 @@ where
         alice_bitcoin_wallet,
         alice_monero_wallet,
         alice_swap_handle,
+        alice_params,
+        alice_event_loop_join_handle,
         bob_params,
         bob_starting_balances,
         bob_bitcoin_wallet,
         bob_monero_wallet,
+        bitcoind_url: containers.bitcoind_url,
     };
 
     testfn(test).await;
@@ -513,6 +894,35 @@ pub async fn init_electrs_container(
     Ok(docker)
 }
 
+/// Calls an RPC of the node that the harness client does not provide.
+async fn bitcoind_rpc(node_url: &Url, method: &str, params: Value) -> Result<Value> {
+    let mut url = node_url.clone();
+    let username = url.username().to_owned();
+    let password = url.password().map(ToOwned::to_owned);
+    url.set_username("")
+        .and_then(|_| url.set_password(None))
+        .map_err(|_| anyhow!("Invalid bitcoind url"))?;
+
+    let mut response: Value = reqwest::Client::new()
+        .post(url)
+        .basic_auth(username, password)
+        .json(&json!({
+            "jsonrpc": "1.0",
+            "id": "test",
+            "method": method,
+            "params": params,
+        }))
+        .send()
+        .await?
+        .json()
+        .await?;
+
+    match response.get("error") {
+        Some(error) if !error.is_null() => bail!("{} failed: {}", method, error),
+        _ => Ok(response["result"].take()),
+    }
+}
+
 async fn mine(bitcoind_client: Client, reward_address: bitcoin::Address) -> Result<()> {
     loop {
         tokio::time::sleep(Duration::from_secs(1)).await;
@@ -583,38 +993,61 @@ async fn init_test_wallets(
     starting_balances: StartingBalances,
     datadir: &Path,
     electrum_rpc_port: u16,
-    electrum_http_port: u16,
     seed: Seed,
+    backend: BitcoinBackend,
 ) -> (Arc<bitcoin::Wallet>, Arc<monero::Wallet>) {
     monero
         .init(vec![(name, starting_balances.xmr.as_piconero())])
         .await
         .unwrap();
 
-    let xmr_wallet = swap::monero::Wallet {
-        inner: monero.wallet(name).unwrap().client(),
-        network: monero::Network::default(),
-    };
+    let monero_wallet_rpc = monero.wallet(name).unwrap();
+    let xmr_wallet = swap::monero::Wallet::new(
+        monero_wallet_rpc.client().url,
+        monero::Network::default(),
+        monero_wallet_rpc.name(),
+    )
+    .with_daemon(monero.monerod().client().url);
 
-    let electrum_rpc_url = {
-        let input = format!("tcp://@localhost:{}", electrum_rpc_port);
-        Url::parse(&input).unwrap()
-    };
-    let electrum_http_url = {
-        let input = format!("http://@localhost:{}", electrum_http_port);
-        Url::parse(&input).unwrap()
+    let fee_estimation = bitcoin::FeeEstimation {
+        target_block: 1,
+        min_sat_per_vb: FEE_RATE.as_sat_per_vb() as f32,
+        max_sat_per_vb: FEE_RATE.as_sat_per_vb() as f32,
+        fallback_sat_per_vb: FEE_RATE.as_sat_per_vb() as f32,
     };
 
-    let btc_wallet = swap::bitcoin::Wallet::new(
-        electrum_rpc_url,
-        electrum_http_url,
-        bitcoin::Network::Regtest,
-        datadir,
-        seed.extended_private_key(bitcoin::Network::Regtest)
-            .expect("Could not create extended private key from seed")
-            .private_key,
-    )
-    .await
+    let btc_wallet = match backend {
+        BitcoinBackend::Electrum => {
+            let electrum_rpc_url = {
+                let input = format!("tcp://@localhost:{}", electrum_rpc_port);
+                Url::parse(&input).unwrap()
+            };
+
+            swap::bitcoin::Wallet::new(
+                electrum_rpc_url,
+                bitcoin::Network::Regtest,
+                datadir,
+                seed.extended_private_key(bitcoin::Network::Regtest)
+                    .expect("Could not create extended private key from seed"),
+                fee_estimation,
+            )
+            .await
+        }
+        BitcoinBackend::Bitcoind => {
+            Client::new(bitcoind_url.clone())
+                .createwallet(name, None, None, None, None)
+                .await
+                .expect("could not create bitcoind wallet");
+
+            swap::bitcoin::Wallet::new_bitcoind(
+                bitcoind_url.clone(),
+                name,
+                bitcoin::Network::Regtest,
+                fee_estimation,
+            )
+            .await
+        }
+    }
     .expect("could not init btc wallet");
 
     if starting_balances.btc != bitcoin::Amount::ZERO {